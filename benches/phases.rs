@@ -0,0 +1,114 @@
+//! Benchmarks of the phases of indexing on generated hierarchies.
+//!
+//! The number of nodes is 100000 by default, or set with the `NSI_BENCH_NODES` environment
+//! variable, such as `NSI_BENCH_NODES=1000000 cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use nested_set_indexer::format::{self, Format, ReadOptions, WriteOptions};
+use nested_set_indexer::generate::{self, Shape};
+use nested_set_indexer::{Graph, Node};
+use std::env;
+
+const SEED: u64 = 1;
+
+/// Returns the shapes of the benchmarked hierarchies with their names.
+fn shapes(nodes: usize) -> Vec<(&'static str, Shape)> {
+    vec![
+        // a root with many children, and their children as leaves
+        (
+            "wide",
+            Shape {
+                nodes,
+                max_depth: Some(2),
+                max_children: None,
+                extra_parent: 0.0,
+            },
+        ),
+        // a single chain of nodes
+        (
+            "deep",
+            Shape {
+                nodes,
+                max_depth: None,
+                max_children: Some(1),
+                extra_parent: 0.0,
+            },
+        ),
+        // about a third of the nodes with a second parent
+        (
+            "dag",
+            Shape {
+                nodes,
+                max_depth: Some(8),
+                max_children: None,
+                extra_parent: 0.3,
+            },
+        ),
+    ]
+}
+
+fn node_count() -> usize {
+    env::var("NSI_BENCH_NODES")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(100_000)
+}
+
+/// Returns the nodes of a tree, converting a directed acyclic graph to it.
+fn tree(nodes: &[Node]) -> Vec<Node> {
+    let graph = Graph::new(nodes.to_vec()).unwrap();
+    if graph.is_dag() {
+        graph.dag_to_tree().unwrap().nodes
+    } else {
+        graph.nodes
+    }
+}
+
+fn bench_phases(c: &mut Criterion) {
+    let count = node_count();
+
+    for (name, shape) in shapes(count) {
+        let nodes = generate::generate(&shape, SEED).unwrap();
+        let tree = tree(&nodes);
+
+        let mut csv = Vec::new();
+        format::write_nodes(&Format::Csv, &mut csv, &nodes, &WriteOptions::default()).unwrap();
+
+        let mut group = c.benchmark_group(name);
+        group.sample_size(10);
+        group.throughput(Throughput::Elements(nodes.len() as u64));
+
+        group.bench_function(BenchmarkId::new("parse", count), |b| {
+            b.iter(|| format::read_nodes(&Format::Csv, &csv[..], &ReadOptions::default()).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("dag_to_tree", count), |b| {
+            b.iter_batched(
+                || Graph::new(nodes.clone()).unwrap(),
+                |graph| graph.dag_to_tree().unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function(BenchmarkId::new("complement_leaf", count), |b| {
+            b.iter_batched(
+                || Graph::new(tree.clone()).unwrap(),
+                |graph| graph.complement_leaf().unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function(BenchmarkId::new("build_index", count), |b| {
+            b.iter_batched(
+                || Graph::new(tree.clone()).unwrap(),
+                |mut graph| {
+                    graph.build_index().unwrap();
+                    graph
+                },
+                BatchSize::LargeInput,
+            )
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_phases);
+criterion_main!(benches);
@@ -0,0 +1,32 @@
+//! Indexes arbitrary nodes, with cycles, missing parents and several parents.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use nested_set_indexer::Node;
+use nested_set_indexer_fuzz::index;
+
+/// A node whose id and parent are taken from few values, so that they often collide.
+#[derive(Debug, Arbitrary)]
+struct Input {
+    id: u8,
+    parent: Option<u8>,
+    label: String,
+    leaf: Option<bool>,
+}
+
+fuzz_target!(|input: (bool, Vec<Input>)| {
+    let (complement_leaf, inputs) = input;
+    let nodes = inputs.into_iter().map(|x| {
+        let mut node = Node::new(x.id.to_string(), x.label);
+        if let Some(parent) = x.parent {
+            node = node.with_parent(parent.to_string());
+        }
+        if let Some(leaf) = x.leaf {
+            node = node.with_leaf(leaf);
+        }
+        node
+    });
+    index(nodes.collect(), complement_leaf);
+});
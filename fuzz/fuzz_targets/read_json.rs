@@ -0,0 +1,15 @@
+//! Reads JSON, JSON Lines and nested JSON nodes from arbitrary bytes, and indexes them.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nested_set_indexer::format::{read_nodes, Format, ReadOptions};
+use nested_set_indexer_fuzz::index;
+
+fuzz_target!(|data: &[u8]| {
+    for format in &[Format::Json, Format::Jsonl, Format::JsonTree] {
+        if let Ok(parsed) = read_nodes(format, data, &ReadOptions::default()) {
+            index(parsed.nodes, false);
+        }
+    }
+});
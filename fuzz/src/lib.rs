@@ -0,0 +1,89 @@
+//! Indexing shared by the fuzz targets.
+
+use nested_set_indexer::resolve::{self, DuplicatePolicy, LeafParentPolicy};
+use nested_set_indexer::resolve::{MissingParentPolicy, OrphanPolicy};
+use nested_set_indexer::{check, Error, Graph, Node, Result};
+use std::collections::HashMap;
+
+/// Maximum number of nodes of the tree converted from a directed acyclic graph, as every node is
+/// copied once for each of its paths from the root.
+const MAX_TREE_NODES: u64 = 100_000;
+
+/// Resolves the problems of `nodes` or fails on them, and builds their graph.
+fn build_graph(nodes: Vec<Node>) -> Result<Graph> {
+    let (nodes, _) = resolve::self_parents(nodes, None)?;
+    let (nodes, _) = resolve::duplicates(nodes, &DuplicatePolicy::default())?;
+    let (nodes, _) = resolve::missing_parents(nodes, &MissingParentPolicy::default())?;
+    let orphans = Node::new("orphans", "orphans");
+    let (mut nodes, _) = resolve::orphans(nodes, &OrphanPolicy::default(), orphans)?;
+    resolve::leaf_parents(&mut nodes, &LeafParentPolicy::default())?;
+
+    Graph::new(nodes)
+}
+
+/// Returns the number of nodes of the tree converted from the graph of `nodes`, saturated at
+/// `u64::MAX`, or `None` if there is a cycle.
+fn tree_size(nodes: &[Node]) -> Option<u64> {
+    let mut children = HashMap::<&str, Vec<&str>>::new();
+    let mut parents = HashMap::<&str, usize>::new();
+    for node in nodes {
+        let count = parents.entry(node.id()).or_insert(0);
+        if let Some(parent) = node.parent() {
+            *count += 1;
+            children.entry(parent).or_default().push(node.id());
+        }
+    }
+
+    // the paths from the root to every node, counted in a topological order
+    let mut paths = HashMap::<&str, u64>::new();
+    let mut pending: Vec<_> = parents.iter().filter(|x| *x.1 == 0).map(|x| *x.0).collect();
+    for id in &pending {
+        paths.insert(id, 1);
+    }
+    let mut done = 0;
+    while let Some(id) = pending.pop() {
+        done += 1;
+        let count = paths[id];
+        for child in children.get(id).into_iter().flatten() {
+            let x = paths.entry(child).or_insert(0);
+            *x = x.saturating_add(count);
+            let parents = parents.get_mut(child).unwrap();
+            *parents -= 1;
+            if *parents == 0 {
+                pending.push(child);
+            }
+        }
+    }
+
+    let total = paths.values().fold(0_u64, |x, y| x.saturating_add(*y));
+    (done == parents.len()).then_some(total)
+}
+
+/// Indexes `nodes` as the indexer does with its default options, and checks the indices.
+///
+/// Inputs rejected with an error are fine, but a panic or invalid indices are not.
+pub fn index(nodes: Vec<Node>, complement_leaf: bool) {
+    let mut graph = match build_graph(nodes) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    if complement_leaf {
+        graph = graph.complement_leaf().unwrap();
+    }
+    let size = tree_size(&graph.nodes);
+    if size.is_none() {
+        // a cycle below the root fails both the conversion to a tree and the indexing
+        assert!(matches!(graph.dag_to_tree(), Err(Error::CycleError { .. })));
+        assert!(matches!(graph.build_index(), Err(Error::CycleError { .. })));
+        return;
+    }
+    if graph.is_dag() {
+        if size.is_some_and(|x| x > MAX_TREE_NODES) {
+            return;
+        }
+        graph = graph.dag_to_tree().unwrap();
+    }
+    graph.build_index().unwrap();
+
+    assert!(check::verify(&graph.nodes, false).is_empty());
+}
@@ -0,0 +1,511 @@
+//! Aggregation of the values of the nodes up the tree before indexing.
+
+use crate::data::Node;
+use crate::error;
+use csv::{ReaderBuilder, Trim};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::BufReader;
+use strum::{EnumString, EnumVariantNames, IntoStaticStr};
+
+/// Output column of the count of a node itself.
+pub const SELF_COUNT: &str = "self_count";
+/// Output column of the count of a node and all its descendants.
+pub const CUMULATIVE_COUNT: &str = "cumulative_count";
+
+/// Reads headerless `id` and `count` rows separated by `delimiter`, summing the counts of the same
+/// id. A first row whose count is not a number is skipped as a header, and lines starting with
+/// `#` are ignored.
+pub fn read_counts<R: io::Read>(input: R, delimiter: u8) -> error::Result<HashMap<String, u64>> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .trim(Trim::All)
+        .comment(Some(b'#'))
+        .from_reader(BufReader::new(input));
+
+    let mut counts = HashMap::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = record.position().map_or(0, |x| x.line());
+        match (record.get(0), record.get(1).map(|x| x.parse::<u64>())) {
+            (Some(id), Some(Ok(count))) => *counts.entry(id.to_owned()).or_default() += count,
+            (_, Some(Err(_))) if i == 0 => continue,
+            _ => Err(error::Error::InvalidRecordError {
+                line,
+                message: "expected id and count".to_owned(),
+            })?,
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Returns the rows of a tree with every node after all its descendants.
+pub(crate) fn post_order(nodes: &[Node]) -> Vec<usize> {
+    let mut children = HashMap::<&str, Vec<usize>>::new();
+    let mut roots = Vec::new();
+    for (i, node) in nodes.iter().enumerate() {
+        match node.parent() {
+            Some(parent) => children.entry(parent).or_default().push(i),
+            None => roots.push(i),
+        }
+    }
+
+    // a pre-order reversed, with an explicit stack for deep trees
+    let mut order = Vec::with_capacity(nodes.len());
+    let mut expanded = HashSet::new();
+    let mut stack = roots;
+    while let Some(i) = stack.pop() {
+        order.push(i);
+        if expanded.insert(nodes[i].id()) {
+            stack.extend(children.get(nodes[i].id()).into_iter().flatten());
+        }
+    }
+    order.reverse();
+    order
+}
+
+/// Sets `self_count` of every node to the count of its `source_id`, or 0 if not counted, and
+/// `cumulative_count` to the sum of the counts of the node and all its descendants. Returns the
+/// counted ids which are not in the tree.
+pub fn roll_up_counts(nodes: &mut [Node], counts: &HashMap<String, u64>) -> Vec<String> {
+    let mut totals = vec![0; nodes.len()];
+    let mut rows = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        totals[i] = counts.get(node.source_id()).copied().unwrap_or(0);
+        if !node.is_leaf() {
+            rows.insert(node.id().to_owned(), i);
+        }
+    }
+
+    let ids: HashSet<&str> = nodes.iter().map(|x| x.source_id()).collect();
+    let mut missing: Vec<String> = counts
+        .keys()
+        .filter(|x| !ids.contains(x.as_str()))
+        .cloned()
+        .collect();
+    missing.sort();
+
+    for i in post_order(nodes) {
+        let own = counts.get(nodes[i].source_id()).copied().unwrap_or(0);
+        let extra = nodes[i].extra_mut();
+        extra.insert(SELF_COUNT.to_owned(), Value::from(own));
+        extra.insert(CUMULATIVE_COUNT.to_owned(), Value::from(totals[i]));
+
+        if let Some(parent) = nodes[i].parent().and_then(|x| rows.get(x)) {
+            totals[*parent] += totals[i];
+        }
+    }
+
+    missing
+}
+
+/// Removes the nodes whose `cumulative_count` set by [`roll_up_counts`] is zero, which are whole
+/// subtrees, keeping the root. Returns the kept nodes and the number of the removed ones.
+pub fn prune_zero(nodes: Vec<Node>) -> (Vec<Node>, usize) {
+    let count = nodes.len();
+    let nodes: Vec<Node> = nodes
+        .into_iter()
+        .filter(|x| {
+            x.parent().is_none()
+                || x.extra()
+                    .get(CUMULATIVE_COUNT)
+                    .and_then(|x| x.as_u64())
+                    .is_some_and(|x| x > 0)
+        })
+        .collect();
+    let pruned = count - nodes.len();
+
+    (nodes, pruned)
+}
+
+/// Returns the `cumulative_count` of a node set by [`roll_up_counts`], or 0.
+fn cumulative_count(node: &Node) -> u64 {
+    node.extra()
+        .get(CUMULATIVE_COUNT)
+        .and_then(|x| x.as_u64())
+        .unwrap_or(0)
+}
+
+/// Keeps the `k` children with the largest `cumulative_count` of every node, merging the subtrees
+/// of the others into a leaf labeled `label` after them, whose id is that of the parent with an
+/// `_other` suffix. Returns the kept nodes and the number of the merged ones.
+pub fn top_children(nodes: Vec<Node>, k: usize, label: &str) -> (Vec<Node>, usize) {
+    let mut rows = HashMap::new();
+    let mut children = HashMap::<&str, Vec<usize>>::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if !node.is_leaf() {
+            rows.insert(node.id(), i);
+        }
+        if let Some(parent) = node.parent() {
+            children.entry(parent).or_default().push(i);
+        }
+    }
+    let mut ids: HashSet<String> = nodes.iter().map(|x| x.id().to_owned()).collect();
+
+    let mut merged = vec![false; nodes.len()];
+    let mut totals = Vec::new();
+    let mut parents: Vec<_> = children.into_iter().filter(|(_, x)| x.len() > k).collect();
+    parents.sort_by_key(|(parent, _)| rows.get(parent).copied());
+    for (parent, mut siblings) in parents {
+        siblings.sort_by_key(|i| Reverse(cumulative_count(&nodes[*i])));
+        let mut total = 0;
+        for i in siblings.into_iter().skip(k) {
+            merged[i] = true;
+            total += cumulative_count(&nodes[i]);
+        }
+        totals.push((parent, total));
+    }
+
+    // the reversed post-order has every node before its descendants
+    for i in post_order(&nodes).into_iter().rev() {
+        if let Some(parent) = nodes[i].parent().and_then(|x| rows.get(x)) {
+            merged[i] |= merged[*parent];
+        }
+    }
+
+    let mut others = Vec::new();
+    for (parent, total) in totals {
+        if rows.get(parent).is_some_and(|x| merged[*x]) {
+            continue;
+        }
+        let mut id = format!("{}_other", parent);
+        while ids.contains(&id) {
+            id.push('_');
+        }
+        ids.insert(id.to_owned());
+        others.push(
+            Node::new(id, label)
+                .with_parent(parent)
+                .with_leaf(true)
+                .with_extra(SELF_COUNT, total)
+                .with_extra(CUMULATIVE_COUNT, total),
+        );
+    }
+
+    let count = merged.iter().filter(|x| **x).count();
+    let nodes = nodes
+        .into_iter()
+        .zip(merged)
+        .filter(|(_, merged)| !merged)
+        .map(|(node, _)| node)
+        .chain(others)
+        .collect();
+
+    (nodes, count)
+}
+
+/// Removes the nodes deeper than `max_depth`, the root being at depth 0, returning the kept nodes
+/// and the number of the removed ones. With `rollup`, the counts rolled up before are kept as the
+/// `self_count` of the nodes at `max_depth` too, as if the removed descendants were collapsed
+/// into them.
+pub fn truncate(nodes: Vec<Node>, max_depth: usize, rollup: bool) -> (Vec<Node>, usize) {
+    let mut rows = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if !node.is_leaf() {
+            rows.insert(node.id().to_owned(), i);
+        }
+    }
+
+    // the reversed post-order has every node before its descendants
+    let mut depths = vec![0; nodes.len()];
+    for i in post_order(&nodes).into_iter().rev() {
+        if let Some(parent) = nodes[i].parent().and_then(|x| rows.get(x)) {
+            depths[i] = depths[*parent] + 1;
+        }
+    }
+
+    let count = nodes.len();
+    let nodes: Vec<Node> = nodes
+        .into_iter()
+        .zip(depths)
+        .filter(|(_, depth)| *depth <= max_depth)
+        .map(|(mut node, depth)| {
+            if rollup && depth == max_depth {
+                if let Some(total) = node.extra().get(CUMULATIVE_COUNT).cloned() {
+                    node.extra_mut().insert(SELF_COUNT.to_owned(), total);
+                }
+            }
+            node
+        })
+        .collect();
+    let pruned = count - nodes.len();
+
+    (nodes, pruned)
+}
+
+/// Function rolling up the values of a column from the descendants to a node.
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, EnumVariantNames, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum Function {
+    Sum,
+    Min,
+    Max,
+    Mean,
+}
+
+/// Aggregation of a numeric input column, emitted as `<column>_<function>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub function: Function,
+    pub column: String,
+}
+
+impl Aggregate {
+    /// Name of the output column such as `abundance_sum`.
+    pub fn output_column(&self) -> String {
+        let function: &str = self.function.into();
+        format!("{}_{}", self.column, function)
+    }
+}
+
+/// Summary of the values of a subtree.
+#[derive(Debug, Clone, Copy)]
+struct Stats {
+    sum: f64,
+    min: f64,
+    max: f64,
+    count: usize,
+}
+
+impl Stats {
+    fn of(value: f64) -> Self {
+        Stats {
+            sum: value,
+            min: value,
+            max: value,
+            count: 1,
+        }
+    }
+
+    fn merge(self, other: Stats) -> Self {
+        Stats {
+            sum: self.sum + other.sum,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            count: self.count + other.count,
+        }
+    }
+
+    fn get(&self, function: Function) -> f64 {
+        match function {
+            Function::Sum => self.sum,
+            Function::Min => self.min,
+            Function::Max => self.max,
+            Function::Mean => self.sum / self.count as f64,
+        }
+    }
+}
+
+/// Returns a number as an integer if it is one, so that sums of integers are not written as `3.0`.
+fn number(x: f64) -> Value {
+    if x.fract() == 0.0 && x.abs() < i64::MAX as f64 {
+        Value::from(x as i64)
+    } else {
+        Value::from(x)
+    }
+}
+
+/// Returns the value of an extra column as a number, parsing strings such as of CSV.
+fn value_of(node: &Node, column: &str) -> Option<f64> {
+    match node.extra().get(column)? {
+        Value::Number(x) => x.as_f64(),
+        Value::String(x) => x.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Sets `<column>_<function>` of every node to the function of the numeric values of `column` of
+/// the node and all its descendants, or `null` if none of them has a value.
+pub fn roll_up(nodes: &mut [Node], aggregates: &[Aggregate]) {
+    let mut rows = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if !node.is_leaf() {
+            rows.insert(node.id().to_owned(), i);
+        }
+    }
+    let order = post_order(nodes);
+
+    for aggregate in aggregates {
+        let mut stats: Vec<Option<Stats>> = nodes
+            .iter()
+            .map(|x| value_of(x, &aggregate.column).map(Stats::of))
+            .collect();
+        let column = aggregate.output_column();
+
+        for i in order.iter().copied() {
+            let value = stats[i].map(|x| number(x.get(aggregate.function)));
+            nodes[i]
+                .extra_mut()
+                .insert(column.to_owned(), value.unwrap_or(Value::Null));
+
+            if let (Some(parent), Some(x)) = (nodes[i].parent().and_then(|x| rows.get(x)), stats[i])
+            {
+                stats[*parent] = Some(match stats[*parent] {
+                    Some(y) => y.merge(x),
+                    None => x,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        prune_zero, read_counts, roll_up, roll_up_counts, top_children, truncate, Aggregate,
+        Function,
+    };
+    use crate::data::{Graph, Node};
+
+    #[test]
+    fn test_roll_up_counts() {
+        let counts = read_counts("taxon\tcount\n3\t5\n4\t2\n3\t1\n9\t7\n".as_bytes(), b'\t');
+        let counts = counts.unwrap();
+        assert_eq!(counts["3"], 6);
+        assert!(read_counts("3\tmany\n4\tmany\n".as_bytes(), b'\t').is_err());
+
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2").with_leaf(true),
+            Node::new("4", "c").with_parent("1"),
+            Node::new("3", "b").with_parent("4").with_leaf(true),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        let missing = roll_up_counts(&mut graph.nodes, &counts);
+        assert_eq!(missing, vec!["9"]);
+
+        let mut zero = graph.nodes.to_owned();
+        zero.push(Node::new("5", "d").with_parent("2"));
+        zero.push(Node::new("6", "e").with_parent("5"));
+        roll_up_counts(&mut zero, &counts);
+        let (kept, pruned) = prune_zero(zero);
+        assert_eq!(pruned, 2);
+        assert_eq!(kept.len(), graph.nodes.len());
+
+        let totals: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|x| {
+                (
+                    x.extra()["self_count"].as_u64(),
+                    x.extra()["cumulative_count"].as_u64(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            totals,
+            vec![
+                (Some(0), Some(14)),
+                (Some(0), Some(6)),
+                (Some(6), Some(6)),
+                (Some(2), Some(8)),
+                (Some(6), Some(6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "c").with_parent("3").with_leaf(true),
+            Node::new("5", "d").with_parent("1").with_leaf(true),
+        ];
+        let counts = [("3", 1), ("4", 2), ("5", 4)]
+            .iter()
+            .map(|(id, count)| (id.to_string(), *count))
+            .collect();
+        roll_up_counts(&mut nodes, &counts);
+
+        let (kept, pruned) = truncate(nodes.to_owned(), 1, false);
+        assert_eq!(pruned, 2);
+        let ids: Vec<_> = kept.iter().map(|x| x.id()).collect();
+        assert_eq!(ids, vec!["1", "2", "5"]);
+        assert_eq!(kept[1].extra()["self_count"], 0);
+
+        let (kept, _) = truncate(nodes, 1, true);
+        assert_eq!(kept[1].extra()["self_count"], 3);
+        assert_eq!(kept[0].extra()["self_count"], 0);
+    }
+
+    #[test]
+    fn test_top_children() {
+        let mut nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("1"),
+            Node::new("4", "c").with_parent("1"),
+            Node::new("5", "d").with_parent("4"),
+            Node::new("6", "e").with_parent("3"),
+            Node::new("7", "f").with_parent("4"),
+        ];
+        let counts = [("2", 1), ("3", 5), ("5", 2)]
+            .iter()
+            .map(|(id, count)| (id.to_string(), *count))
+            .collect();
+        roll_up_counts(&mut nodes, &counts);
+
+        let (kept, merged) = top_children(nodes.to_owned(), 1, "Other");
+        assert_eq!(merged, 4);
+        let ids: Vec<_> = kept.iter().map(|x| x.id()).collect();
+        assert_eq!(ids, vec!["1", "3", "6", "1_other"]);
+        let other = &kept[3];
+        assert_eq!(other.label(), "Other");
+        assert_eq!(other.parent(), Some("1"));
+        assert_eq!(other.extra()["cumulative_count"], 3);
+
+        let (kept, merged) = top_children(nodes, 3, "Other");
+        assert_eq!(merged, 0);
+        assert_eq!(kept.len(), 7);
+    }
+
+    #[test]
+    fn test_roll_up() {
+        let mut nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a")
+                .with_parent("1")
+                .with_extra("score", "1.5"),
+            Node::new("3", "b").with_parent("2").with_extra("score", 4),
+            Node::new("4", "c").with_parent("1").with_extra("score", 2),
+            Node::new("5", "d").with_parent("4"),
+        ];
+        let aggregates: Vec<_> = [Function::Sum, Function::Max, Function::Mean]
+            .iter()
+            .map(|function| Aggregate {
+                function: *function,
+                column: "score".to_owned(),
+            })
+            .collect();
+        roll_up(&mut nodes, &aggregates);
+
+        let values: Vec<_> = nodes
+            .iter()
+            .map(|x| {
+                let extra = x.extra();
+                (
+                    extra["score_sum"].as_f64(),
+                    extra["score_max"].as_f64(),
+                    extra["score_mean"].as_f64(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                (Some(7.5), Some(4.0), Some(2.5)),
+                (Some(5.5), Some(4.0), Some(2.75)),
+                (Some(4.0), Some(4.0), Some(4.0)),
+                (Some(2.0), Some(2.0), Some(2.0)),
+                (None, None, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,409 @@
+//! Structural checks of a hierarchy, reporting every problem instead of failing on the first one.
+
+use crate::data::Node;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Kind of an [`Issue`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// A record of the input could not be parsed.
+    InvalidRecord,
+    /// No node is without a parent.
+    NoRoot,
+    /// More than one node is without a parent.
+    MultipleRoots,
+    /// The parent of the node does not exist.
+    MissingParent,
+    /// The parent of the node is the node itself.
+    SelfParent,
+    /// The node is on a cycle of parent links, whether the cycle is reachable from the root or not.
+    Cycle,
+    /// The node is marked as a leaf but is the parent of other nodes.
+    LeafParent,
+    /// The same pair of node and parent appears more than once.
+    Duplicate,
+    /// `lft` or `rgt` of the node is missing.
+    MissingBounds,
+    /// `lft` of the node is not less than `rgt`.
+    InvalidBounds,
+    /// `lft` or `rgt` of the node is also used by another node.
+    DuplicateBounds,
+    /// The interval of the node partially overlaps the interval of another node.
+    Overlap,
+    /// The innermost interval containing the node is not the interval of its parent.
+    ContainmentMismatch,
+    /// The numbers used by `lft` and `rgt` are not `1` to twice the number of nodes.
+    NonContiguous,
+}
+
+/// A problem found in the nodes.
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    /// Position of the node among the nodes read, starting from 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub row: Option<usize>,
+    /// Line of the input of a record which could not be parsed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub message: String,
+}
+
+impl Issue {
+    fn new<S: Into<String>>(kind: IssueKind, row: usize, node: &Node, message: S) -> Self {
+        Issue {
+            kind,
+            row: Some(row + 1),
+            line: None,
+            id: Some(node.id().to_owned()),
+            message: message.into(),
+        }
+    }
+}
+
+/// Checks that `nodes` make a single tree, or a directed acyclic graph, that can be indexed.
+///
+/// Reports the roots unless there is exactly one, missing parents, nodes which are their own
+/// parent, cycles, leaf nodes used as parents and duplicated pairs of node and parent.
+pub fn validate(nodes: &[Node]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let roots: Vec<usize> = (0..nodes.len())
+        .filter(|i| nodes[*i].parent().is_none())
+        .collect();
+    match roots.len() {
+        0 => issues.push(Issue {
+            kind: IssueKind::NoRoot,
+            row: None,
+            line: None,
+            id: None,
+            message: "no node without a parent was found".to_owned(),
+        }),
+        1 => {}
+        n => {
+            for i in &roots {
+                let message = format!("one of {} nodes without a parent", n);
+                issues.push(Issue::new(
+                    IssueKind::MultipleRoots,
+                    *i,
+                    &nodes[*i],
+                    message,
+                ));
+            }
+        }
+    }
+
+    let mut rows = HashMap::new();
+    let mut leaves = HashSet::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if node.is_leaf() {
+            leaves.insert(node.id());
+        } else {
+            rows.entry(node.id()).or_insert(i);
+        }
+    }
+
+    let mut pairs = HashSet::new();
+    let mut children = HashMap::<&str, Vec<usize>>::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if !pairs.insert((node.id(), node.parent())) {
+            let message = format!("duplicated node with parent {:?}", node.parent());
+            issues.push(Issue::new(IssueKind::Duplicate, i, node, message));
+        }
+
+        match node.parent() {
+            Some(parent) if parent == node.id() => {
+                let message = "the parent is the node itself";
+                issues.push(Issue::new(IssueKind::SelfParent, i, node, message));
+            }
+            Some(parent) if rows.contains_key(parent) => {
+                children.entry(parent).or_default().push(i);
+            }
+            Some(parent) if leaves.contains(parent) => {
+                let message = format!("parent {} is marked as a leaf", parent);
+                issues.push(Issue::new(IssueKind::LeafParent, i, node, message));
+            }
+            Some(parent) => {
+                let message = format!("parent {} not found", parent);
+                issues.push(Issue::new(IssueKind::MissingParent, i, node, message));
+            }
+            None => {}
+        }
+    }
+
+    // peel the nodes without parents off, so that only the cycles and the nodes below them are
+    // left, including the cycles below a root
+    let mut parents = HashMap::<&str, usize>::new();
+    for node in nodes {
+        parents.entry(node.id()).or_insert(0);
+    }
+    for rows in children.values() {
+        for i in rows {
+            *parents.get_mut(nodes[*i].id()).unwrap() += 1;
+        }
+    }
+    let mut pending: Vec<&str> = parents
+        .iter()
+        .filter(|(_, x)| **x == 0)
+        .map(|(id, _)| *id)
+        .collect();
+    while let Some(id) = pending.pop() {
+        for i in children.get(id).into_iter().flatten() {
+            let count = parents.get_mut(nodes[*i].id()).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                pending.push(nodes[*i].id());
+            }
+        }
+    }
+
+    // peel the nodes without children off until only the cycles are left
+    let mut remaining: HashSet<&str> = parents
+        .iter()
+        .filter(|(_, x)| **x > 0)
+        .map(|(id, _)| *id)
+        .collect();
+    loop {
+        let peeled: Vec<&str> = remaining
+            .iter()
+            .filter(|id| {
+                children
+                    .get(*id)
+                    .is_none_or(|x| x.iter().all(|i| !remaining.contains(nodes[*i].id())))
+            })
+            .copied()
+            .collect();
+        if peeled.is_empty() {
+            break;
+        }
+        for id in peeled {
+            remaining.remove(id);
+        }
+    }
+
+    let mut cycles: Vec<usize> = remaining.iter().map(|id| rows[id]).collect();
+    cycles.sort_unstable();
+    for i in cycles {
+        let message = "on a cycle of parent links";
+        issues.push(Issue::new(IssueKind::Cycle, i, &nodes[i], message));
+    }
+
+    issues.sort_by_key(|x| x.row);
+    issues
+}
+
+/// Checks that the existing `lft` and `rgt` of `nodes` make a valid nested set.
+///
+/// Reports missing bounds, `lft` not less than `rgt`, numbers used more than once, partially
+/// overlapping intervals, intervals not directly contained in the interval of the parent and
+/// numbers other than `1` to `2n` unless `allow_gaps`, such as of [`NumberingOptions::gap`].
+///
+/// [`NumberingOptions::gap`]: crate::data::NumberingOptions::gap
+pub fn verify(nodes: &[Node], allow_gaps: bool) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let mut intervals = Vec::new();
+    for (i, node) in nodes.iter().enumerate() {
+        match (node.lft(), node.rgt()) {
+            (Some(lft), Some(rgt)) if lft < rgt => intervals.push((lft, rgt, i)),
+            (Some(lft), Some(rgt)) => {
+                let message = format!("lft {} is not less than rgt {}", lft, rgt);
+                issues.push(Issue::new(IssueKind::InvalidBounds, i, node, message));
+            }
+            _ => {
+                let message = "lft or rgt is missing";
+                issues.push(Issue::new(IssueKind::MissingBounds, i, node, message));
+            }
+        }
+    }
+
+    let mut used = HashMap::<usize, usize>::new();
+    for (i, node) in nodes.iter().enumerate() {
+        for value in [node.lft(), node.rgt()].iter().flatten() {
+            if let Some(other) = used.insert(*value, i) {
+                if other != i {
+                    let message = format!("{} is also used by row {}", value, other + 1);
+                    issues.push(Issue::new(IssueKind::DuplicateBounds, i, node, message));
+                }
+            }
+        }
+    }
+
+    let missing: Vec<String> = (1..=nodes.len() * 2)
+        .filter(|x| !used.contains_key(x))
+        .map(|x| x.to_string())
+        .collect();
+    let extra: Vec<String> = used
+        .keys()
+        .filter(|x| **x == 0 || **x > nodes.len() * 2)
+        .map(|x| x.to_string())
+        .collect();
+    if !allow_gaps && (!missing.is_empty() || !extra.is_empty()) {
+        issues.push(Issue {
+            kind: IssueKind::NonContiguous,
+            row: None,
+            line: None,
+            id: None,
+            message: format!(
+                "expected numbers from 1 to {}, missing: [{}], out of range: [{}]",
+                nodes.len() * 2,
+                missing.join(", "),
+                extra.join(", ")
+            ),
+        });
+    }
+
+    // sweep the intervals in the order of `lft`, keeping the ones containing the current one
+    intervals.sort_unstable();
+    let mut stack: Vec<(usize, usize, usize)> = Vec::new();
+    for (lft, rgt, i) in intervals {
+        let node = &nodes[i];
+        while stack.last().is_some_and(|x| x.1 < lft) {
+            stack.pop();
+        }
+
+        if let Some(&(_, other_rgt, other)) = stack.last() {
+            if other_rgt < rgt {
+                let message = format!("overlaps row {}", other + 1);
+                issues.push(Issue::new(IssueKind::Overlap, i, node, message));
+                continue;
+            }
+        }
+
+        let container = stack.last().map(|x| nodes[x.2].id());
+        if container != node.parent() {
+            let message = format!(
+                "contained in {:?} but the parent is {:?}",
+                container,
+                node.parent()
+            );
+            issues.push(Issue::new(IssueKind::ContainmentMismatch, i, node, message));
+        }
+
+        stack.push((lft, rgt, i));
+    }
+
+    issues.sort_by_key(|x| x.row);
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, verify, IssueKind};
+    use crate::data::{Graph, Node, NumberingOptions};
+    use crate::format::{read_nodes, Format, ReadOptions};
+
+    #[test]
+    fn test_validate() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("4"),
+            Node::new("4", "c").with_parent("3"),
+            Node::new("5", "d").with_parent("4"),
+            Node::new("6", "e").with_parent("9"),
+            Node::new("7", "f").with_parent("1").with_leaf(true),
+            Node::new("8", "g").with_parent("7"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("10", "h").with_parent("10"),
+        ];
+        assert!(validate(&nodes[..2]).is_empty());
+
+        let issues: Vec<_> = validate(&nodes)
+            .into_iter()
+            .map(|x| (x.kind, x.row))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (IssueKind::Cycle, Some(3)),
+                (IssueKind::Cycle, Some(4)),
+                (IssueKind::MissingParent, Some(6)),
+                (IssueKind::LeafParent, Some(8)),
+                (IssueKind::Duplicate, Some(9)),
+                (IssueKind::SelfParent, Some(10)),
+            ]
+        );
+
+        let issues = validate(&nodes[1..]);
+        assert_eq!(issues[0].kind, IssueKind::NoRoot);
+
+        // a cycle below the root, through a node with a second parent
+        let nodes = vec![
+            Node::new("1", "a"),
+            Node::new("2", "b").with_parent("1"),
+            Node::new("3", "c").with_parent("2"),
+            Node::new("4", "d").with_parent("3"),
+            Node::new("2", "b").with_parent("4"),
+        ];
+        let issues: Vec<_> = validate(&nodes)
+            .into_iter()
+            .map(|x| (x.kind, x.row))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (IssueKind::Cycle, Some(2)),
+                (IssueKind::Cycle, Some(3)),
+                (IssueKind::Cycle, Some(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "c").with_parent("1"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        graph.build_index().unwrap();
+        assert!(verify(&graph.nodes, false).is_empty());
+
+        // the numbers of --gap are only accepted with `allow_gaps`
+        let mut graph = Graph::new(graph.nodes).unwrap();
+        graph
+            .build_index_with(&NumberingOptions {
+                gap: 10,
+                lft_start: Some(101),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(verify(&graph.nodes, true).is_empty());
+        let issues = verify(&graph.nodes, false);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::NonContiguous);
+
+        let nodes = read_nodes(
+            &Format::Csv,
+            "id,parent,label,lft,rgt\n\
+             1,,root,1,8\n\
+             2,1,a,2,5\n\
+             3,1,b,3,4\n\
+             4,1,c,4,7\n"
+                .as_bytes(),
+            &ReadOptions::default(),
+        )
+        .unwrap()
+        .nodes;
+        let issues: Vec<_> = verify(&nodes, false)
+            .into_iter()
+            .map(|x| (x.kind, x.row))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (IssueKind::NonContiguous, None),
+                (IssueKind::ContainmentMismatch, Some(3)),
+                (IssueKind::DuplicateBounds, Some(4)),
+                (IssueKind::Overlap, Some(4)),
+            ]
+        );
+    }
+}
@@ -1,53 +1,1151 @@
-use std::path::PathBuf;
-use std::str::FromStr;
+use crate::config;
+use nested_set_indexer::aggregate::Aggregate;
+use nested_set_indexer::format::{
+    Compression, Dialect, Format, IfExists, OutputColumns, ReadOptions, WriteOptions,
+};
+use nested_set_indexer::generate::Shape;
+use nested_set_indexer::merge::ConflictPolicy;
+use nested_set_indexer::resolve::{
+    DuplicatePolicy, IdNormalization, LeafParentPolicy, MissingParentPolicy, OrphanPolicy,
+    SelfParentFix,
+};
+use nested_set_indexer::{
+    error, ColumnMapping, ComplementOptions, DagOptions, DupNaming, KeepId, NumberingOptions,
+    PathOptions, PathSegment, SiblingOrder,
+};
+use regex::Regex;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use structopt::clap::{self, AppSettings, ArgGroup, Shell};
 use structopt::StructOpt;
 use strum::{EnumString, EnumVariantNames, VariantNames};
 
+fn parse_probability(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if (0.0..=1.0).contains(&x) => Ok(x),
+        _ => Err(format!("expected a number from 0 to 1: {}", s)),
+    }
+}
+
+fn parse_sibling_order(s: &str) -> Result<SiblingOrder, String> {
+    match s {
+        "input" => Ok(SiblingOrder::Input),
+        "id" => Ok(SiblingOrder::Id),
+        "label" => Ok(SiblingOrder::Label),
+        _ => match s.strip_prefix("column:") {
+            Some(column) if !column.is_empty() => Ok(SiblingOrder::Column(column.to_owned())),
+            _ => Err(format!(
+                "expected `input`, `id`, `label` or `column:<name>`: {}",
+                s
+            )),
+        },
+    }
+}
+
+fn parse_positive(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err(format!("expected a positive integer: {}", s)),
+    }
+}
+
+fn parse_aggregate(s: &str) -> Result<Aggregate, String> {
+    let error = || format!("expected `<function>:<column>`: {}", s);
+    let (function, column) = s.split_once(':').ok_or_else(error)?;
+    match (function.parse(), column) {
+        (Ok(function), column) if !column.is_empty() => Ok(Aggregate {
+            function,
+            column: column.to_owned(),
+        }),
+        _ => Err(error()),
+    }
+}
+
+fn parse_rename(s: &str) -> Result<(String, String), String> {
+    match s.find('=') {
+        Some(i) => Ok((s[..i].to_owned(), s[i + 1..].to_owned())),
+        None => Err(format!("expected `<column>=<name>`: {}", s)),
+    }
+}
+
+/// Format of the log messages written to stderr.
 #[derive(Debug, Clone, EnumString, EnumVariantNames)]
 #[strum(serialize_all = "snake_case")]
-pub enum Format {
-    CSV,
-    TSV,
-    JSON,
+pub enum LogFormat {
+    Text,
+    /// One JSON object per message.
+    Json,
+}
+
+/// Format of the error message written to stderr when a command fails.
+#[derive(Debug, Clone, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorFormat {
+    Text,
+    /// A JSON object with the kind and the class of the error, and the node id or the line if
+    /// known.
+    Json,
+}
+
+/// Order of the output rows.
+#[derive(Debug, Clone, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum OutputOrder {
+    Pid,
+    /// Pre-order of the tree, each node coming before its descendants.
+    Lft,
+    /// Order of the input rows, the nodes added by the indexer coming last.
+    Input,
+}
+
+/// How a directed acyclic graph is turned into a tree, or kept.
+#[derive(Debug, Clone, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum DagMode {
+    /// Duplicate the nodes with multiple parents under each of them.
+    Duplicate,
+    /// Keep the graph and write its closure table of ids with --to closure instead of indexing it.
+    Closure,
+    /// Keep every node only under its primary parent, dropping the other edges.
+    PrimaryParent,
 }
 
 #[derive(Debug, StructOpt)]
-pub struct Options {
-    /// Complement leaf nodes
+#[structopt(
+    global_setting = AppSettings::AllArgsOverrideSelf,
+    global_setting = AppSettings::ColorNever,
+    after_help = "Default options are read from `nested_set_indexer.toml` in the current directory, or \
+                  from the file given by `--config <path>`. `--no-<option>` turns off an option of the \
+                  file.
+
+Exits with 1 for other errors, 2 for invalid options, 3 for an input which can not be parsed, 4 \
+                  for an input which is not a valid hierarchy and 5 for errors reading or writing."
+)]
+pub struct Cli {
+    /// Log the details and the timings of the phases (-v), or everything (-vv)
+    #[structopt(short, long, parse(from_occurrences), global = true)]
+    pub verbose: u8,
+
+    /// Format of the log messages on stderr
+    #[structopt(long, default_value = "text", possible_values = LogFormat::VARIANTS, global = true)]
+    pub log_format: LogFormat,
+
+    /// Format of the error message on stderr when the command fails
+    #[structopt(long, default_value = "text", possible_values = ErrorFormat::VARIANTS, global = true)]
+    pub error_format: ErrorFormat,
+
+    /// Write the wall-clock time of every phase and the peak memory after it to stderr as JSON
+    /// when the command ends
+    #[structopt(long, global = true)]
+    pub timings: bool,
+
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+impl Cli {
+    /// Parses the command line, running `index` if no subcommand is given.
+    ///
+    /// The help and the version are printed by exiting, while the other errors of the options are
+    /// returned to be reported in --error-format.
+    pub fn from_args() -> error::Result<Cli> {
+        Cli::from_command_line(env::args_os().collect()).map_err(|e| match e.kind {
+            clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => e.exit(),
+            _ => {
+                let message = e.message.strip_prefix("error: ").unwrap_or(&e.message);
+                error::Error::UsageError(message.to_owned())
+            }
+        })
+    }
+
+    /// Parses `args` like [`Cli::from_args`].
+    fn from_command_line(mut args: Vec<OsString>) -> clap::Result<Cli> {
+        Cli::lead_with_command(&mut args);
+        Cli::apply_config(&mut args)
+            .map_err(|e| clap::Error::with_description(&e, clap::ErrorKind::InvalidValue))?;
+
+        Cli::from_iter_safe(args)
+    }
+
+    /// Returns --error-format of the command line, which is used for the errors of parsing it.
+    pub fn error_format() -> ErrorFormat {
+        Cli::error_format_of(&env::args_os().collect::<Vec<_>>())
+    }
+
+    fn error_format_of(args: &[OsString]) -> ErrorFormat {
+        let args: Vec<_> = args.iter().filter_map(|x| x.to_str()).collect();
+        // the last one takes precedence like the other options
+        let value = args.iter().enumerate().rev().find_map(|(i, x)| match *x {
+            "--error-format" => args.get(i + 1).copied(),
+            _ => x.strip_prefix("--error-format="),
+        });
+        value
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(ErrorFormat::Text)
+    }
+
+    /// Moves the subcommand to the first argument, after the global options given before it, or
+    /// inserts `index` there if no subcommand is given.
+    fn lead_with_command(args: &mut Vec<OsString>) {
+        // the global options which take a value
+        const VALUED: &[&str] = &["--log-format", "--error-format", "--config"];
+
+        let mut i = 1;
+        while let Some(arg) = args.get(i).and_then(|x| x.to_str()) {
+            let verbose =
+                arg.len() > 1 && arg.starts_with('-') && arg[1..].bytes().all(|c| c == b'v');
+            let valued = VALUED
+                .iter()
+                .any(|x| arg.strip_prefix(x).is_some_and(|x| x.starts_with('=')));
+            i += match arg {
+                _ if VALUED.contains(&arg) => 2,
+                "--verbose" | "--timings" => 1,
+                _ if verbose || valued => 1,
+                _ => break,
+            };
+        }
+
+        let is_command = args.get(i).and_then(|x| x.to_str()).is_some_and(|x| {
+            Command::VARIANTS.contains(&x)
+                || matches!(x, "help" | "-h" | "--help" | "-V" | "--version")
+        });
+        if is_command {
+            // the global options are also accepted after the subcommand
+            let command = args.remove(i);
+            args.insert(1, command);
+        } else {
+            args.insert(1, OsString::from("index"));
+        }
+    }
+
+    /// Inserts the options of the configuration file which are not given in the command line,
+    /// or turned off with `--no-<key>`.
+    fn apply_config(args: &mut Vec<OsString>) -> Result<(), String> {
+        let path = config::take_path(args)?;
+        let command = match args.get(1).and_then(|x| x.to_str()) {
+            Some(x) if Command::VARIANTS.contains(&x) => x.to_owned(),
+            _ => return Ok(()),
+        };
+        // taken without a file as well, so that the same command line works everywhere
+        let mut given = config::take_negations(args, |key| Cli::accepts(&command, key));
+        let path = match path {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        let config = config::read(&path)?;
+        given.extend(config::long_options(&args[2..]));
+        let inserted = config::args(&config, &command, Command::VARIANTS, &given, Cli::accepts)?;
+        args.splice(2..2, inserted);
+
+        Ok(())
+    }
+
+    /// Whether `command` has the option `--<key>`.
+    fn accepts(command: &str, key: &str) -> bool {
+        let option = format!("--{}", key);
+        match Cli::clap().get_matches_from_safe(["", command, &option]) {
+            Ok(_) => true,
+            Err(e) => e.kind != clap::ErrorKind::UnknownArgument,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Command {
+    /// Assign the left/right indices of nested set to the nodes (default)
+    Index(IndexOptions),
+    /// Check the structure of the input and report every problem as JSON without indexing it
+    Validate(ValidateOptions),
+    /// Check the existing lft/rgt of the input and report every violation as JSON
+    Verify(VerifyOptions),
+    /// Rebuild broken lft/rgt of the input from the parent links, keeping the `pid` column
+    Repair(RepairOptions),
+    /// Renumber the lft/rgt of an indexed input contiguously from 1, keeping the structure and the
+    /// `pid` column
+    Renumber(RepairOptions),
+    /// Index the input and output only the ancestors, the descendants or the subtree of a node
+    Query(QueryOptions),
+    /// Index the input and report the lowest common ancestor of nodes and the paths to them as JSON
+    Lca(LcaOptions),
+    /// Compare two inputs and report the added, removed, moved and relabeled nodes as JSON
+    Diff(DiffOptions),
+    /// Apply a change file of add, move, delete and rename operations to an indexed input
+    Patch(PatchOptions),
+    /// Insert a node into an indexed input, shifting lft/rgt only where needed
+    Insert(InsertOptions),
+    /// Move a node with its descendants under another parent of an indexed input
+    Move(MoveOptions),
+    /// Delete a node with its descendants, or only the node, from an indexed input
+    Delete(DeleteOptions),
+    /// Merge several inputs into one hierarchy and index it
+    Merge(MergeOptions),
+    /// Index the input and write the subtree of every child of the root to its own file
+    Split(SplitOptions),
+    /// Write a random tree or directed acyclic graph for benchmarking and testing
+    Generate(GenerateOptions),
+    /// Serve indexing of posted nodes and queries about the input over HTTP
+    Serve(ServeOptions),
+    /// Write the shell completion script to stdout
+    Completions(CompletionsOptions),
+}
+
+impl Command {
+    /// Whether --quiet is given to the command.
+    pub fn quiet(&self) -> bool {
+        match self {
+            Command::Index(x) => x.quiet,
+            Command::Query(QueryOptions { index, .. })
+            | Command::Lca(LcaOptions { index, .. })
+            | Command::Merge(MergeOptions { index, .. })
+            | Command::Split(SplitOptions { index, .. })
+            | Command::Serve(ServeOptions { index, .. }) => index.quiet,
+            Command::Repair(x) | Command::Renumber(x) => x.quiet,
+            Command::Patch(x) => x.quiet,
+            Command::Insert(x) => x.quiet,
+            Command::Move(x) => x.quiet,
+            Command::Delete(x) => x.quiet,
+            Command::Generate(x) => x.quiet,
+            Command::Validate(_)
+            | Command::Verify(_)
+            | Command::Diff(_)
+            | Command::Completions(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct InputOptions {
+    /// Input column of node ids. The columns of the default output are detected unless any
+    /// column is given
+    #[structopt(long, default_value = "id")]
+    pub id_column: String,
+
+    /// Input column of parent node ids
+    #[structopt(long, default_value = "parent")]
+    pub parent_column: String,
+
+    /// Input column of node labels
+    #[structopt(long, default_value = "label")]
+    pub label_column: String,
+
+    /// Input column of the pids of already indexed inputs, such as `id` of the output
+    #[structopt(long, default_value = "pid")]
+    pub pid_column: String,
+
+    /// SQL query to read the nodes with for database input formats
     #[structopt(long)]
-    pub complement_leaf: bool,
+    pub query: Option<String>,
+
+    /// Table to read the nodes from for database input formats
+    #[structopt(long, conflicts_with = "query")]
+    pub input_table: Option<String>,
+
+    /// Sheet to read the nodes from for the xlsx input format (default: the first sheet)
+    #[structopt(long)]
+    pub sheet: Option<String>,
+
+    /// Separator of the segments of paths for the path input format and --with-path
+    #[structopt(long, default_value = "/")]
+    pub path_separator: String,
 
-    /// Input format
+    /// Input format (default: by the extension of the input file)
     #[structopt(short, long, possible_values = Format::VARIANTS)]
     pub from: Option<Format>,
 
-    /// Output format
+    /// Fail if any CSV/TSV/JSON Lines record or OBO term can not be parsed (default: skip it with a
+    /// warning)
+    #[structopt(long)]
+    pub strict: bool,
+
+    /// File, directory for `ncbi-taxdump` or database URL such as `postgres://...` to process
+    /// (default: stdin)
+    #[structopt(parse(from_os_str))]
+    pub input: Option<PathBuf>,
+}
+
+impl InputOptions {
+    pub fn column_mapping(&self) -> ColumnMapping {
+        ColumnMapping {
+            id: self.id_column.to_owned(),
+            parent: self.parent_column.to_owned(),
+            label: self.label_column.to_owned(),
+            pid: self.pid_column.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn read_options(&self) -> ReadOptions {
+        ReadOptions {
+            strict: self.strict,
+            columns: self.column_mapping(),
+            query: self.query.to_owned(),
+            table: self.input_table.to_owned(),
+            sheet: self.sheet.to_owned(),
+            path_separator: Some(self.path_separator.to_owned()),
+        }
+    }
+
+    /// Returns --from, or the format of the input by its extension.
+    pub fn format(&self) -> error::Result<Format> {
+        self.format_of(self.input.as_deref())
+    }
+
+    /// Returns --from, or the format of the file at `path` by its extension.
+    pub fn format_of(&self, path: Option<&Path>) -> error::Result<Format> {
+        match &self.from {
+            Some(v) => Ok(v.clone()),
+            None => path
+                .and_then(Format::from_path)
+                .ok_or_else(|| error::Error::UsageError("missing option --from".to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct OutputOptions {
+    /// Output format (default: by the extension of the output file, or the input format)
     #[structopt(short, long, possible_values = Format::VARIANTS)]
     pub to: Option<Format>,
 
-    /// Output to a file (default: stdout)
+    /// Comma-separated output columns to emit, in order (default: all)
+    #[structopt(long, use_delimiter = true)]
+    pub out_columns: Vec<String>,
+
+    /// Rename an output column, given as `<column>=<name>` (can be repeated)
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_rename))]
+    pub rename_column: Vec<(String, String)>,
+
+    /// SQL dialect for the sql output format
+    #[structopt(long, default_value = "postgres", possible_values = Dialect::VARIANTS)]
+    pub dialect: Dialect,
+
+    /// What to do with an existing table for database output formats
+    #[structopt(long, default_value = "truncate", possible_values = IfExists::VARIANTS)]
+    pub if_exists: IfExists,
+
+    /// Table to write for database and SQL output formats
+    #[structopt(long, default_value = "nested_set")]
+    pub table: String,
+
+    /// Output to a file or a database URL such as `postgres://...` (default: stdout)
     #[structopt(short, long, parse(from_os_str))]
     pub output: Option<PathBuf>,
 
+    /// Compress the output (default: by the extension of the output file such as `.gz` or `.zst`)
+    #[structopt(long, possible_values = Compression::VARIANTS)]
+    pub compress: Option<Compression>,
+}
+
+impl OutputOptions {
+    /// Returns the options to write the nodes with, emitting the `optional` columns.
+    pub fn write_options(&self, optional: Vec<String>) -> WriteOptions {
+        WriteOptions {
+            columns: OutputColumns {
+                select: self.out_columns.to_owned(),
+                optional,
+                rename: self.rename_column.to_owned(),
+            },
+            table: self.table.to_owned(),
+            dialect: self.dialect.to_owned(),
+            if_exists: self.if_exists.to_owned(),
+            compression: self.compress.to_owned(),
+        }
+    }
+
+    /// Returns --to, or the format of the output by its extension, or `input` otherwise.
+    pub fn format(&self, input: &Format) -> Format {
+        match &self.to {
+            Some(v) => v.clone(),
+            None => self.format_from_output().unwrap_or_else(|| input.clone()),
+        }
+    }
+
+    pub fn format_from_output(&self) -> Option<Format> {
+        Format::from_path(self.output.as_ref()?)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct IndexOptions {
+    /// Complement leaf nodes
+    #[structopt(long)]
+    pub complement_leaf: bool,
+
+    /// Prefix of the ids of the classification nodes of --complement-leaf
+    #[structopt(long, default_value = "c")]
+    pub complement_prefix: String,
+
+    /// Template of the label of the leaves of --complement-leaf, such as `{label} (unclassified)`,
+    /// where `{label}` and `{id}` are replaced with the label and the id of the node (default: the
+    /// label)
+    #[structopt(long)]
+    pub complement_label: Option<String>,
+
+    /// Which of the classification node and its leaf of --complement-leaf keeps the original id,
+    /// the other one being prefixed with --complement-prefix
+    #[structopt(long, default_value = "leaf", possible_values = KeepId::VARIANTS)]
+    pub complement_keep_id: KeepId,
+
+    /// Complement only the nodes which have children, leaving the leaves as they are
+    #[structopt(long)]
+    pub complement_internal_only: bool,
+
+    /// Separator of the suffix of the nodes duplicated to convert a directed acyclic graph to a
+    /// tree, and of the prefix of --complement-leaf
+    #[structopt(long, default_value = "__")]
+    pub separator: String,
+
+    /// Order of the children of every node: `input`, `id`, `label` or the values of an input column
+    /// with `column:<name>`, numbers being compared numerically
+    #[structopt(long, default_value = "input", parse(try_from_str = parse_sibling_order))]
+    pub sort_children: SiblingOrder,
+
+    /// How to handle a directed acyclic graph: `duplicate` the nodes with multiple parents, write
+    /// its `closure` table of ids with --to closure instead of indexing it, or keep every node
+    /// under its `primary_parent` column or its first listed parent with `primary-parent`
+    #[structopt(long, default_value = "duplicate", possible_values = DagMode::VARIANTS)]
+    pub dag_mode: DagMode,
+
+    /// Write the edges dropped by --dag-mode primary-parent to the file as headerless
+    /// `child,parent` rows
+    #[structopt(long, parse(from_os_str))]
+    pub secondary_edges: Option<PathBuf>,
+
+    /// Naming of the nodes duplicated to convert a directed acyclic graph to a tree: `counter`
+    /// numbers them in the order of the traversal, `parent-suffix` and `path-hash` suffix every
+    /// duplicate with the id of its parent or a hash of its ancestors for ids stable across runs
+    #[structopt(long, default_value = "counter", possible_values = DupNaming::VARIANTS)]
+    pub dup_naming: DupNaming,
+
+    /// Write the ids and the parents of the copies of every node duplicated to convert a directed
+    /// acyclic graph to a tree to the JSON file, keyed by the original id
+    #[structopt(long, parse(from_os_str))]
+    pub dup_mapping: Option<PathBuf>,
+
+    /// Mark the nodes which are not the parent of any node as leaves, unless the `leaf` column is
+    /// given for the node
+    #[structopt(long)]
+    pub infer_leaf: bool,
+
+    /// What to do with a node marked as a leaf which is the parent of other nodes
+    #[structopt(long, default_value = "error", possible_values = LeafParentPolicy::VARIANTS)]
+    pub on_leaf_parent: LeafParentPolicy,
+
+    /// Attach multiple root nodes to a virtual root node instead of failing
+    #[structopt(long)]
+    pub allow_forest: bool,
+
+    /// Id of the virtual root node for --allow-forest
+    #[structopt(long, default_value = "root")]
+    pub forest_root_id: String,
+
+    /// Label of the virtual root node for --allow-forest
+    #[structopt(long, default_value = "root")]
+    pub forest_root_label: String,
+
+    /// What to do with a node which appears more than once with the same parent
+    #[structopt(long, default_value = "dedupe", possible_values = DuplicatePolicy::VARIANTS)]
+    pub on_duplicate: DuplicatePolicy,
+
+    /// Comma-separated normalizations of the ids, the parents and --root-node, applied in order
+    /// before matching the parents to the ids, such as `trim,lowercase`
+    #[structopt(long, use_delimiter = true, possible_values = IdNormalization::VARIANTS)]
+    pub normalize_ids: Vec<IdNormalization>,
+
+    /// Fix a node whose parent is itself by removing the parent or dropping the row (default: fail)
+    #[structopt(long, possible_values = SelfParentFix::VARIANTS)]
+    pub fix_self_parent: Option<SelfParentFix>,
+
+    /// What to do with a node whose parent is not in the input
+    #[structopt(long, default_value = "error", possible_values = MissingParentPolicy::VARIANTS)]
+    pub on_missing_parent: MissingParentPolicy,
+
+    /// What to do with the nodes not reachable from the root, such as the nodes on a cycle of
+    /// parent links
+    #[structopt(long, default_value = "error", possible_values = OrphanPolicy::VARIANTS)]
+    pub on_orphans: OrphanPolicy,
+
+    /// Id of the node created under the root to attach the orphaned nodes to for `--on-orphans
+    /// attach`
+    #[structopt(long, default_value = "orphans")]
+    pub orphans_id: String,
+
+    /// Label of the node created for `--on-orphans attach`
+    #[structopt(long, default_value = "orphans")]
+    pub orphans_label: String,
+
+    /// Index only the node with this id and its descendants
+    #[structopt(long)]
+    pub root_node: Option<String>,
+
+    /// Drop the nodes whose id or label matches this regular expression with their descendants
+    #[structopt(long)]
+    pub exclude: Option<Regex>,
+
+    /// Keep only the nodes whose id or label matches this regular expression with their ancestors
+    /// and descendants
+    #[structopt(long)]
+    pub include: Option<Regex>,
+
+    /// Comma-separated ranks of a taxonomy, such as `phylum,genus,species`, to keep only the root
+    /// and the nodes of, attaching each of them to its nearest kept ancestor
+    #[structopt(long, use_delimiter = true)]
+    pub ranks: Vec<String>,
+
+    /// Input column of the ranks for --ranks
+    #[structopt(long, default_value = "rank")]
+    pub rank_column: String,
+
+    /// Only convert the format and expand a directed acyclic graph to a tree, leaving lft/rgt as
+    /// they are
+    #[structopt(long, conflicts_with_all = &["with-depth", "with-counts", "with-path", "ltree-path", "with-label-path", "with-hash"])]
+    pub no_index: bool,
+
+    /// Tab-separated file, or comma-separated with the `.csv` extension, of the counts of the
+    /// nodes such as the reads of taxa, emitted as `self_count` and summed up the ancestors as
+    /// `cumulative_count`
+    #[structopt(long, parse(from_os_str))]
+    pub counts: Option<PathBuf>,
+
+    /// Remove the subtrees whose cumulative count of --counts is zero before indexing
+    #[structopt(long, requires = "counts")]
+    pub prune_zero: bool,
+
+    /// Comma-separated numeric input columns to roll up from the descendants with a function, such
+    /// as `sum:abundance,max:score`, emitted as `abundance_sum` and `score_max`. The functions are
+    /// sum, min, max and mean
+    #[structopt(long, use_delimiter = true, parse(try_from_str = parse_aggregate))]
+    pub aggregate: Vec<Aggregate>,
+
+    /// Keep the given number of children with the largest cumulative count of --counts of every
+    /// node, merging the others into an --other-label child
+    #[structopt(long, requires = "counts")]
+    pub top_children: Option<usize>,
+
+    /// Label of the child merging the others of --top-children
+    #[structopt(long, default_value = "Other")]
+    pub other_label: String,
+
+    /// Remove the nodes deeper than this, the root being at depth 0 (default: unlimited)
+    #[structopt(long)]
+    pub max_depth: Option<usize>,
+
+    /// Collapse the nodes deeper than --max-depth into their ancestor at that depth, keeping
+    /// their --counts and --aggregate values in it, instead of dropping them before rolling up
+    #[structopt(long, requires = "max-depth")]
+    pub rollup: bool,
+
+    /// Step between the consecutive lft/rgt values, such as 10 for 10, 20, 30, ..., leaving room
+    /// for inserting nodes without renumbering the others
+    #[structopt(long, default_value = "1", parse(try_from_str = parse_positive))]
+    pub gap: usize,
+
+    /// First lft, such as one more than the largest rgt of the table the output is appended to
+    /// (default: --gap)
+    #[structopt(long)]
+    pub lft_start: Option<usize>,
+
+    /// Number added to every pid and parent_id, such as the largest id of the table the output is
+    /// appended to
+    #[structopt(long, default_value = "0")]
+    pub pid_offset: usize,
+
+    /// Use the ids, which must be unique integers, as pid and parent_id instead of numbering the
+    /// nodes, keeping the foreign keys of other tables valid
+    #[structopt(long)]
+    pub pid_from_id: bool,
+
+    /// Order of the output rows of index and merge: by `pid`, by `lft` or as the `input` rows
+    #[structopt(long, default_value = "pid", possible_values = OutputOrder::VARIANTS)]
+    pub order: OutputOrder,
+
+    /// Emit the depth of each node from the root (root is 0)
+    #[structopt(long)]
+    pub with_depth: bool,
+
+    /// Emit the number of all the descendants and of the descendants without children
+    #[structopt(long)]
+    pub with_counts: bool,
+
+    /// Emit the materialized path from the root to each node in a `path` column
+    #[structopt(long)]
+    pub with_path: bool,
+
+    /// What the segments of --with-path are made of
+    #[structopt(long, default_value = "id", possible_values = PathSegment::VARIANTS)]
+    pub path_segment: PathSegment,
+
+    /// Make --with-path a valid PostgreSQL ltree, delimited by `.` with the characters other than
+    /// alphanumerics replaced with `_`
+    #[structopt(long)]
+    pub ltree_path: bool,
+
+    /// Emit the labels from the root to each node in a `label_path` column
+    #[structopt(long)]
+    pub with_label_path: bool,
+
+    /// Separator of the labels of --with-label-path
+    #[structopt(long, default_value = " > ")]
+    pub label_path_separator: String,
+
+    /// Emit a hash of the ids, the labels and the structure of the subtree of each node in a
+    /// `hash` column, which stays the same between releases unless the subtree changes
+    #[structopt(long)]
+    pub with_hash: bool,
+
+    /// Keep running and run again whenever the input file changes
+    #[structopt(long)]
+    pub watch: bool,
+
+    /// No output messages
+    #[structopt(short, long)]
+    pub quiet: bool,
+
+    #[structopt(flatten)]
+    pub input: InputOptions,
+
+    #[structopt(flatten)]
+    pub output: OutputOptions,
+}
+
+impl IndexOptions {
+    /// Fails with --no-index or --dag-mode closure for the commands which work on lft/rgt.
+    pub fn require_index(&self) -> error::Result<()> {
+        if self.no_index {
+            Err(error::Error::UsageError(
+                "--no-index can not be used with this command".to_owned(),
+            ))?
+        }
+        if self.dag_mode == DagMode::Closure {
+            Err(error::Error::UsageError(
+                "--dag-mode closure can not be used with this command".to_owned(),
+            ))?
+        }
+
+        Ok(())
+    }
+
+    /// Fails with --dag-mode closure unless the output is written `to` a closure table, or with
+    /// the options which work on the indexed tree.
+    pub fn require_closure_output(&self, to: &Format) -> error::Result<()> {
+        if self.dag_mode != DagMode::Closure {
+            return Ok(());
+        }
+        if !matches!(to, Format::Closure) {
+            Err(error::Error::UsageError(
+                "--dag-mode closure needs --to closure".to_owned(),
+            ))?
+        }
+
+        let conflicts = [
+            ("--counts", self.counts.is_some()),
+            ("--prune-zero", self.prune_zero),
+            ("--aggregate", !self.aggregate.is_empty()),
+            ("--top-children", self.top_children.is_some()),
+            ("--max-depth", self.max_depth.is_some()),
+            ("--dup-mapping", self.dup_mapping.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, used)| *used) {
+            Err(error::Error::UsageError(format!(
+                "{} can not be used with --dag-mode closure",
+                flag
+            )))?
+        }
+
+        Ok(())
+    }
+
+    pub fn numbering_options(&self) -> NumberingOptions {
+        NumberingOptions {
+            gap: self.gap,
+            lft_start: self.lft_start,
+            pid_offset: self.pid_offset,
+            pid_from_id: self.pid_from_id,
+        }
+    }
+
+    pub fn dag_options(&self) -> DagOptions {
+        DagOptions {
+            separator: self.separator.to_owned(),
+            naming: self.dup_naming,
+        }
+    }
+
+    pub fn complement_options(&self) -> ComplementOptions {
+        ComplementOptions {
+            prefix: self.complement_prefix.to_owned(),
+            separator: self.separator.to_owned(),
+            leaf_label: self.complement_label.to_owned(),
+            keep_id: self.complement_keep_id.to_owned(),
+            internal_only: self.complement_internal_only,
+        }
+    }
+
+    /// Returns the options of --with-path, or `None` if paths are not requested.
+    pub fn path_options(&self) -> Option<PathOptions> {
+        if !self.with_path && !self.ltree_path {
+            return None;
+        }
+
+        Some(PathOptions {
+            segment: self.path_segment.to_owned(),
+            separator: self.input.path_separator.to_owned(),
+            ltree: self.ltree_path,
+        })
+    }
+
+    pub fn write_options(&self) -> WriteOptions {
+        let mut optional = Vec::new();
+        if self.with_depth {
+            optional.push("depth".to_owned());
+        }
+        if self.with_counts {
+            optional.push("descendant_count".to_owned());
+            optional.push("leaf_count".to_owned());
+        }
+
+        self.output.write_options(optional)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ValidateOptions {
+    #[structopt(flatten)]
+    pub input: InputOptions,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct VerifyOptions {
+    /// Accept numbers with free ones between them, such as of --gap or --lft-start, instead of
+    /// expecting the numbers from 1 to 2n
+    #[structopt(long)]
+    pub allow_gaps: bool,
+
+    #[structopt(flatten)]
+    pub input: InputOptions,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RepairOptions {
     /// No output messages
     #[structopt(short, long)]
     pub quiet: bool,
 
-    /// File to process (default: stdin)
+    #[structopt(flatten)]
+    pub input: InputOptions,
+
+    #[structopt(flatten)]
+    pub output: OutputOptions,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(group = ArgGroup::with_name("relation").required(true))]
+pub struct QueryOptions {
+    /// Id of the node to query
+    #[structopt(long)]
+    pub node: String,
+
+    /// Output the ancestors of the node from the root
+    #[structopt(long, group = "relation")]
+    pub ancestors: bool,
+
+    /// Output the descendants of the node
+    #[structopt(long, group = "relation")]
+    pub descendants: bool,
+
+    /// Output the node and its descendants
+    #[structopt(long, group = "relation")]
+    pub subtree: bool,
+
+    #[structopt(flatten)]
+    pub index: IndexOptions,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct LcaOptions {
+    /// Comma-separated ids of two or more nodes
+    #[structopt(long, require_delimiter = true, min_values = 2, required = true)]
+    pub nodes: Vec<String>,
+
+    #[structopt(flatten)]
+    pub index: IndexOptions,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct DiffOptions {
+    /// Old file or database URL to compare the input with
     #[structopt(parse(from_os_str))]
-    pub input: Option<PathBuf>,
+    pub old: PathBuf,
+
+    #[structopt(flatten)]
+    pub input: InputOptions,
 }
 
-impl Options {
-    pub fn format_from_input(&self) -> Option<Format> {
-        if let Some(input) = self.input.as_ref() {
-            if let Some(ext) = input.extension() {
-                if let Some(str) = ext.to_str() {
-                    return Format::from_str(str).ok();
-                }
-            }
+#[derive(Debug, StructOpt)]
+pub struct PatchOptions {
+    /// JSON array or JSON Lines of operations such as `{"op": "move", "id": "3", "parent": "1"}`
+    #[structopt(long, parse(from_os_str))]
+    pub changes: PathBuf,
+
+    /// No output messages
+    #[structopt(short, long)]
+    pub quiet: bool,
+
+    #[structopt(flatten)]
+    pub input: InputOptions,
+
+    #[structopt(flatten)]
+    pub output: OutputOptions,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct InsertOptions {
+    /// Id of the new node
+    #[structopt(long)]
+    pub id: String,
+
+    /// Id of the parent, under which the node is added as the last child
+    #[structopt(long)]
+    pub parent: String,
+
+    /// Label of the new node (default: the id)
+    #[structopt(long)]
+    pub label: Option<String>,
+
+    /// No output messages
+    #[structopt(short, long)]
+    pub quiet: bool,
+
+    #[structopt(flatten)]
+    pub input: InputOptions,
+
+    #[structopt(flatten)]
+    pub output: OutputOptions,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct MoveOptions {
+    /// Id of the node to move with its descendants
+    #[structopt(long)]
+    pub node: String,
+
+    /// Id of the parent, under which the node is moved as the last child
+    #[structopt(long)]
+    pub new_parent: String,
+
+    /// No output messages
+    #[structopt(short, long)]
+    pub quiet: bool,
+
+    #[structopt(flatten)]
+    pub input: InputOptions,
+
+    #[structopt(flatten)]
+    pub output: OutputOptions,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct DeleteOptions {
+    /// Id of the node to delete with its descendants
+    #[structopt(long)]
+    pub node: String,
+
+    /// Delete only the node, attaching its children to its parent
+    #[structopt(long)]
+    pub promote_children: bool,
+
+    /// No output messages
+    #[structopt(short, long)]
+    pub quiet: bool,
+
+    #[structopt(flatten)]
+    pub input: InputOptions,
+
+    #[structopt(flatten)]
+    pub output: OutputOptions,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct MergeOptions {
+    /// What to do with a node which appears in more than one input with a different parent or
+    /// label
+    #[structopt(long, default_value = "error", possible_values = ConflictPolicy::VARIANTS)]
+    pub on_conflict: ConflictPolicy,
+
+    #[structopt(flatten)]
+    pub index: IndexOptions,
+
+    /// More files to merge with the input
+    #[structopt(parse(from_os_str))]
+    pub inputs: Vec<PathBuf>,
+}
+
+/// How the subtrees of the split command are numbered.
+#[derive(Debug, Clone, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum Numbering {
+    /// Renumber each subtree from the lft of the root, 1 unless --gap or --lft-start is given.
+    Local,
+    /// Keep the numbering of the whole hierarchy.
+    Global,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SplitOptions {
+    /// Directory to write the subtrees to, named by the id of their root such as `2.csv`
+    #[structopt(long, parse(from_os_str), conflicts_with = "output")]
+    pub output_dir: PathBuf,
+
+    /// Whether lft/rgt of each subtree start from 1 or keep the numbering of the whole hierarchy
+    #[structopt(long, default_value = "global", possible_values = Numbering::VARIANTS)]
+    pub numbering: Numbering,
+
+    #[structopt(flatten)]
+    pub index: IndexOptions,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct GenerateOptions {
+    /// Number of nodes, including the root
+    #[structopt(long, default_value = "1000")]
+    pub nodes: usize,
+
+    /// Maximum depth of the nodes, the root being at depth 0 (default: unlimited)
+    #[structopt(long)]
+    pub max_depth: Option<usize>,
+
+    /// Maximum number of children of a node (default: unlimited)
+    #[structopt(long)]
+    pub max_children: Option<usize>,
+
+    /// Probability of a node to get a second parent, making a directed acyclic graph
+    #[structopt(long, default_value = "0", parse(try_from_str = parse_probability))]
+    pub dag_probability: f64,
+
+    /// Seed of the random numbers to generate the same hierarchy again (default: random)
+    #[structopt(long)]
+    pub seed: Option<u64>,
+
+    /// Assign lft/rgt to the generated nodes, converting a directed acyclic graph to a tree
+    #[structopt(long)]
+    pub index: bool,
+
+    /// No output messages
+    #[structopt(short, long)]
+    pub quiet: bool,
+
+    #[structopt(flatten)]
+    pub output: OutputOptions,
+}
+
+impl GenerateOptions {
+    pub fn shape(&self) -> Shape {
+        Shape {
+            nodes: self.nodes,
+            max_depth: self.max_depth,
+            max_children: self.max_children,
+            extra_parent: self.dag_probability,
         }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[cfg_attr(not(feature = "serve"), allow(dead_code))]
+pub struct ServeOptions {
+    /// Address to listen on
+    #[structopt(long, default_value = "127.0.0.1:8080")]
+    pub address: String,
+
+    #[structopt(flatten)]
+    pub index: IndexOptions,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CompletionsOptions {
+    /// Shell to complete the commands and options in
+    #[structopt(possible_values = &Shell::variants())]
+    pub shell: Shell,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cli, Command, ErrorFormat};
+    use nested_set_indexer::format::Format;
+    use std::ffi::OsString;
+    use structopt::clap;
+
+    fn to_args(args: &[&str]) -> Vec<OsString> {
+        let args = ["nested_set_indexer"].iter().chain(args);
+        args.map(OsString::from).collect()
+    }
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::from_command_line(to_args(args)).unwrap()
+    }
+
+    #[test]
+    fn test_global_options_before_command() {
+        let cli = parse(&[
+            "-vv",
+            "--error-format",
+            "json",
+            "--timings",
+            "validate",
+            "t.csv",
+        ]);
+        assert!(matches!(cli.command, Command::Validate(_)));
+        assert_eq!(cli.verbose, 2);
+        assert!(matches!(cli.error_format, ErrorFormat::Json));
+        assert!(cli.timings);
+
+        let cli = parse(&["--log-format=json", "-v", "t.csv"]);
+        assert!(matches!(cli.command, Command::Index(_)));
+        assert_eq!(cli.verbose, 1);
+
+        // an option of index ends the global options
+        let cli = parse(&["-v", "--to", "json", "t.csv"]);
+        assert!(matches!(cli.command, Command::Index(_)));
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        for args in [
+            &["--bogus", "t.csv"][..],
+            &["--to", "bogus", "t.csv"],
+            &["lca", "t.csv"],
+            &["query", "t.csv"],
+        ] {
+            let e = Cli::from_command_line(to_args(args)).unwrap_err();
+            assert!(
+                !matches!(e.kind, clap::ErrorKind::HelpDisplayed),
+                "{:?}",
+                args
+            );
+        }
+
+        let args = to_args(&["--error-format", "json", "--bogus", "--error-format=text"]);
+        assert!(matches!(Cli::error_format_of(&args), ErrorFormat::Text));
+        let args = to_args(&["validate", "--bogus", "--error-format", "json"]);
+        assert!(matches!(Cli::error_format_of(&args), ErrorFormat::Json));
+    }
 
-        None
+    #[test]
+    fn test_output_format() {
+        let format = |args: &[&str]| match parse(args).command {
+            Command::Index(options) => options.output.format(&Format::Tsv),
+            _ => unreachable!(),
+        };
+        assert!(matches!(format(&["-o", "out.json"]), Format::Json));
+        assert!(matches!(format(&["-o", "out.jsonl.gz"]), Format::Jsonl));
+        assert!(matches!(format(&["-o", "out.unknown"]), Format::Tsv));
+        assert!(matches!(format(&["-o", "out"]), Format::Tsv));
+        // --to takes precedence over the extension
+        assert!(matches!(
+            format(&["-o", "out.json", "--to", "csv"]),
+            Format::Csv
+        ));
     }
 }
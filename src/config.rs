@@ -0,0 +1,233 @@
+//! Default options read from a TOML configuration file.
+//!
+//! The keys are the long options without `--`, such as `id-column = "code"` or
+//! `complement-leaf = true`, and a table named after a command such as `[query]` applies only to
+//! that command on top of the keys outside tables. The options of the command line take
+//! precedence over the file, and `--no-<key>` such as `--no-complement-leaf` keeps the file from
+//! giving an option, turning off a flag set to `true`.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Name of the configuration file read from the current directory unless `--config` is given.
+pub const DEFAULT_PATH: &str = "nested_set_indexer.toml";
+
+/// Removes `--config <path>` from `args`, returning the path of the configuration file to read,
+/// if any.
+pub fn take_path(args: &mut Vec<OsString>) -> Result<Option<PathBuf>, String> {
+    let mut path = None;
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].to_string_lossy();
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            args.remove(i);
+            if i >= args.len() {
+                return Err("missing value for --config".to_owned());
+            }
+            path = Some(PathBuf::from(args.remove(i)));
+        } else if let Some(value) = arg.strip_prefix("--config=") {
+            path = Some(PathBuf::from(value));
+            args.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+
+    if path.is_none() && Path::new(DEFAULT_PATH).is_file() {
+        path = Some(PathBuf::from(DEFAULT_PATH));
+    }
+    Ok(path)
+}
+
+/// Removes the arguments `--no-<key>` of the options `key` which `accepts` tells the command has,
+/// returning the keys so that the configuration file does not give them. An option which is named
+/// `no-...` itself, such as `--no-index`, is kept.
+pub fn take_negations<F: Fn(&str) -> bool>(args: &mut Vec<OsString>, accepts: F) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].to_string_lossy();
+        if arg == "--" {
+            break;
+        }
+        match arg.strip_prefix("--no-") {
+            Some(key) if !key.contains('=') && !accepts(&arg[2..]) && accepts(key) => {
+                keys.push(key.to_owned());
+                args.remove(i);
+            }
+            _ => i += 1,
+        }
+    }
+    keys
+}
+
+/// Reads the configuration file at `path`.
+pub fn read(path: &Path) -> Result<Table, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    text.parse::<Table>()
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Converts a value to the arguments of the option `key`, repeating it for arrays.
+fn option_args(key: &str, value: &Value) -> Result<Vec<OsString>, String> {
+    // `--key=value` so that an option of multiple values does not take the next arguments
+    let option = |value: &str| OsString::from(format!("--{}={}", key, value));
+
+    match value {
+        Value::Boolean(true) => Ok(vec![OsString::from(format!("--{}", key))]),
+        Value::Boolean(false) => Ok(Vec::new()),
+        Value::String(x) => Ok(vec![option(x)]),
+        Value::Integer(_) | Value::Float(_) => Ok(vec![option(&value.to_string())]),
+        Value::Array(values) => {
+            let mut args = Vec::new();
+            for x in values {
+                args.extend(option_args(key, x)?);
+            }
+            Ok(args)
+        }
+        _ => Err(format!("unsupported value of {}: {}", key, value)),
+    }
+}
+
+/// Returns the long options of `args` without `--`.
+pub fn long_options(args: &[OsString]) -> Vec<String> {
+    args.iter()
+        .filter_map(|x| x.to_str()?.strip_prefix("--"))
+        .take_while(|x| !x.is_empty())
+        .map(|x| x.split('=').next().unwrap_or(x).to_owned())
+        .collect()
+}
+
+/// Returns the arguments given by `config` to `command` in the order of the file, except the
+/// options in `given`.
+///
+/// `accepts` tells whether `command` has an option. Keys outside tables which only the other
+/// commands have are skipped, and keys which no command has are an error.
+pub fn args<F: Fn(&str, &str) -> bool>(
+    config: &Table,
+    command: &str,
+    commands: &[&str],
+    given: &[String],
+    accepts: F,
+) -> Result<Vec<OsString>, String> {
+    let mut common = Vec::new();
+    for (key, value) in config {
+        if let Value::Table(_) = value {
+            if !commands.contains(&key.as_str()) {
+                return Err(format!("unknown command in the configuration: {}", key));
+            }
+        } else if accepts(command, key) {
+            common.push((key, value));
+        } else if !commands.iter().any(|x| accepts(x, key)) {
+            return Err(format!("unknown option in the configuration: {}", key));
+        }
+    }
+
+    let mut own = Vec::new();
+    if let Some(Value::Table(table)) = config.get(command) {
+        for (key, value) in table {
+            if !accepts(command, key) {
+                return Err(format!(
+                    "unknown option of {} in the configuration: {}",
+                    command, key
+                ));
+            }
+            own.push((key, value));
+        }
+    }
+
+    let mut args = Vec::new();
+    for (key, value) in &common {
+        if !own.iter().any(|(k, _)| k == key) && !given.contains(key) {
+            args.extend(option_args(key, value)?);
+        }
+    }
+    for (key, value) in own.iter().filter(|(k, _)| !given.contains(k)) {
+        args.extend(option_args(key, value)?);
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{args, long_options, take_negations};
+    use std::ffi::OsString;
+    use toml::Table;
+
+    #[test]
+    fn test_args() {
+        let config: Table = r#"
+            id-column = "code"
+            complement-leaf = true
+            with-depth = false
+            out-columns = ["lft", "rgt"]
+
+            [query]
+            id-column = "key"
+            "#
+        .parse()
+        .unwrap();
+        let commands = ["index", "validate", "query"];
+        let accepts = |command: &str, key: &str| match key {
+            "id-column" => true,
+            "complement-leaf" | "with-depth" | "out-columns" => command != "validate",
+            _ => false,
+        };
+
+        let to_args = |x: &[&str]| x.iter().map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            args(&config, "index", &commands, &[], accepts).unwrap(),
+            to_args(&[
+                "--id-column=code",
+                "--complement-leaf",
+                "--out-columns=lft",
+                "--out-columns=rgt"
+            ])
+        );
+        assert_eq!(
+            args(&config, "validate", &commands, &[], accepts).unwrap(),
+            to_args(&["--id-column=code"])
+        );
+        assert_eq!(
+            args(&config, "query", &commands, &[], accepts).unwrap(),
+            to_args(&[
+                "--complement-leaf",
+                "--out-columns=lft",
+                "--out-columns=rgt",
+                "--id-column=key"
+            ])
+        );
+
+        let given = long_options(&to_args(&[
+            "input.csv",
+            "--out-columns=id",
+            "--id-column",
+            "x",
+        ]));
+        assert_eq!(given, vec!["out-columns", "id-column"]);
+        assert_eq!(
+            args(&config, "query", &commands, &given, accepts).unwrap(),
+            to_args(&["--complement-leaf"])
+        );
+
+        // `--no-complement-leaf` is not an option but turns off the one of the file
+        let mut input = to_args(&["input.csv", "--no-complement-leaf", "--no-index"]);
+        let given = take_negations(&mut input, |key| {
+            matches!(key, "complement-leaf" | "index" | "no-index")
+        });
+        assert_eq!(input, to_args(&["input.csv", "--no-index"]));
+        assert_eq!(
+            args(&config, "index", &commands, &given, accepts).unwrap(),
+            to_args(&["--id-column=code", "--out-columns=lft", "--out-columns=rgt"])
+        );
+
+        let config: Table = "unknown = 1".parse().unwrap();
+        assert!(args(&config, "index", &commands, &[], accepts).is_err());
+    }
+}
@@ -1,312 +1,2093 @@
 use crate::error;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet, VecDeque};
-
-const SEPARATOR: &'static str = "__";
-
-fn default_if_empty<'de, D, T>(de: D) -> error::Result<T, D::Error>
-where
-    D: serde::Deserializer<'de>,
-    T: serde::Deserialize<'de> + Default,
-{
-    Option::<T>::deserialize(de).map(|x| x.unwrap_or_else(|| T::default()))
+use std::iter::Peekable;
+use std::sync::Arc;
+use std::{fmt, mem, vec};
+use strum::{EnumString, EnumVariantNames};
+
+/// Names of the input columns deserialized into the fields of [`Node`]. The others are kept in
+/// [`Node::extra`].
+pub(crate) const INPUT_FIELDS: &[&str] = &[
+    "pid",
+    "id",
+    "origin",
+    "source_id",
+    "label",
+    "parent",
+    "parent_id",
+    "leaf",
+    "lft",
+    "rgt",
+    "count",
+];
+
+/// Names of the output columns serialized from the fields of [`Node`], in their order. The extra
+/// columns follow them.
+pub(crate) const OUTPUT_FIELDS: &[&str] = &[
+    "id",
+    "classification",
+    "classification_origin",
+    "classification_label",
+    "classification_parent",
+    "parent_id",
+    "leaf",
+    "lft",
+    "rgt",
+    "count",
+    "depth",
+    "descendant_count",
+    "leaf_count",
+    "path",
+    "label_path",
+    "hash",
+    "synthetic",
+    "source_id",
+];
+
+/// Name of the input column of the primary parent of a node used by
+/// [`Graph::primary_parent_tree`].
+pub const PRIMARY_PARENT: &str = "primary_parent";
+
+/// Writes a leaf flag which is not given in the input as `false`.
+fn serialize_leaf<S: serde::Serializer>(leaf: &Option<bool>, se: S) -> Result<S::Ok, S::Error> {
+    se.serialize_bool(leaf.unwrap_or_default())
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A node of the hierarchy.
+///
+/// `id` and `parent` identify the node and its parent in the input, while `pid`, `parent_id`, `lft`,
+/// `rgt`, `count`, `depth`, `descendant_count` and `leaf_count` are assigned by
+/// [`Graph::build_index`].
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Node {
     #[serde(rename(serialize = "id"))]
     pid: Option<usize>,
     #[serde(rename(serialize = "classification", deserialize = "id"))]
-    node: String,
+    node: Arc<str>,
     #[serde(rename(serialize = "classification_origin"))]
-    origin: Option<String>,
+    origin: Option<Arc<str>>,
     #[serde(rename(serialize = "classification_label"))]
-    label: String,
+    label: Arc<str>,
     #[serde(rename(serialize = "classification_parent", deserialize = "parent"))]
-    parent_node: Option<String>,
+    parent_node: Option<Arc<str>>,
     #[serde(rename(serialize = "parent_id"))]
     parent_id: Option<usize>,
-    #[serde(default, deserialize_with = "default_if_empty")]
-    leaf: bool,
+    #[serde(default, serialize_with = "serialize_leaf")]
+    leaf: Option<bool>,
     lft: Option<usize>,
     rgt: Option<usize>,
     count: Option<usize>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    depth: Option<usize>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    descendant_count: Option<usize>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    leaf_count: Option<usize>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    label_path: Option<String>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    synthetic: Option<bool>,
+    #[serde(rename = "source_id")]
+    source: Option<Arc<str>>,
+    #[serde(skip)]
+    extra: Arc<Map<String, Value>>,
+}
+
+impl Node {
+    /// Creates a root node. Use [`Node::with_parent`] to attach it to a parent.
+    pub fn new<S: Into<String>, T: Into<String>>(id: S, label: T) -> Self {
+        Node {
+            node: id.into().into(),
+            label: label.into().into(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the id of the parent node.
+    pub fn with_parent<S: Into<String>>(mut self, parent: S) -> Self {
+        self.parent_node = Some(parent.into().into());
+        self
+    }
+
+    /// Marks the node as a leaf. Leaf nodes can not be a parent of other nodes.
+    pub fn with_leaf(mut self, leaf: bool) -> Self {
+        self.leaf = Some(leaf);
+        self
+    }
+
+    /// Adds a column which is passed through to the output.
+    pub fn with_extra<S: Into<String>, V: Into<Value>>(mut self, column: S, value: V) -> Self {
+        Arc::make_mut(&mut self.extra).insert(column.into(), value.into());
+        self
+    }
+
+    /// Sequential id assigned by [`Graph::build_index`].
+    pub fn pid(&self) -> Option<usize> {
+        self.pid
+    }
+
+    /// Id of the node. Nodes duplicated by [`Graph::dag_to_tree`] have a suffixed id.
+    pub fn id(&self) -> &str {
+        &self.node
+    }
+
+    /// Original id of a node duplicated by [`Graph::dag_to_tree`].
+    pub fn origin(&self) -> Option<&str> {
+        self.origin.as_deref()
+    }
+
+    /// Id of the input row the node comes from, which is kept by the copies of a directed acyclic
+    /// graph and the classification nodes of [`Graph::complement_leaf`].
+    pub fn source_id(&self) -> &str {
+        self.source
+            .as_deref()
+            .or(self.origin.as_deref())
+            .unwrap_or(&self.node)
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Id of the parent node, `None` for the root.
+    pub fn parent(&self) -> Option<&str> {
+        self.parent_node.as_deref()
+    }
+
+    /// `pid` of the parent node assigned by [`Graph::build_index`].
+    pub fn parent_id(&self) -> Option<usize> {
+        self.parent_id
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.leaf.unwrap_or_default()
+    }
+
+    /// Whether the node is marked as a leaf or not in the input, `None` if it is not given.
+    pub fn leaf(&self) -> Option<bool> {
+        self.leaf
+    }
+
+    pub fn lft(&self) -> Option<usize> {
+        self.lft
+    }
+
+    pub fn rgt(&self) -> Option<usize> {
+        self.rgt
+    }
+
+    /// Number of the direct children.
+    pub fn count(&self) -> Option<usize> {
+        self.count
+    }
+
+    /// Distance from the root node, which is at depth 0.
+    pub fn depth(&self) -> Option<usize> {
+        self.depth
+    }
+
+    /// Number of all the descendants, i.e. `(rgt - lft - 1) / 2`.
+    pub fn descendant_count(&self) -> Option<usize> {
+        self.descendant_count
+    }
+
+    /// Number of the descendants without children.
+    pub fn leaf_count(&self) -> Option<usize> {
+        self.leaf_count
+    }
+
+    /// Materialized path assigned by [`Graph::build_paths`].
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Path of the labels from the root assigned by [`Graph::build_label_paths`].
+    pub fn label_path(&self) -> Option<&str> {
+        self.label_path.as_deref()
+    }
+
+    /// Hash of the subtree assigned by [`Graph::build_hashes`].
+    pub fn hash(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
+
+    /// Whether the node was created for a parent missing in the input, set only when stubs are
+    /// created by [`crate::resolve::missing_parents`].
+    pub fn synthetic(&self) -> Option<bool> {
+        self.synthetic
+    }
+
+    /// Input columns other than the fields of the node, in the input order.
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
+    }
+
+    /// Whether the output column of [`OUTPUT_FIELDS`] is serialized, the columns which are
+    /// skipped when they are not set being left out.
+    pub(crate) fn has_output_field(&self, column: &str) -> bool {
+        match column {
+            "depth" => self.depth.is_some(),
+            "descendant_count" => self.descendant_count.is_some(),
+            "leaf_count" => self.leaf_count.is_some(),
+            "path" => self.path.is_some(),
+            "label_path" => self.label_path.is_some(),
+            "hash" => self.hash.is_some(),
+            "synthetic" => self.synthetic.is_some(),
+            _ => OUTPUT_FIELDS.contains(&column),
+        }
+    }
+
+    /// Replaces the id, keeping the former one as [`Node::source_id`].
+    pub(crate) fn set_id(&mut self, id: String) {
+        if self.source.is_none() {
+            self.source = Some(Arc::clone(&self.node));
+        }
+        self.node = id.into();
+    }
+
+    pub(crate) fn set_parent(&mut self, parent: Option<String>) {
+        self.parent_node = parent.map(Arc::from);
+    }
+
+    pub(crate) fn set_leaf(&mut self, leaf: bool) {
+        self.leaf = Some(leaf);
+    }
+
+    pub(crate) fn set_synthetic(&mut self, synthetic: bool) {
+        self.synthetic = Some(synthetic);
+    }
+
+    pub(crate) fn set_label(&mut self, label: &str) {
+        self.label = label.into();
+    }
+
+    /// Returns the value of an extra column as a string, or `None` if it is empty.
+    fn extra_str(&self, column: &str) -> Option<String> {
+        match self.extra.get(column)? {
+            Value::String(x) if x.is_empty() => None,
+            Value::String(x) => Some(x.to_owned()),
+            Value::Null => None,
+            x => Some(x.to_string()),
+        }
+    }
+
+    pub(crate) fn extra_mut(&mut self) -> &mut Map<String, Value> {
+        Arc::make_mut(&mut self.extra)
+    }
+}
+
+/// Fields of a [`Node`] borrowed from an input record, so that a record is checked without
+/// copying its fields and only converted to an owned node once it is kept.
+#[derive(Debug, Deserialize)]
+pub(crate) struct BorrowedNode<'a> {
+    pid: Option<usize>,
+    #[serde(borrow)]
+    id: &'a str,
+    #[serde(borrow)]
+    origin: Option<&'a str>,
+    #[serde(borrow)]
+    source_id: Option<&'a str>,
+    #[serde(borrow)]
+    label: &'a str,
+    #[serde(borrow)]
+    parent: Option<&'a str>,
+    parent_id: Option<usize>,
+    leaf: Option<bool>,
+    lft: Option<usize>,
+    rgt: Option<usize>,
+    count: Option<usize>,
+}
+
+impl From<BorrowedNode<'_>> for Node {
+    fn from(x: BorrowedNode<'_>) -> Self {
+        Node {
+            pid: x.pid,
+            node: x.id.into(),
+            origin: x.origin.map(Arc::from),
+            source: x.source_id.map(Arc::from),
+            label: x.label.into(),
+            parent_node: x.parent.map(Arc::from),
+            parent_id: x.parent_id,
+            leaf: x.leaf,
+            lft: x.lft,
+            rgt: x.rgt,
+            count: x.count,
+            ..Default::default()
+        }
+    }
+}
+
+/// Names of the input columns deserialized into the fields of [`Node`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMapping {
+    pub id: String,
+    pub parent: String,
+    pub label: String,
+    /// Column of the `pid` of already indexed inputs.
+    pub pid: String,
+    /// Column of the original id of a node duplicated by [`Graph::dag_to_tree`].
+    pub origin: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            id: "id".to_owned(),
+            parent: "parent".to_owned(),
+            label: "label".to_owned(),
+            pid: "pid".to_owned(),
+            origin: "origin".to_owned(),
+        }
+    }
+}
+
+impl ColumnMapping {
+    pub fn is_default(&self) -> bool {
+        *self == ColumnMapping::default()
+    }
+
+    /// Returns the name to deserialize the input column `column` as.
+    ///
+    /// Columns which are not mapped but have the name of a mapped field are renamed to an empty
+    /// string so that they are ignored.
+    pub fn field_name<'a>(&self, column: &'a str) -> &'a str {
+        if column == self.id {
+            "id"
+        } else if column == self.parent {
+            "parent"
+        } else if column == self.label {
+            "label"
+        } else if column == self.pid {
+            "pid"
+        } else if column == self.origin {
+            "origin"
+        } else if ["id", "parent", "label", "pid", "origin"].contains(&column) {
+            ""
+        } else {
+            column
+        }
+    }
+
+    /// Mapping of the columns of the default output, which reads an indexed table back with its
+    /// `pid` from the `id` column.
+    pub fn output() -> Self {
+        ColumnMapping {
+            id: "classification".to_owned(),
+            parent: "classification_parent".to_owned(),
+            label: "classification_label".to_owned(),
+            pid: "id".to_owned(),
+            origin: "classification_origin".to_owned(),
+        }
+    }
+
+    /// Returns [`ColumnMapping::output`] for the columns of the default output if the mapping is
+    /// the default one and the input has no `parent` or `label` column, or the mapping otherwise.
+    pub fn detect<'a, I: IntoIterator<Item = &'a str>>(&self, columns: I) -> Cow<'_, Self> {
+        if !self.is_default() {
+            return Cow::Borrowed(self);
+        }
+
+        let mut found = [false; 3];
+        for column in columns {
+            match column {
+                "parent" | "label" => return Cow::Borrowed(self),
+                "classification" => found[0] = true,
+                "classification_parent" => found[1] = true,
+                "classification_label" => found[2] = true,
+                _ => (),
+            }
+        }
+        match found {
+            [true, true, true] => Cow::Owned(ColumnMapping::output()),
+            _ => Cow::Borrowed(self),
+        }
+    }
+}
+
+/// What the segments of a materialized path are made of.
+#[derive(Debug, Clone, Default, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum PathSegment {
+    #[default]
+    Id,
+    Label,
+}
+
+/// Options for [`Graph::build_paths`].
+#[derive(Debug, Clone)]
+pub struct PathOptions {
+    pub segment: PathSegment,
+    pub separator: String,
+    /// Make the path a valid PostgreSQL `ltree`, delimited by `.` with the characters other than
+    /// alphanumerics and `_` replaced with `_`. `separator` is ignored.
+    pub ltree: bool,
+}
+
+impl Default for PathOptions {
+    fn default() -> Self {
+        PathOptions {
+            segment: PathSegment::default(),
+            separator: "/".to_owned(),
+            ltree: false,
+        }
+    }
+}
+
+/// Nested set values of a node counted from the start of the traversal of its subtree.
+#[derive(Debug, Clone, Copy, Default)]
+struct Visit {
+    lft: usize,
+    rgt: usize,
+    depth: usize,
+    count: usize,
+    leaf_count: usize,
+    parent_id: Option<usize>,
 }
 
+impl Visit {
+    /// Number of the leaves of the node, counting itself if it has no children.
+    fn leaves(&self) -> usize {
+        if self.count == 0 {
+            1
+        } else {
+            self.leaf_count
+        }
+    }
+}
+
+/// Nodes numbered by [`Graph::into_indexed`], yielded in the order of `pid` as soon as the
+/// traversal has entered them.
+pub struct IndexedNodes {
+    options: NumberingOptions,
+    /// Nodes in the order of the rows, taken out as they are yielded.
+    nodes: Vec<Node>,
+    symbols: Symbols,
+    child_map: Children,
+    /// `parent_id` of every row below the root.
+    parent_ids: Vec<Option<usize>>,
+    /// Number of the nodes of the subtree of every row, including itself.
+    sizes: Vec<usize>,
+    leaf_counts: Vec<usize>,
+    /// Rows to enter with the position of their `lft` in the traversal and their depth.
+    stack: Vec<(usize, usize, usize)>,
+    entered: Vec<bool>,
+    /// Rows left to yield in the order of `pid`.
+    pids: Peekable<vec::IntoIter<usize>>,
+}
+
+impl IndexedNodes {
+    /// Whether the output column of [`OUTPUT_FIELDS`] is serialized for any of the nodes once
+    /// they are numbered.
+    pub(crate) fn has_output_field(&self, column: &str) -> bool {
+        matches!(column, "depth" | "descendant_count" | "leaf_count")
+            || self.nodes.iter().any(|x| x.has_output_field(column))
+    }
+
+    /// Nodes which have not been yielded yet.
+    pub(crate) fn pending(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Assigns the values of the next node of the traversal.
+    fn enter(&mut self, i: usize, n: usize, depth: usize) {
+        let children = self.child_map.of(self.symbols.ids[i]);
+        let size = self.sizes[i];
+
+        let node = &mut self.nodes[i];
+        node.lft = Some(self.options.value(n));
+        node.rgt = Some(self.options.value(n + size * 2 - 1));
+        node.count = Some(children.len());
+        node.depth = Some(depth);
+        node.descendant_count = Some(size - 1);
+        node.leaf_count = Some(self.leaf_counts[i]);
+        node.parent_id = self.parent_ids[i];
+        self.entered[i] = true;
+
+        // the subtrees of the children take consecutive ranges after the lft of their parent
+        let top = self.stack.len();
+        let mut next = n + 1;
+        for child in children {
+            self.stack.push((*child, next, depth + 1));
+            next += self.sizes[*child] * 2;
+        }
+        self.stack[top..].reverse();
+    }
+}
+
+impl Iterator for IndexedNodes {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Node> {
+        loop {
+            let i = *self.pids.peek()?;
+            // the nodes which are not below the root are yielded without values at the end
+            if self.entered[i] || self.stack.is_empty() {
+                self.pids.next();
+                return Some(mem::take(&mut self.nodes[i]));
+            }
+            if let Some((i, n, depth)) = self.stack.pop() {
+                self.enter(i, n, depth);
+            }
+        }
+    }
+}
+
+/// How [`Graph::sort_children`] orders the children of every node.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum SiblingOrder {
+    /// The order of the input rows.
+    #[default]
+    Input,
+    Id,
+    Label,
+    /// The values of an extra input column, the nodes without a value coming last.
+    Column(String),
+}
+
+/// Names of the input columns of the position of a node among its siblings, which order the
+/// children unless another order is given.
+pub const POSITION_COLUMNS: &[&str] = &["position", "sort_order"];
+
+/// Compares numbers numerically and other values as strings, `None` coming last.
+fn compare_keys(a: Option<&str>, b: Option<&str>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => match (a.parse::<f64>(), b.parse::<f64>()) {
+            (Ok(x), Ok(y)) => x.total_cmp(&y),
+            _ => a.cmp(b),
+        },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Options for [`Graph::build_index_with`].
+#[derive(Debug, Clone)]
+pub struct NumberingOptions {
+    /// Step between the consecutive `lft` and `rgt` values, leaving room for inserting nodes
+    /// without renumbering the others.
+    pub gap: usize,
+    /// First `lft`, `gap` if `None`.
+    pub lft_start: Option<usize>,
+    /// Added to every `pid` and `parent_id`, which start from `pid_offset + 1`.
+    pub pid_offset: usize,
+    /// Uses the ids, which must be unique integers, as `pid` instead of the positions.
+    pub pid_from_id: bool,
+}
+
+impl Default for NumberingOptions {
+    fn default() -> Self {
+        NumberingOptions {
+            gap: 1,
+            lft_start: None,
+            pid_offset: 0,
+            pid_from_id: false,
+        }
+    }
+}
+
+impl NumberingOptions {
+    /// Returns the n-th `lft`/`rgt` value of a traversal, counted from 1.
+    fn value(&self, n: usize) -> usize {
+        let gap = self.gap.max(1);
+        self.lft_start.unwrap_or(gap) + (n - 1) * gap
+    }
+}
+
+/// Options for [`Graph::dag_to_tree_with`].
+#[derive(Debug, Clone)]
+pub struct DagOptions {
+    /// Separator between the id and the suffix of a duplicated node, such as `__` in `4__1`.
+    pub separator: String,
+    pub naming: DupNaming,
+}
+
+impl Default for DagOptions {
+    fn default() -> Self {
+        DagOptions {
+            separator: "__".to_owned(),
+            naming: DupNaming::default(),
+        }
+    }
+}
+
+/// How [`Graph::dag_to_tree_with`] names the duplicates of a node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum DupNaming {
+    /// Numbers the duplicates in the order of the traversal, the first one keeping the id.
+    #[default]
+    Counter,
+    /// Suffixes every duplicate with the id of its parent.
+    ParentSuffix,
+    /// Suffixes every duplicate with a hash of the ids of its ancestors.
+    PathHash,
+}
+
+/// Continues a 32-bit FNV-1a hash, which unlike the hasher of the standard library is stable
+/// across releases.
+fn fnv1a(hash: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(hash, |hash, &b| {
+        (hash ^ u32::from(b)).wrapping_mul(0x0100_0193)
+    })
+}
+
+const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+
+/// Continues a 64-bit FNV-1a hash, for fewer collisions among the subtrees of a large hierarchy.
+fn fnv1a64(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+const FNV64_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Which of the classification node and its leaf added by [`Graph::complement_leaf_with`] keeps
+/// the original id, the other one being given a prefixed id.
+#[derive(Debug, Clone, Default, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum KeepId {
+    #[default]
+    Leaf,
+    Classification,
+}
+
+/// Options for [`Graph::complement_leaf_with`].
+#[derive(Debug, Clone)]
+pub struct ComplementOptions {
+    /// Prefix of the generated ids, such as `c` in `c__4`.
+    pub prefix: String,
+    /// Separator between the prefix and the id.
+    pub separator: String,
+    /// Template of the label of the leaves, where `{label}` and `{id}` are replaced with the label
+    /// and the id of the node. The label is kept if `None`.
+    pub leaf_label: Option<String>,
+    pub keep_id: KeepId,
+    /// Complements only the nodes which have children, leaving the leaves as they are.
+    pub internal_only: bool,
+}
+
+impl Default for ComplementOptions {
+    fn default() -> Self {
+        ComplementOptions {
+            prefix: "c".to_owned(),
+            separator: "__".to_owned(),
+            leaf_label: None,
+            keep_id: KeepId::default(),
+            internal_only: false,
+        }
+    }
+}
+
+/// A node without a parent, reported by [`error::Error::MultipleRootNodeError`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootNode {
+    pub id: String,
+    pub label: String,
+    /// Position of the node among the nodes, starting from 1.
+    pub row: usize,
+    /// Number of the distinct nodes reachable from the node.
+    pub descendant_count: usize,
+}
+
+impl fmt::Display for RootNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) at row {} with {} descendant(s)",
+            self.id, self.label, self.row, self.descendant_count
+        )
+    }
+}
+
+/// Returns the nodes without a parent with the number of their descendants.
+fn root_nodes(nodes: &[Node]) -> Vec<RootNode> {
+    let mut children = HashMap::<&str, Vec<&str>>::new();
+    for node in nodes {
+        if let Some(parent) = &node.parent_node {
+            children.entry(parent).or_default().push(&node.node);
+        }
+    }
+
+    nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.parent_node.is_none())
+        .map(|(i, root)| {
+            let mut visited = HashSet::new();
+            let mut stack = vec![&*root.node];
+            while let Some(id) = stack.pop() {
+                if visited.insert(id) {
+                    stack.extend(children.get(id).into_iter().flatten());
+                }
+            }
+
+            RootNode {
+                id: root.node.to_string(),
+                label: root.label.to_string(),
+                row: i + 1,
+                descendant_count: visited.len() - 1,
+            }
+        })
+        .collect()
+}
+
+/// Symbols of the ids of the nodes, dense integers which the traversals index vectors with
+/// instead of maps keyed by copies of the ids.
+struct Symbols {
+    /// Symbol of the id of every row.
+    ids: Vec<u32>,
+    /// Symbol of the parent of every row.
+    parents: Vec<Option<u32>>,
+    /// Number of the distinct ids, including the parents which are not nodes.
+    count: usize,
+}
+
+impl Symbols {
+    fn new(nodes: &[Node]) -> Self {
+        let mut table =
+            FxHashMap::<&str, u32>::with_capacity_and_hasher(nodes.len(), Default::default());
+        let mut intern = |id| {
+            let next = table.len() as u32;
+            *table.entry(id).or_insert(next)
+        };
+
+        let ids = nodes.iter().map(|x| intern(&x.node)).collect();
+        let parents = nodes
+            .iter()
+            .map(|x| x.parent_node.as_deref().map(&mut intern))
+            .collect();
+
+        Symbols {
+            ids,
+            parents,
+            count: table.len(),
+        }
+    }
+
+    /// Returns the rows of the children of every symbol in the order of the rows.
+    fn children(&self) -> Children {
+        // a counting sort of the rows by the symbol of their parent
+        let mut offsets = vec![0; self.count + 1];
+        for parent in self.parents.iter().flatten() {
+            offsets[*parent as usize + 1] += 1;
+        }
+        for i in 1..offsets.len() {
+            offsets[i] += offsets[i - 1];
+        }
+
+        let mut next = offsets.to_owned();
+        let mut rows = vec![0; offsets[self.count]];
+        for (i, parent) in self.parents.iter().enumerate() {
+            if let Some(parent) = parent {
+                rows[next[*parent as usize]] = i;
+                next[*parent as usize] += 1;
+            }
+        }
+
+        Children { offsets, rows }
+    }
+}
+
+/// Rows of the children of every symbol, stored contiguously.
+struct Children {
+    offsets: Vec<usize>,
+    rows: Vec<usize>,
+}
+
+impl Children {
+    fn of(&self, symbol: u32) -> &[usize] {
+        let symbol = symbol as usize;
+        &self.rows[self.offsets[symbol]..self.offsets[symbol + 1]]
+    }
+}
+
+/// A copy of a node made by [`Graph::dag_to_tree_with`] with its generated id and its parent.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct NodeCopy {
+    pub id: String,
+    pub parent: Option<String>,
+}
+
+/// A hierarchy with a single root node.
 #[derive(Debug)]
 pub struct Graph {
+    /// Nodes of the graph, sorted by `pid` after [`Graph::build_index`].
     pub nodes: Vec<Node>,
     root: usize, // index of root node in the vector
 }
 
-impl Graph {
-    pub fn new(nodes: Vec<Node>) -> error::Result<Self> {
-        let mut root: Option<usize> = None;
+impl Graph {
+    /// Creates a graph, failing unless exactly one node has no parent.
+    pub fn new(mut nodes: Vec<Node>) -> error::Result<Self> {
+        for node in nodes.iter_mut().filter(|x| x.source.is_none()) {
+            node.source = Some(node.source_id().into());
+        }
+
+        let mut root: Option<usize> = None;
+
+        for (i, node) in nodes.iter().enumerate() {
+            if node.parent_node.is_none() {
+                if root.is_none() {
+                    root = Some(i)
+                } else {
+                    Err(error::Error::MultipleRootNodeError(root_nodes(&nodes)))?
+                }
+            }
+        }
+
+        Ok(Graph {
+            nodes,
+            root: root.ok_or(error::Error::RootNodeNotFoundError())?,
+        })
+    }
+
+    /// Creates a graph allowing multiple root nodes.
+    ///
+    /// If more than one node has no parent, `root` is added as a virtual root node and all the
+    /// root nodes are attached to it. Otherwise this is the same as [`Graph::new`].
+    pub fn from_forest(mut nodes: Vec<Node>, mut root: Node) -> error::Result<Self> {
+        if nodes.iter().filter(|x| x.parent_node.is_none()).count() <= 1 {
+            return Graph::new(nodes);
+        }
+
+        if nodes.iter().any(|x| x.node == root.node) {
+            Err(error::Error::VirtualRootConflictError(
+                root.node.to_string(),
+            ))?
+        }
+
+        for node in nodes.iter_mut() {
+            if node.parent_node.is_none() {
+                node.parent_node = Some(root.node.to_owned());
+            }
+        }
+
+        root.parent_node = None;
+        root.leaf = Some(false);
+        nodes.insert(0, root);
+
+        Graph::new(nodes)
+    }
+
+    /// Returns `true` if a non-leaf node appears more than once, i.e. it has multiple parents.
+    pub fn is_dag(&self) -> bool {
+        let mut set = FxHashSet::default();
+
+        for node in self.nodes.iter() {
+            if !node.is_leaf() && !set.insert(&*node.node) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Reorders the nodes so that the children of every node come in `order`, keeping the input
+    /// order of the ties and the root first.
+    pub fn sort_children(&mut self, order: &SiblingOrder) -> &Self {
+        let key = |x: &Node| match order {
+            SiblingOrder::Input => None,
+            SiblingOrder::Id => Some(x.node.to_string()),
+            SiblingOrder::Label => Some(x.label.to_string()),
+            SiblingOrder::Column(column) => x.extra_str(column),
+        };
+        if *order == SiblingOrder::Input {
+            return self;
+        }
+
+        let root = self.root;
+        let mut keys: Vec<_> = self
+            .nodes
+            .drain(..)
+            .enumerate()
+            .map(|(i, x)| (i != root, key(&x), x))
+            .collect();
+        keys.sort_by(|(a_root, a, _), (b_root, b, _)| {
+            a_root
+                .cmp(b_root)
+                .then_with(|| compare_keys(a.as_deref(), b.as_deref()))
+        });
+        self.nodes = keys.into_iter().map(|(_, _, x)| x).collect();
+        self.root = 0;
+
+        self
+    }
+
+    /// Sets `column` of every node to its position from 1 among its siblings in the order of
+    /// `lft`. Must be called after [`Graph::build_index`].
+    pub fn number_siblings(&mut self, column: &str) -> &Self {
+        let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+        order.sort_by_key(|i| self.nodes[*i].lft);
+
+        let mut positions = FxHashMap::default();
+        for i in order {
+            let node = &mut self.nodes[i];
+            let position = positions.entry(node.parent_id).or_insert(0);
+            *position += 1;
+            node.extra_mut()
+                .insert(column.to_owned(), Value::from(*position));
+        }
+
+        self
+    }
+
+    /// Converts a directed acyclic graph to a tree by duplicating nodes with multiple parents.
+    ///
+    /// The duplicates are given an id suffixed with `__<n>` and the original id is kept in `origin`.
+    pub fn dag_to_tree(&self) -> error::Result<Self> {
+        self.dag_to_tree_with(&DagOptions::default())
+    }
+
+    /// Converts a directed acyclic graph to a tree like [`Graph::dag_to_tree`] with `options`.
+    #[tracing::instrument(name = "dag_to_tree", level = "debug", skip_all)]
+    pub fn dag_to_tree_with(&self, options: &DagOptions) -> error::Result<Self> {
+        let symbols = Symbols::new(&self.nodes);
+        let child_map = symbols.children();
+        // a cycle below the root would be copied endlessly
+        self.check_acyclic(&symbols, &child_map)?;
+        let mut visited = vec![0_usize; symbols.count];
+
+        // the stable namings rename every occurrence of a duplicated node, so count them first
+        let mut occurrences = vec![0_usize; symbols.count];
+        if options.naming != DupNaming::Counter {
+            let mut pending = vec![self.root];
+            while let Some(orig) = pending.pop() {
+                for i in child_map.of(symbols.ids[orig]) {
+                    occurrences[symbols.ids[*i] as usize] += 1;
+                    pending.push(*i);
+                }
+            }
+        }
+
+        // the tree as index arrays over the rows of the graph: the row every copy is made from,
+        // the copy of its parent and the id given to a renamed copy, in the order of a
+        // breadth-first traversal
+        let mut rows = vec![self.root];
+        let mut parents = vec![None];
+        let mut renamed: Vec<Option<Arc<str>>> = vec![None];
+        let mut hashes = vec![fnv1a(
+            FNV_OFFSET_BASIS,
+            self.nodes[self.root].node.as_bytes(),
+        )];
+        let mut copy = 0;
+        while copy < rows.len() {
+            for i in child_map.of(symbols.ids[rows[copy]]) {
+                let child = symbols.ids[*i] as usize;
+                let branch = visited[child];
+                visited[child] += 1;
+
+                let node = &self.nodes[*i];
+                let hash = fnv1a(fnv1a(hashes[copy], &[0]), node.node.as_bytes());
+                let suffix = match options.naming {
+                    DupNaming::Counter if branch != 0 => Some(branch.to_string()),
+                    DupNaming::Counter => None,
+                    _ if occurrences[child] < 2 => None,
+                    DupNaming::ParentSuffix => {
+                        let parent = renamed[copy]
+                            .as_ref()
+                            .unwrap_or(&self.nodes[rows[copy]].node);
+                        Some(parent.to_string())
+                    }
+                    DupNaming::PathHash => Some(format!("{:08x}", hash)),
+                };
+                let suffix = suffix.filter(|_| !node.is_leaf());
+
+                rows.push(*i);
+                parents.push(Some(copy));
+                renamed.push(
+                    suffix.map(|x| format!("{}{}{}", node.node, options.separator, x).into()),
+                );
+                hashes.push(hash);
+            }
+            copy += 1;
+        }
+
+        // the copies share the ids, labels and extra columns of their rows
+        let id = |copy: usize| match &renamed[copy] {
+            Some(id) => Arc::clone(id),
+            None => Arc::clone(&self.nodes[rows[copy]].node),
+        };
+        let nodes = (0..rows.len())
+            .map(|copy| {
+                let node = &self.nodes[rows[copy]];
+                Node {
+                    node: id(copy),
+                    origin: match renamed[copy] {
+                        Some(_) => Some(Arc::clone(&node.node)),
+                        None => node.origin.to_owned(),
+                    },
+                    parent_node: parents[copy].map(id),
+                    ..node.to_owned()
+                }
+            })
+            .collect();
+
+        Ok(Graph { nodes, root: 0 })
+    }
+
+    /// Fails with the nodes of a cycle of parent links, if any, such as below the root of a graph
+    /// which is kept as it is instead of being converted or indexed.
+    pub fn ensure_acyclic(&self) -> error::Result<()> {
+        let symbols = Symbols::new(&self.nodes);
+        self.check_acyclic(&symbols, &symbols.children())
+    }
+
+    /// Fails with the nodes of a cycle of parent links, if any.
+    fn check_acyclic(&self, symbols: &Symbols, child_map: &Children) -> error::Result<()> {
+        // peel the nodes without parents off, so that only the cycles and the nodes below them
+        // keep parents
+        let mut parents = vec![0_usize; symbols.count];
+        for (id, parent) in symbols.ids.iter().zip(&symbols.parents) {
+            if parent.is_some() {
+                parents[*id as usize] += 1;
+            }
+        }
+        let mut pending: Vec<_> = (0..symbols.count).filter(|x| parents[*x] == 0).collect();
+        while let Some(symbol) = pending.pop() {
+            for i in child_map.of(symbol as u32) {
+                let child = symbols.ids[*i] as usize;
+                parents[child] -= 1;
+                if parents[child] == 0 {
+                    pending.push(child);
+                }
+            }
+        }
+
+        // every node left has a parent left, so following them ends up going round a cycle
+        let mut next = vec![None; symbols.count];
+        for (i, (id, parent)) in symbols.ids.iter().zip(&symbols.parents).enumerate() {
+            match parent {
+                Some(parent) if parents[*id as usize] > 0 && parents[*parent as usize] > 0 => {
+                    next[*id as usize] = Some((*parent as usize, i));
+                }
+                _ => {}
+            }
+        }
+        let mut symbol = match (0..symbols.count).find(|x| parents[*x] > 0) {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let mut seen = vec![false; symbols.count];
+        while !seen[symbol] {
+            seen[symbol] = true;
+            symbol = next[symbol].map_or(symbol, |(parent, _)| parent);
+        }
+
+        let start = symbol;
+        let mut rows = Vec::new();
+        while let Some((parent, i)) = next[symbol] {
+            rows.push(i);
+            symbol = parent;
+            if symbol == start {
+                break;
+            }
+        }
+        // from parent to child, starting at the first row on the cycle
+        rows.reverse();
+        let first = (0..rows.len()).min_by_key(|x| rows[*x]).unwrap_or(0);
+        rows.rotate_left(first);
+
+        let ids = rows.iter().map(|i| self.nodes[*i].node.to_string());
+        Err(error::Error::CycleError {
+            ids: ids.collect(),
+            rows: rows.iter().map(|i| i + 1).collect(),
+        })
+    }
+
+    /// Converts a directed acyclic graph to a tree by keeping every node only under its primary
+    /// parent, returning the dropped edges as pairs of the id and the parent.
+    ///
+    /// The primary parent is given by the `primary_parent` column of any row of the node, or is
+    /// the first listed parent otherwise.
+    pub fn primary_parent_tree(&self) -> error::Result<(Self, Vec<(String, String)>)> {
+        let mut primary = HashMap::new();
+        for node in &self.nodes {
+            let parent = node.extra_str(PRIMARY_PARENT);
+            if parent.is_some() && node.parent_node.is_some() {
+                primary.entry(&*node.node).or_insert(parent);
+            }
+        }
+
+        // the row of each node which is kept
+        let mut kept = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let is_primary = node.parent_node.is_some()
+                && primary
+                    .get(&*node.node)
+                    .is_some_and(|x| x.as_deref() == node.parent_node.as_deref());
+            match kept.entry(&*node.node) {
+                Entry::Vacant(entry) => {
+                    entry.insert(i);
+                }
+                Entry::Occupied(mut entry) if is_primary => {
+                    entry.insert(i);
+                }
+                Entry::Occupied(_) => {}
+            }
+        }
+
+        let mut nodes = Vec::new();
+        let mut dropped = Vec::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if kept.get(&*node.node) == Some(&i) {
+                nodes.push(node.to_owned());
+            } else if let Some(parent) = &node.parent_node {
+                dropped.push((node.node.to_string(), parent.to_string()));
+            }
+        }
+
+        Ok((Graph::new(nodes)?, dropped))
+    }
+
+    /// Returns the original ids of the nodes which appear more than once, such as after
+    /// [`Graph::dag_to_tree`], with all their copies in the order of the nodes.
+    pub fn copies(&self) -> Vec<(String, Vec<NodeCopy>)> {
+        let mut order = Vec::new();
+        let mut copies = HashMap::<&str, Vec<NodeCopy>>::new();
+        for node in &self.nodes {
+            let id = node.origin.as_deref().unwrap_or(&node.node);
+            let copy = NodeCopy {
+                id: node.node.to_string(),
+                parent: node.parent_node.as_deref().map(str::to_owned),
+            };
+            copies
+                .entry(id)
+                .or_insert_with(|| {
+                    order.push(id);
+                    Vec::new()
+                })
+                .push(copy);
+        }
+
+        order
+            .into_iter()
+            .filter_map(|id| {
+                let copies = copies.remove(id)?;
+                (copies.len() > 1).then(|| (id.to_owned(), copies))
+            })
+            .collect()
+    }
+
+    /// Wraps every node with a classification node prefixed with `c__`, so that each node also
+    /// appears as a leaf under its own classification.
+    pub fn complement_leaf(&self) -> error::Result<Self> {
+        self.complement_leaf_with(&ComplementOptions::default())
+    }
+
+    /// Wraps every node with a classification node like [`Graph::complement_leaf`] with `options`.
+    #[tracing::instrument(name = "complement_leaf", level = "debug", skip_all)]
+    pub fn complement_leaf_with(&self, options: &ComplementOptions) -> error::Result<Self> {
+        let prefix = |id: &str| format!("{}{}{}", options.prefix, options.separator, id);
+        let mut nodes = VecDeque::new();
+        let mut set = FxHashSet::default();
+
+        let mut push_unless_exist = |node: Node| {
+            if set.insert((node.node.to_owned(), node.parent_node.to_owned())) {
+                nodes.push_back(node);
+            }
+        };
+
+        let parents: FxHashSet<_> = self
+            .nodes
+            .iter()
+            .filter_map(|x| x.parent_node.as_deref())
+            .collect();
+
+        for node in self.nodes.iter() {
+            if options.internal_only && !parents.contains(&*node.node) {
+                let mut leaf = node.to_owned();
+                if options.keep_id == KeepId::Leaf {
+                    leaf.parent_node = leaf.parent_node.as_deref().map(|x| prefix(x).into());
+                }
+                push_unless_exist(leaf);
+                continue;
+            }
+
+            let mut classification = node.to_owned();
+            let mut leaf = classification.to_owned();
+
+            match options.keep_id {
+                KeepId::Leaf => {
+                    classification.node = prefix(&classification.node).into();
+                    classification.parent_node = classification
+                        .parent_node
+                        .as_deref()
+                        .map(|x| prefix(x).into());
+                }
+                KeepId::Classification => leaf.node = prefix(&leaf.node).into(),
+            }
+            classification.leaf = Some(false);
+
+            leaf.parent_node = Some(classification.node.to_owned());
+            leaf.leaf = Some(true);
+            if let Some(template) = &options.leaf_label {
+                leaf.label = template
+                    .replace("{label}", &node.label)
+                    .replace("{id}", &node.node)
+                    .into();
+            }
+
+            push_unless_exist(classification);
+            push_unless_exist(leaf);
+        }
+
+        // the root is not the first node if it was not the first row
+        let root = nodes
+            .iter()
+            .position(|x| x.parent_node.is_none())
+            .ok_or(error::Error::RootNodeNotFoundError())?;
+
+        Ok(Graph {
+            nodes: nodes.into(),
+            root,
+        })
+    }
+
+    /// Assigns `pid`, `parent_id`, `lft`, `rgt`, `count`, `depth`, `descendant_count` and
+    /// `leaf_count` to every node.
+    pub fn build_index(&mut self) -> error::Result<&Self> {
+        self.build_index_with(&NumberingOptions::default())
+    }
+
+    /// Assigns the nested set values like [`Graph::build_index`] with `options`.
+    #[tracing::instrument(name = "build_index", level = "debug", skip_all)]
+    pub fn build_index_with(&mut self, options: &NumberingOptions) -> error::Result<&Self> {
+        let value = |n: usize| options.value(n);
+        let symbols = Symbols::new(&self.nodes);
+        let parent_map = self.number_pids(options, &symbols)?;
+
+        let child_map = symbols.children();
+        // a cycle below the root would be traversed endlessly
+        self.check_acyclic(&symbols, &child_map)?;
+        let children = child_map.of(symbols.ids[self.root]);
+
+        // sibling subtrees get disjoint ranges of lft/rgt, so they are numbered in parallel from 1
+        // and shifted by the sizes of the subtrees before them
+        let subtrees = children
+            .par_iter()
+            .map(|i| self.visit_subtree(*i, &symbols, &child_map, &parent_map))
+            .collect::<error::Result<Vec<_>>>()?;
+
+        // the root is entered first
+        let mut offset = 1;
+        let mut leaf_count = 0;
+        for visits in subtrees {
+            for (i, visit) in &visits {
+                let node = &mut self.nodes[*i];
+                node.lft = Some(value(offset + visit.lft));
+                node.rgt = Some(value(offset + visit.rgt));
+                node.count = Some(visit.count);
+                node.depth = Some(visit.depth);
+                node.descendant_count = Some((visit.rgt - visit.lft - 1) / 2);
+                node.leaf_count = Some(visit.leaf_count);
+                node.parent_id = visit.parent_id;
+            }
+            if let Some((_, top)) = visits.first() {
+                leaf_count += top.leaves();
+            }
+            offset += visits.len() * 2;
+        }
+
+        let parent_id = self.parent_pid(self.root, &symbols, &parent_map)?;
+        let root = &mut self.nodes[self.root];
+        root.lft = Some(value(1));
+        root.rgt = Some(value(offset + 1));
+        root.count = Some(children.len());
+        root.depth = Some(0);
+        root.descendant_count = Some((offset - 1) / 2);
+        root.leaf_count = Some(leaf_count);
+        root.parent_id = parent_id;
+
+        // pids are numbered in the order of the nodes unless they are taken from the ids
+        if options.pid_from_id {
+            self.nodes.sort_by_key(|x| x.pid);
+        }
+
+        Ok(self)
+    }
+
+    /// Assigns the `pid` of every node, returning the pid of every symbol of a node which can have
+    /// children.
+    fn number_pids(
+        &mut self,
+        options: &NumberingOptions,
+        symbols: &Symbols,
+    ) -> error::Result<Vec<Option<usize>>> {
+        let mut parent_map = vec![None; symbols.count];
+        let mut pids = FxHashSet::default();
+        for (i, x) in self.nodes.iter_mut().enumerate() {
+            let pid = if options.pid_from_id {
+                let id = x.node.parse::<usize>();
+                let id = id.map_err(|_| error::Error::NonIntegerIdError(x.node.to_string()))?;
+                options.pid_offset + id
+            } else {
+                options.pid_offset + i + 1
+            };
+            if options.pid_from_id && !pids.insert(pid) {
+                Err(error::Error::DuplicatePidError(pid))?
+            }
+            x.pid = Some(pid);
+            if !x.is_leaf() {
+                parent_map[symbols.ids[i] as usize] = Some(pid);
+            }
+        }
+
+        Ok(parent_map)
+    }
+
+    /// Assigns the nested set values like [`Graph::build_index_with`] while the nodes are taken
+    /// out one at a time in the order of `pid`, so that they can be written without keeping
+    /// another copy of the whole hierarchy.
+    ///
+    /// The sizes of the subtrees are counted first, so that the `rgt` of a node is known as soon
+    /// as the traversal enters it. A node is yielded once it and the nodes before it in the order
+    /// of `pid` have been entered, which is right away for the nodes in the order of the
+    /// traversal.
+    #[tracing::instrument(name = "build_index", level = "debug", skip_all)]
+    pub fn into_indexed(mut self, options: &NumberingOptions) -> error::Result<IndexedNodes> {
+        let symbols = Symbols::new(&self.nodes);
+        let parent_map = self.number_pids(options, &symbols)?;
+        let child_map = symbols.children();
+        self.check_acyclic(&symbols, &child_map)?;
+
+        // the nodes below the root in the order of the traversal, with the pid of their parent
+        let mut parent_ids = vec![None; self.nodes.len()];
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![self.root];
+        while let Some(i) = stack.pop() {
+            parent_ids[i] = self.parent_pid(i, &symbols, &parent_map)?;
+            order.push(i);
+            stack.extend(child_map.of(symbols.ids[i]).iter().rev());
+        }
 
-        for (i, node) in nodes.iter().enumerate() {
-            if node.parent_node.is_none() {
-                if root.is_none() {
-                    root = Some(i)
-                } else {
-                    Err(error::Error::MultipleRootNodeError())?
-                }
+        // children come after their parent in the order of the traversal
+        let mut sizes = vec![1; self.nodes.len()];
+        let mut leaf_counts = vec![0; self.nodes.len()];
+        for i in order.into_iter().rev() {
+            for child in child_map.of(symbols.ids[i]) {
+                sizes[i] += sizes[*child];
+                leaf_counts[i] += leaf_counts[*child].max(1);
             }
         }
 
-        Ok(Graph {
-            nodes,
-            root: root.ok_or(error::Error::RootNodeNotFoundError())?,
+        let mut pids: Vec<usize> = (0..self.nodes.len()).collect();
+        if options.pid_from_id {
+            pids.sort_by_key(|i| self.nodes[*i].pid);
+        }
+
+        Ok(IndexedNodes {
+            options: options.to_owned(),
+            stack: vec![(self.root, 1, 0)],
+            entered: vec![false; self.nodes.len()],
+            nodes: self.nodes,
+            symbols,
+            child_map,
+            parent_ids,
+            sizes,
+            leaf_counts,
+            pids: pids.into_iter().peekable(),
         })
     }
 
-    pub fn is_dag(&self) -> bool {
-        let mut set = HashSet::new();
-
-        for node in self.nodes.iter() {
-            if !node.leaf && !set.insert(node.node.to_owned()) {
-                return true;
+    /// Traverses the subtree of the node at `top`, a child of the root, numbering its nodes from 1
+    /// in the order of the traversal.
+    fn visit_subtree(
+        &self,
+        top: usize,
+        symbols: &Symbols,
+        child_map: &Children,
+        parent_map: &[Option<usize>],
+    ) -> error::Result<Vec<(usize, Visit)>> {
+        let mut visits: Vec<(usize, Visit)> = Vec::new();
+        let mut slots = FxHashMap::<usize, usize>::default();
+
+        // traverse with an explicit stack instead of recursion so that deep trees do not overflow
+        // the call stack. `false` marks entering a node and `true` leaving it.
+        let mut stack = vec![(top, false, 1)];
+        let mut n = 0;
+
+        while let Some((i, leaving, depth)) = stack.pop() {
+            n += 1;
+
+            let children = child_map.of(symbols.ids[i]);
+
+            if leaving {
+                // children have already been left, so their counts are available
+                let leaf_count = children.iter().map(|i2| visits[slots[i2]].1.leaves()).sum();
+
+                let visit = &mut visits[slots[&i]].1;
+                visit.rgt = n;
+                visit.count = children.len();
+                visit.leaf_count = leaf_count;
+                continue;
             }
+
+            slots.insert(i, visits.len());
+            visits.push((
+                i,
+                Visit {
+                    lft: n,
+                    depth,
+                    parent_id: self.parent_pid(i, symbols, parent_map)?,
+                    ..Default::default()
+                },
+            ));
+
+            stack.push((i, true, depth));
+            stack.extend(children.iter().rev().map(|i2| (*i2, false, depth + 1)));
         }
 
-        false
+        Ok(visits)
     }
 
-    fn build_child_map(&self) -> HashMap<String, Vec<(usize, String)>> {
-        let mut child_map = HashMap::new();
+    /// Returns the pid of the parent of the node at row `i` in `parent_map`, failing if the
+    /// parent is not a node which can have children.
+    fn parent_pid(
+        &self,
+        i: usize,
+        symbols: &Symbols,
+        parent_map: &[Option<usize>],
+    ) -> error::Result<Option<usize>> {
+        match (symbols.parents[i], &self.nodes[i].parent_node) {
+            (Some(symbol), Some(parent)) => match parent_map[symbol as usize] {
+                Some(pid) => Ok(Some(pid)),
+                None => Err(error::Error::ParentNodeNotFoundError {
+                    id: self.nodes[i].node.to_string(),
+                    parent: parent.to_string(),
+                    row: Some(i + 1),
+                }),
+            },
+            _ => Ok(None),
+        }
+    }
 
-        for (i, node) in self.nodes.iter().enumerate() {
-            if let Some(parent) = &node.parent_node {
-                child_map
-                    .entry(parent.to_owned())
-                    .or_insert_with(Vec::new)
-                    .push((i, node.node.to_owned()))
+    /// Assigns the nested set values like [`Graph::build_index`], keeping the `pid` read from the
+    /// input. Nodes without a `pid` are numbered after the largest one.
+    ///
+    /// This repairs broken `lft` and `rgt` by rebuilding them from the parent links.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn rebuild_index(&mut self) -> error::Result<&Self> {
+        let pids: Vec<Option<usize>> = self.nodes.iter().map(|x| x.pid).collect();
+        self.build_index()?;
+
+        let mut next = pids.iter().flatten().max().copied().unwrap_or(0);
+        let mut seen = FxHashSet::default();
+        let mut map = Vec::with_capacity(pids.len());
+        for pid in pids {
+            let pid = match pid {
+                Some(x) => x,
+                None => {
+                    next += 1;
+                    next
+                }
+            };
+            if !seen.insert(pid) {
+                Err(error::Error::DuplicatePidError(pid))?
             }
+            map.push(pid);
+        }
+
+        // nodes are still in the input order, numbered from 1 by `build_index`
+        for node in self.nodes.iter_mut() {
+            node.pid = node.pid.map(|x| map[x - 1]);
+            node.parent_id = node.parent_id.map(|x| map[x - 1]);
         }
+        self.nodes.sort_by_key(|x| x.pid);
 
-        child_map
+        Ok(self)
     }
 
-    pub fn dag_to_tree(&self) -> error::Result<Self> {
-        let child_map = self.build_child_map();
-        let mut queue = VecDeque::new();
-        let mut visited = HashMap::new();
+    /// Joins the segments of every node and its ancestors from the root with `separator`.
+    fn join_ancestors<F: Fn(&Node) -> String>(&self, separator: &str, segment: F) -> Vec<String> {
+        let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+        order.sort_by_key(|i| self.nodes[*i].lft);
+        let positions: FxHashMap<usize, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| x.pid.map(|pid| (pid, i)))
+            .collect();
+
+        // parents come before their children in the order of `lft`
+        let mut paths = vec![String::new(); self.nodes.len()];
+        for i in order {
+            let node = &self.nodes[i];
+            let segment = segment(node);
+            paths[i] = match node.parent_id.and_then(|x| positions.get(&x)) {
+                Some(parent) => format!("{}{}{}", paths[*parent], separator, segment),
+                None => segment,
+            };
+        }
 
-        let mut nodes = Vec::new();
-        nodes.push(self.nodes[self.root].to_owned());
-        queue.push_back((self.root, nodes.len() - 1));
-
-        while let Some((orig, new)) = queue.pop_front() {
-            if let Some(children) = child_map.get(&self.nodes[orig].node) {
-                for (i, child) in children {
-                    let branch = visited
-                        .entry(child)
-                        .and_modify(|c| *c += 1)
-                        .or_insert(0 as usize);
-
-                    let mut node = self.nodes[*i].to_owned();
-                    node.parent_node = Some(nodes[new].node.to_owned());
-                    if !node.leaf && *branch != (0 as usize) {
-                        node.origin = Some(node.node.to_owned());
-                        node.node = format!("{}{}{}", node.node, SEPARATOR, *branch);
-                    }
+        paths
+    }
 
-                    nodes.push(node);
-                    queue.push_back((*i, nodes.len() - 1));
-                }
+    /// Assigns the materialized path from the root to every node, such as `1/2/5`.
+    ///
+    /// Must be called after [`Graph::build_index`].
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn build_paths(&mut self, options: &PathOptions) -> &Self {
+        let separator = if options.ltree {
+            "."
+        } else {
+            &options.separator
+        };
+
+        let paths = self.join_ancestors(separator, |node| {
+            let segment = match options.segment {
+                PathSegment::Id => &*node.node,
+                PathSegment::Label => &node.label,
+            };
+            if options.ltree {
+                ltree_label(segment)
+            } else {
+                segment.to_owned()
             }
+        });
+        for (node, path) in self.nodes.iter_mut().zip(paths) {
+            node.path = Some(path);
         }
 
-        Ok(Graph { nodes, root: 0 })
+        self
     }
 
-    pub fn complement_leaf(&self) -> error::Result<Self> {
-        let mut nodes = VecDeque::new();
-        let mut set = HashSet::new();
+    /// Assigns the labels of every node and its ancestors from the root joined with `separator`,
+    /// such as `Bacteria > Proteobacteria`.
+    ///
+    /// Must be called after [`Graph::build_index`].
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn build_label_paths(&mut self, separator: &str) -> &Self {
+        let paths = self.join_ancestors(separator, |node| node.label.to_string());
+        for (node, path) in self.nodes.iter_mut().zip(paths) {
+            node.label_path = Some(path);
+        }
 
-        let mut push_unless_exist = |node: Node| {
-            if set.insert((node.node.to_owned(), node.parent_node.to_owned())) {
-                nodes.push_back(node);
+        self
+    }
+
+    /// Assigns to every node a hash of the ids and the labels of its subtree and of the order of
+    /// their children, as 16 hexadecimal digits which stay the same across runs and releases as
+    /// long as the subtree does not change.
+    ///
+    /// Must be called after [`Graph::build_index`].
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn build_hashes(&mut self) -> &Self {
+        let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+        order.sort_by_key(|i| self.nodes[*i].lft);
+        let mut children = FxHashMap::<usize, Vec<usize>>::default();
+        for i in order.iter().copied() {
+            if let Some(parent) = self.nodes[i].parent_id {
+                children.entry(parent).or_default().push(i);
             }
-        };
+        }
 
-        for node in self.nodes.iter() {
-            let mut classification = node.to_owned();
-            let mut leaf = classification.to_owned();
+        // children come after their parents in the order of `lft`
+        let mut hashes = vec![0u64; self.nodes.len()];
+        for i in order.into_iter().rev() {
+            let node = &self.nodes[i];
+            let mut hash = fnv1a64(FNV64_OFFSET_BASIS, node.node.as_bytes());
+            hash = fnv1a64(hash, &[0]);
+            hash = fnv1a64(hash, node.label.as_bytes());
+            for child in node
+                .pid
+                .and_then(|x| children.get(&x))
+                .into_iter()
+                .flatten()
+            {
+                hash = fnv1a64(hash, &hashes[*child].to_le_bytes());
+            }
+            hashes[i] = hash;
+        }
+
+        for (node, hash) in self.nodes.iter_mut().zip(hashes) {
+            node.hash = Some(format!("{:016x}", hash));
+        }
+
+        self
+    }
+
+    fn position(&self, id: &str) -> error::Result<usize> {
+        self.nodes
+            .iter()
+            .position(|x| *x.node == *id)
+            .ok_or_else(|| error::Error::NodeNotFoundError(id.to_owned()))
+    }
+
+    /// Returns the position of the node `id` which can have children, failing with the node
+    /// `child` at `row` whose parent it is.
+    fn parent_position(&self, id: &str, child: &str, row: usize) -> error::Result<usize> {
+        self.nodes
+            .iter()
+            .position(|x| *x.node == *id && !x.is_leaf())
+            .ok_or_else(|| error::Error::ParentNodeNotFoundError {
+                id: child.to_owned(),
+                parent: id.to_owned(),
+                row: Some(row),
+            })
+    }
+
+    fn bounds(&self, i: usize) -> error::Result<(usize, usize)> {
+        match (self.nodes[i].lft, self.nodes[i].rgt) {
+            (Some(lft), Some(rgt)) => Ok((lft, rgt)),
+            _ => Err(error::Error::NotIndexedError(
+                self.nodes[i].node.to_string(),
+            )),
+        }
+    }
+
+    /// Maps `lft` and `rgt` of every node with `f`.
+    fn shift<F: Fn(usize) -> usize>(&mut self, f: F) {
+        for node in self.nodes.iter_mut() {
+            node.lft = node.lft.map(&f);
+            node.rgt = node.rgt.map(&f);
+        }
+    }
 
-            classification.node = format!("c{}{}", SEPARATOR, classification.node);
-            if let Some(node) = classification.parent_node {
-                classification.parent_node = Some(format!("c{}{}", SEPARATOR, node));
+    /// Recomputes `count`, and `depth`, `descendant_count` and `leaf_count` if assigned, from
+    /// `lft` and `rgt` after an edit.
+    fn refresh_counts(&mut self) {
+        let mut order: Vec<usize> = (0..self.nodes.len())
+            .filter(|i| self.nodes[*i].lft.is_some() && self.nodes[*i].rgt.is_some())
+            .collect();
+        order.sort_by_key(|i| self.nodes[*i].lft);
+
+        // descendants and leaves are counted by their `lft` within the interval, which is
+        // independent of the spacing of the values; a leaf is not followed by a node inside it
+        let lfts: Vec<usize> = order.iter().filter_map(|i| self.nodes[*i].lft).collect();
+        let leaves: Vec<usize> = order
+            .iter()
+            .enumerate()
+            .filter(|(k, i)| {
+                lfts.get(k + 1)
+                    .is_none_or(|x| Some(*x) > self.nodes[**i].rgt)
+            })
+            .map(|(k, _)| lfts[k])
+            .collect();
+        let within = |values: &[usize], lft: usize, rgt: usize| {
+            values.partition_point(|x| *x < rgt) - values.partition_point(|x| *x <= lft)
+        };
+
+        let mut counts = vec![0; self.nodes.len()];
+        let mut stack: Vec<usize> = Vec::new();
+        for i in order {
+            let (lft, rgt) = match (self.nodes[i].lft, self.nodes[i].rgt) {
+                (Some(lft), Some(rgt)) => (lft, rgt),
+                _ => continue,
+            };
+            while stack.last().is_some_and(|x| self.nodes[*x].rgt < Some(lft)) {
+                stack.pop();
+            }
+            if let Some(parent) = stack.last() {
+                counts[*parent] += 1;
             }
-            classification.leaf = false;
 
-            leaf.parent_node = Some(classification.node.to_owned());
-            leaf.leaf = true;
+            let depth = stack.len();
+            let node = &mut self.nodes[i];
+            node.depth = node.depth.map(|_| depth);
+            node.descendant_count = node.descendant_count.map(|_| within(&lfts, lft, rgt));
+            node.leaf_count = node.leaf_count.map(|_| within(&leaves, lft, rgt));
+            stack.push(i);
+        }
 
-            push_unless_exist(classification);
-            push_unless_exist(leaf);
+        for (node, count) in self.nodes.iter_mut().zip(counts) {
+            node.count = Some(count);
         }
+    }
 
-        Ok(Graph {
-            nodes: nodes.into(),
-            root: 0,
-        })
+    fn next_pid(&self) -> usize {
+        self.nodes.iter().filter_map(|x| x.pid).max().unwrap_or(0) + 1
     }
 
-    pub fn build_index(&mut self) -> error::Result<&Self> {
-        fn fill(
-            nodes: &mut Vec<Node>,
-            child_map: &HashMap<String, Vec<(usize, String)>>,
-            parent_map: &HashMap<String, usize>,
-            i: usize,
-            n: usize,
-        ) -> error::Result<usize> {
-            {
-                let node = nodes.get_mut(i).unwrap();
-                node.lft = Some(n);
-
-                if let Some(p) = &node.parent_node {
-                    let pi = parent_map
-                        .get(p)
-                        .ok_or(error::Error::ParentNodeNotFoundError(p.to_owned()))?;
-                    node.parent_id = Some(*pi)
-                }
+    /// Inserts `node` as the last child of its parent into an indexed graph, shifting `lft` and
+    /// `rgt` of only the nodes to the right of it unless the parent has free numbers after its last
+    /// child, such as with [`NumberingOptions::gap`]. The node is given the next `pid`.
+    pub fn insert(&mut self, mut node: Node) -> error::Result<&Node> {
+        if self.nodes.iter().any(|x| x.node == node.node) {
+            Err(error::Error::NodeConflictError(node.node.to_string()))?
+        }
+        let parent = match &node.parent_node {
+            Some(x) => self.parent_position(x, &node.node, self.nodes.len() + 1)?,
+            None => {
+                let mut roots = root_nodes(&self.nodes);
+                roots.push(RootNode {
+                    id: node.node.to_string(),
+                    label: node.label.to_string(),
+                    row: self.nodes.len() + 1,
+                    descendant_count: 0,
+                });
+                Err(error::Error::MultipleRootNodeError(roots))?
             }
+        };
+        let (lft, rgt) = self.bounds(parent)?;
+
+        // the numbers left free after the last child by `--gap` are split into thirds, keeping room
+        // for the children of the node and for its later siblings, or the nodes are shifted
+        let last = self.nodes.iter().filter_map(|x| x.rgt);
+        let last = last.filter(|x| lft < *x && *x < rgt).max().unwrap_or(lft);
+        let step = (rgt - last) / 3;
+        let bounds = if step > 0 {
+            (last + step, rgt - step)
+        } else {
+            self.shift(|x| if x >= rgt { x + 2 } else { x });
+            (rgt, rgt + 1)
+        };
 
-            match child_map.get(&nodes.get(i).unwrap().node) {
-                Some(children) => {
-                    let mut n2 = n;
+        let parent = &self.nodes[parent];
+        node.source = Some(node.source_id().into());
+        node.pid = Some(self.next_pid());
+        node.parent_id = parent.pid;
+        node.lft = Some(bounds.0);
+        node.rgt = Some(bounds.1);
+        node.depth = parent.depth.map(|x| x + 1);
+        node.descendant_count = parent.descendant_count.map(|_| 0);
+        node.leaf_count = parent.leaf_count.map(|_| 0);
+        self.nodes.push(node);
+        self.refresh_counts();
+
+        Ok(&self.nodes[self.nodes.len() - 1])
+    }
 
-                    for (i2, _child) in children {
-                        n2 = fill(nodes, child_map, parent_map, *i2, n2 + 1)?;
-                    }
+    /// Moves the node `id` with its descendants to the last child of `parent` in an indexed graph,
+    /// shifting `lft` and `rgt` of only the nodes between the old and the new positions.
+    pub fn move_subtree(&mut self, id: &str, parent: &str) -> error::Result<&Self> {
+        let i = self.position(id)?;
+        let (lft, rgt) = self.bounds(i)?;
+        let p = self.parent_position(parent, id, i + 1)?;
+        let (parent_lft, target) = self.bounds(p)?;
+
+        if lft <= parent_lft && target <= rgt {
+            Err(error::Error::InvalidMoveError(
+                id.to_owned(),
+                parent.to_owned(),
+            ))?
+        }
 
-                    {
-                        let node = nodes.get_mut(i).unwrap();
-                        node.rgt = Some(n2 + 1);
-                        node.count = Some(children.len());
-                    }
+        let width = rgt - lft + 1;
+        if target > rgt {
+            self.shift(|x| match x {
+                x if lft <= x && x <= rgt => x + target - rgt - 1,
+                x if rgt < x && x < target => x - width,
+                x => x,
+            });
+        } else {
+            self.shift(|x| match x {
+                x if lft <= x && x <= rgt => x - (lft - target),
+                x if target <= x && x < lft => x + width,
+                x => x,
+            });
+        }
 
-                    Ok(n2 + 1)
-                }
-                None => {
-                    {
-                        let node = nodes.get_mut(i).unwrap();
-                        node.rgt = Some(n + 1);
-                        node.count = Some(0);
-                    }
+        self.nodes[i].parent_node = Some(parent.into());
+        self.nodes[i].parent_id = self.nodes[p].pid;
+        self.refresh_counts();
+
+        Ok(self)
+    }
 
-                    Ok(n + 1)
+    /// Deletes the node `id` with its descendants from an indexed graph, or only the node with
+    /// `promote_children`, attaching its children to its parent.
+    pub fn delete_subtree(&mut self, id: &str, promote_children: bool) -> error::Result<&Self> {
+        let i = self.position(id)?;
+        let (lft, rgt) = self.bounds(i)?;
+        let node = self.nodes[i].to_owned();
+        if node.parent_node.is_none() {
+            Err(error::Error::DeleteRootError(id.to_owned()))?
+        }
+
+        if promote_children {
+            self.nodes.remove(i);
+            for x in self.nodes.iter_mut() {
+                let inside = x.lft.is_some_and(|x| lft < x && x < rgt);
+                if inside && x.parent_node.as_deref() == Some(id) {
+                    x.parent_node = node.parent_node.to_owned();
+                    x.parent_id = node.parent_id;
                 }
             }
+            self.shift(|x| match x {
+                x if x > rgt => x - 2,
+                x if x > lft => x - 1,
+                x => x,
+            });
+        } else {
+            self.nodes
+                .retain(|x| !x.lft.is_some_and(|x| lft <= x && x <= rgt));
+            self.shift(|x| if x > rgt { x - (rgt - lft + 1) } else { x });
         }
 
-        let mut parent_map = HashMap::<String, usize>::new();
-        for (i, x) in self.nodes.iter_mut().enumerate() {
-            x.pid = Some(i + 1);
-            if !x.leaf {
-                parent_map.insert(x.node.to_owned(), i + 1);
-            }
+        self.root = self
+            .nodes
+            .iter()
+            .position(|x| x.parent_node.is_none())
+            .unwrap_or_default();
+        self.refresh_counts();
+
+        Ok(self)
+    }
+
+    /// Renumbers `lft` and `rgt` of an indexed graph contiguously from 1 in the same order, such as
+    /// after edits or [`NumberingOptions::gap`], keeping `pid` and the structure.
+    pub fn renumber(&mut self) -> error::Result<&Self> {
+        let mut values = Vec::with_capacity(self.nodes.len() * 2);
+        for i in 0..self.nodes.len() {
+            let (lft, rgt) = self.bounds(i)?;
+            values.extend([lft, rgt]);
         }
+        values.sort_unstable();
 
-        let child_map = self.build_child_map();
+        let ranks: HashMap<usize, usize> = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| (x, i + 1))
+            .collect();
+        self.shift(|x| ranks[&x]);
+        self.refresh_counts();
 
-        fill(self.nodes.as_mut(), &child_map, &parent_map, self.root, 1)?;
+        Ok(self)
+    }
 
-        self.nodes.sort_by(|a, b| a.pid.cmp(&b.pid));
+    /// Sets the label of the node `id`.
+    pub fn rename(&mut self, id: &str, label: &str) -> error::Result<&Self> {
+        self.position(id)?;
+        for node in self.nodes.iter_mut().filter(|x| *x.node == *id) {
+            node.label = label.into();
+        }
 
         Ok(self)
     }
+
+    /// Returns the subtree of every child of the root in the order of `lft`, renumbered to start
+    /// from the `lft` of the root if `local`, or keeping the numbering of the whole graph otherwise.
+    /// Must be called after [`Graph::build_index`].
+    pub fn split(&self, local: bool) -> Vec<Vec<Node>> {
+        let root = &self.nodes[self.root];
+        let mut children: Vec<&Node> = self
+            .nodes
+            .iter()
+            .filter(|x| x.parent_node.as_deref() == Some(&*root.node))
+            .collect();
+        children.sort_by_key(|x| x.lft);
+
+        children
+            .into_iter()
+            .map(|child| {
+                let offset = match (local, child.lft, root.lft) {
+                    (true, Some(lft), Some(start)) => lft - start,
+                    _ => 0,
+                };
+                let mut nodes: Vec<Node> = self
+                    .nodes
+                    .iter()
+                    .filter(|x| child.lft <= x.lft && x.rgt <= child.rgt)
+                    .cloned()
+                    .collect();
+                for node in nodes.iter_mut() {
+                    node.lft = node.lft.map(|x| x - offset);
+                    node.rgt = node.rgt.map(|x| x - offset);
+                }
+                nodes
+            })
+            .collect()
+    }
+
+    /// Returns the first node with the id `id`.
+    pub fn find(&self, id: &str) -> error::Result<&Node> {
+        self.nodes
+            .iter()
+            .find(|x| *x.node == *id)
+            .ok_or_else(|| error::Error::NodeNotFoundError(id.to_owned()))
+    }
+
+    /// Returns the nodes whose interval satisfies `predicate` with the interval of the node `id`,
+    /// in the order of `lft`.
+    fn select<F>(&self, id: &str, predicate: F) -> error::Result<Vec<&Node>>
+    where
+        F: Fn((usize, usize), (usize, usize)) -> bool,
+    {
+        let node = self.find(id)?;
+        let bounds = match (node.lft, node.rgt) {
+            (Some(lft), Some(rgt)) => (lft, rgt),
+            _ => return Ok(Vec::new()),
+        };
+
+        let mut nodes: Vec<&Node> = self
+            .nodes
+            .iter()
+            .filter(|x| match (x.lft, x.rgt) {
+                (Some(lft), Some(rgt)) => predicate(bounds, (lft, rgt)),
+                _ => false,
+            })
+            .collect();
+        nodes.sort_by_key(|x| x.lft);
+
+        Ok(nodes)
+    }
+
+    /// Returns the ancestors of the node `id` from the root. Must be called after
+    /// [`Graph::build_index`].
+    pub fn ancestors(&self, id: &str) -> error::Result<Vec<&Node>> {
+        self.select(id, |(lft, rgt), x| x.0 < lft && rgt < x.1)
+    }
+
+    /// Returns the descendants of the node `id` in the order of `lft`. Must be called after
+    /// [`Graph::build_index`].
+    pub fn descendants(&self, id: &str) -> error::Result<Vec<&Node>> {
+        self.select(id, |(lft, rgt), x| lft < x.0 && x.1 < rgt)
+    }
+
+    /// Returns the node `id` followed by its descendants. Must be called after
+    /// [`Graph::build_index`].
+    pub fn subtree(&self, id: &str) -> error::Result<Vec<&Node>> {
+        self.select(id, |(lft, rgt), x| lft <= x.0 && x.1 <= rgt)
+    }
+
+    /// Returns the deepest node which is the node or an ancestor of every node of `ids`. Must be
+    /// called after [`Graph::build_index`].
+    pub fn lowest_common_ancestor(&self, ids: &[&str]) -> error::Result<&Node> {
+        let mut lft = usize::MAX;
+        let mut rgt = 0;
+        for id in ids {
+            let node = self.find(id)?;
+            lft = lft.min(node.lft.unwrap_or_default());
+            rgt = rgt.max(node.rgt.unwrap_or_default());
+        }
+
+        self.nodes
+            .iter()
+            .filter(|x| x.lft.is_some_and(|x| x <= lft) && x.rgt.is_some_and(|x| rgt <= x))
+            .max_by_key(|x| x.lft)
+            .ok_or(error::Error::RootNodeNotFoundError())
+    }
+}
+
+/// Replaces the characters not allowed in a label of `ltree` with `_`.
+fn ltree_label(segment: &str) -> String {
+    let label: String = segment
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    if label.is_empty() {
+        "_".to_owned()
+    } else {
+        label
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::get_first)]
 mod tests {
-    use crate::data::{Graph, Node};
+    use crate::data::{ColumnMapping, ComplementOptions, DagOptions, Graph, Node, PathOptions};
+    use crate::data::{DupNaming, KeepId, NodeCopy, NumberingOptions, PathSegment, SiblingOrder};
+    use crate::error::Error;
+    use crate::generate::trees;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
 
     fn test_data() -> Vec<Node> {
         vec![
             Node {
                 pid: None,
-                node: "1".to_owned(),
+                node: "1".into(),
                 origin: None,
-                label: "1".to_owned(),
+                label: "1".into(),
                 parent_node: None,
                 parent_id: None,
-                leaf: false,
+                leaf: Some(false),
                 lft: None,
                 rgt: None,
                 count: None,
+                ..Default::default()
             },
             Node {
                 pid: None,
-                node: "2".to_owned(),
+                node: "2".into(),
                 origin: None,
-                label: "2".to_owned(),
-                parent_node: Some("1".to_owned()),
+                label: "2".into(),
+                parent_node: Some("1".into()),
                 parent_id: None,
-                leaf: false,
+                leaf: Some(false),
                 lft: None,
                 rgt: None,
                 count: None,
+                ..Default::default()
             },
             Node {
                 pid: None,
-                node: "3".to_owned(),
+                node: "3".into(),
                 origin: None,
-                label: "3".to_owned(),
-                parent_node: Some("2".to_owned()),
+                label: "3".into(),
+                parent_node: Some("2".into()),
                 parent_id: None,
-                leaf: false,
+                leaf: Some(false),
                 lft: None,
                 rgt: None,
                 count: None,
+                ..Default::default()
             },
             Node {
                 pid: None,
-                node: "4".to_owned(),
+                node: "4".into(),
                 origin: None,
-                label: "4".to_owned(),
-                parent_node: Some("3".to_owned()),
+                label: "4".into(),
+                parent_node: Some("3".into()),
                 parent_id: None,
-                leaf: false,
+                leaf: Some(false),
                 lft: None,
                 rgt: None,
                 count: None,
+                ..Default::default()
             },
             Node {
                 pid: None,
-                node: "4".to_owned(),
+                node: "4".into(),
                 origin: None,
-                label: "4".to_owned(),
-                parent_node: Some("1".to_owned()),
+                label: "4".into(),
+                parent_node: Some("1".into()),
                 parent_id: None,
-                leaf: false,
+                leaf: Some(false),
                 lft: None,
                 rgt: None,
                 count: None,
+                ..Default::default()
             },
             Node {
                 pid: None,
-                node: "5".to_owned(),
+                node: "5".into(),
                 origin: None,
-                label: "5".to_owned(),
-                parent_node: Some("3".to_owned()),
+                label: "5".into(),
+                parent_node: Some("3".into()),
                 parent_id: None,
-                leaf: true,
+                leaf: Some(true),
                 lft: None,
                 rgt: None,
                 count: None,
+                ..Default::default()
             },
             Node {
                 pid: None,
-                node: "5".to_owned(),
+                node: "5".into(),
                 origin: None,
-                label: "5".to_owned(),
-                parent_node: Some("4".to_owned()),
+                label: "5".into(),
+                parent_node: Some("4".into()),
                 parent_id: None,
-                leaf: true,
+                leaf: Some(true),
                 lft: None,
                 rgt: None,
                 count: None,
+                ..Default::default()
             },
         ]
     }
@@ -326,12 +2107,12 @@ mod tests {
         {
             let node = nodes.get(0).unwrap();
             assert_eq!(node.pid, Some(1));
-            assert_eq!(node.node, "1".to_owned());
+            assert_eq!(&*node.node, "1");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "1".to_owned());
+            assert_eq!(node.label(), "1");
             assert_eq!(node.parent_node, None);
             assert_eq!(node.parent_id, None);
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(1));
             assert_eq!(node.rgt, Some(16));
             assert_eq!(node.count, Some(2));
@@ -340,12 +2121,12 @@ mod tests {
         {
             let node = nodes.get(1).unwrap();
             assert_eq!(node.pid, Some(2));
-            assert_eq!(node.node, "2".to_owned());
+            assert_eq!(&*node.node, "2");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "2".to_owned());
-            assert_eq!(node.parent_node, Some("1".to_owned()));
+            assert_eq!(node.label(), "2");
+            assert_eq!(node.parent_node.as_deref(), Some("1"));
             assert_eq!(node.parent_id, Some(1));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(2));
             assert_eq!(node.rgt, Some(11));
             assert_eq!(node.count, Some(1));
@@ -354,12 +2135,12 @@ mod tests {
         {
             let node = nodes.get(2).unwrap();
             assert_eq!(node.pid, Some(3));
-            assert_eq!(node.node, "4".to_owned());
+            assert_eq!(&*node.node, "4");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "4".to_owned());
-            assert_eq!(node.parent_node, Some("1".to_owned()));
+            assert_eq!(node.label(), "4");
+            assert_eq!(node.parent_node.as_deref(), Some("1"));
             assert_eq!(node.parent_id, Some(1));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(12));
             assert_eq!(node.rgt, Some(15));
             assert_eq!(node.count, Some(1));
@@ -368,12 +2149,12 @@ mod tests {
         {
             let node = nodes.get(3).unwrap();
             assert_eq!(node.pid, Some(4));
-            assert_eq!(node.node, "3".to_owned());
+            assert_eq!(&*node.node, "3");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "3".to_owned());
-            assert_eq!(node.parent_node, Some("2".to_owned()));
+            assert_eq!(node.label(), "3");
+            assert_eq!(node.parent_node.as_deref(), Some("2"));
             assert_eq!(node.parent_id, Some(2));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(3));
             assert_eq!(node.rgt, Some(10));
             assert_eq!(node.count, Some(2));
@@ -382,12 +2163,12 @@ mod tests {
         {
             let node = nodes.get(4).unwrap();
             assert_eq!(node.pid, Some(5));
-            assert_eq!(node.node, "5".to_owned());
+            assert_eq!(&*node.node, "5");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "5".to_owned());
-            assert_eq!(node.parent_node, Some("4".to_owned()));
+            assert_eq!(node.label(), "5");
+            assert_eq!(node.parent_node.as_deref(), Some("4"));
             assert_eq!(node.parent_id, Some(3));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(13));
             assert_eq!(node.rgt, Some(14));
             assert_eq!(node.count, Some(0));
@@ -396,12 +2177,12 @@ mod tests {
         {
             let node = nodes.get(5).unwrap();
             assert_eq!(node.pid, Some(6));
-            assert_eq!(node.node, "4__1".to_owned());
-            assert_eq!(node.origin, Some("4".to_owned()));
-            assert_eq!(node.label, "4".to_owned());
-            assert_eq!(node.parent_node, Some("3".to_owned()));
+            assert_eq!(&*node.node, "4__1");
+            assert_eq!(node.origin.as_deref(), Some("4"));
+            assert_eq!(node.label(), "4");
+            assert_eq!(node.parent_node.as_deref(), Some("3"));
             assert_eq!(node.parent_id, Some(4));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(4));
             assert_eq!(node.rgt, Some(7));
             assert_eq!(node.count, Some(1));
@@ -410,12 +2191,12 @@ mod tests {
         {
             let node = nodes.get(6).unwrap();
             assert_eq!(node.pid, Some(7));
-            assert_eq!(node.node, "5".to_owned());
+            assert_eq!(&*node.node, "5");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "5".to_owned());
-            assert_eq!(node.parent_node, Some("3".to_owned()));
+            assert_eq!(node.label(), "5");
+            assert_eq!(node.parent_node.as_deref(), Some("3"));
             assert_eq!(node.parent_id, Some(4));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(8));
             assert_eq!(node.rgt, Some(9));
             assert_eq!(node.count, Some(0));
@@ -424,18 +2205,718 @@ mod tests {
         {
             let node = nodes.get(7).unwrap();
             assert_eq!(node.pid, Some(8));
-            assert_eq!(node.node, "5".to_owned());
+            assert_eq!(&*node.node, "5");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "5".to_owned());
-            assert_eq!(node.parent_node, Some("4__1".to_owned()));
+            assert_eq!(node.label(), "5");
+            assert_eq!(node.parent_node.as_deref(), Some("4__1"));
             assert_eq!(node.parent_id, Some(6));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(5));
             assert_eq!(node.rgt, Some(6));
             assert_eq!(node.count, Some(0));
         }
     }
 
+    #[test]
+    fn test_dag_cycle() {
+        let nodes = vec![
+            Node::new("1", "a"),
+            Node::new("2", "b").with_parent("1"),
+            Node::new("3", "c").with_parent("2"),
+            Node::new("4", "d").with_parent("3"),
+            Node::new("2", "b").with_parent("4"),
+        ];
+        let graph = Graph::new(nodes).unwrap();
+        assert_eq!(graph.is_dag(), true);
+
+        match graph.dag_to_tree() {
+            Err(Error::CycleError { ids, .. }) => assert_eq!(ids, ["3", "4", "2"]),
+            x => panic!("unexpected result: {:?}", x.map(|x| x.nodes.len())),
+        }
+
+        match graph.ensure_acyclic() {
+            Err(Error::CycleError { ids, rows }) => {
+                assert_eq!(ids, ["3", "4", "2"]);
+                assert_eq!(rows, [3, 4, 5]);
+            }
+            x => panic!("unexpected result: {:?}", x),
+        }
+
+        let mut graph = graph;
+        match graph.build_index() {
+            Err(Error::CycleError { ids, .. }) => assert_eq!(ids, ["3", "4", "2"]),
+            x => panic!("unexpected result: {:?}", x.map(|x| x.nodes.len())),
+        }
+    }
+
+    #[test]
+    fn test_separator() {
+        let dag_options = DagOptions {
+            separator: "#".to_owned(),
+            ..Default::default()
+        };
+        let graph = Graph::new(test_data()).unwrap();
+        let graph = graph.dag_to_tree_with(&dag_options).unwrap();
+        assert!(graph.nodes.iter().any(|x| &*x.node == "4#1"));
+
+        let complement_options = ComplementOptions {
+            prefix: "class".to_owned(),
+            separator: ":".to_owned(),
+            ..Default::default()
+        };
+        let graph = Graph::new(test_data()).unwrap();
+        let graph = graph.complement_leaf_with(&complement_options).unwrap();
+        assert_eq!(&*graph.nodes[0].node, "class:1");
+        assert_eq!(graph.nodes[2].parent_node.as_deref(), Some("class:1"));
+    }
+
+    #[test]
+    fn test_dup_naming() {
+        let mut reordered = test_data();
+        reordered[1..].reverse();
+        for naming in [DupNaming::ParentSuffix, DupNaming::PathHash] {
+            let options = DagOptions {
+                naming,
+                ..Default::default()
+            };
+            let nodes = |data: Vec<Node>| {
+                let graph = Graph::new(data).unwrap();
+                let graph = graph.dag_to_tree_with(&options).unwrap();
+                let mut nodes: Vec<_> = graph
+                    .nodes
+                    .iter()
+                    .map(|x| {
+                        (
+                            x.node.to_string(),
+                            x.parent_node.as_deref().map(str::to_owned),
+                        )
+                    })
+                    .collect();
+                nodes.sort();
+                nodes
+            };
+            let nodes_of_test_data = nodes(test_data());
+            assert_eq!(nodes_of_test_data, nodes(reordered.to_owned()));
+            assert!(!nodes_of_test_data.iter().any(|(x, _)| x == "4"));
+        }
+
+        let graph = Graph::new(test_data()).unwrap();
+        let tree = graph.dag_to_tree().unwrap();
+        assert_eq!(tree.find("4__1").unwrap().source_id(), "4");
+        let tree = tree.complement_leaf().unwrap();
+        assert_eq!(tree.find("c__4__1").unwrap().source_id(), "4");
+        let copies = graph.dag_to_tree().unwrap().copies();
+        let ids: Vec<_> = copies.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["4", "5"]);
+        assert_eq!(
+            copies[0].1,
+            vec![
+                NodeCopy {
+                    id: "4".to_owned(),
+                    parent: Some("1".to_owned()),
+                },
+                NodeCopy {
+                    id: "4__1".to_owned(),
+                    parent: Some("3".to_owned()),
+                },
+            ]
+        );
+
+        let (tree, dropped) = graph.primary_parent_tree().unwrap();
+        assert!(!tree.is_dag());
+        assert_eq!(
+            dropped,
+            vec![
+                ("4".to_owned(), "1".to_owned()),
+                ("5".to_owned(), "4".to_owned()),
+            ]
+        );
+
+        let mut nodes = test_data();
+        nodes[3] = nodes[3].to_owned().with_extra("primary_parent", "1");
+        let (tree, dropped) = Graph::new(nodes).unwrap().primary_parent_tree().unwrap();
+        assert_eq!(tree.find("4").unwrap().parent(), Some("1"));
+        assert_eq!(dropped[0], ("4".to_owned(), "3".to_owned()));
+
+        let options = DagOptions {
+            naming: DupNaming::ParentSuffix,
+            ..Default::default()
+        };
+        let graph = graph.dag_to_tree_with(&options).unwrap();
+        let ids: Vec<_> = graph.nodes.iter().map(|x| x.id()).collect();
+        assert!(ids.contains(&"4__1") && ids.contains(&"4__3"));
+    }
+
+    #[test]
+    fn test_sort_children() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("10", "b").with_parent("1").with_extra("rank", 2),
+            Node::new("9", "c").with_parent("1"),
+            Node::new("2", "a").with_parent("1").with_extra("rank", 10),
+        ];
+        for (order, expected) in [
+            (SiblingOrder::Input, vec!["1", "10", "9", "2"]),
+            (SiblingOrder::Id, vec!["1", "2", "9", "10"]),
+            (SiblingOrder::Label, vec!["1", "2", "10", "9"]),
+            (
+                SiblingOrder::Column("rank".to_owned()),
+                vec!["1", "10", "2", "9"],
+            ),
+        ] {
+            let mut graph = Graph::new(nodes.to_owned()).unwrap();
+            graph.sort_children(&order);
+            graph.build_index().unwrap();
+            let mut nodes: Vec<_> = graph.nodes.iter().collect();
+            nodes.sort_by_key(|x| x.lft);
+            let ids: Vec<_> = nodes.iter().map(|x| x.id()).collect();
+            assert_eq!(ids, expected);
+        }
+    }
+
+    #[test]
+    fn test_number_siblings() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a")
+                .with_parent("1")
+                .with_extra("position", 20),
+            Node::new("3", "b")
+                .with_parent("1")
+                .with_extra("position", 5),
+            Node::new("4", "c")
+                .with_parent("2")
+                .with_extra("position", 7),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        graph.sort_children(&SiblingOrder::Column("position".to_owned()));
+        graph.build_index().unwrap();
+        graph.number_siblings("position");
+
+        let positions: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.extra()["position"].as_u64()))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                ("1", Some(1)),
+                ("3", Some(1)),
+                ("4", Some(1)),
+                ("2", Some(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gap() {
+        let mut graph = Graph::new(test_data()[..3].to_vec()).unwrap();
+        graph
+            .build_index_with(&NumberingOptions {
+                gap: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        let nodes: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|x| (x.lft, x.rgt, x.descendant_count))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                (Some(10), Some(60), Some(2)),
+                (Some(20), Some(50), Some(1)),
+                (Some(30), Some(40), Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gap_counts() {
+        let mut graph = Graph::new(test_data()[..3].to_vec()).unwrap();
+        graph
+            .build_index_with(&NumberingOptions {
+                gap: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        graph.insert(Node::new("9", "9").with_parent("2")).unwrap();
+
+        let nodes: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.count, x.descendant_count, x.leaf_count))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("1", Some(1), Some(3), Some(2)),
+                ("2", Some(2), Some(2), Some(2)),
+                ("3", Some(0), Some(0), Some(0)),
+                ("9", Some(0), Some(0), Some(0)),
+            ]
+        );
+
+        // the node is numbered within the free numbers of its parent without shifting the others
+        let bounds: Vec<_> = graph.nodes.iter().map(|x| (x.lft, x.rgt)).collect();
+        assert_eq!(
+            bounds,
+            vec![
+                (Some(10), Some(60)),
+                (Some(20), Some(50)),
+                (Some(30), Some(40)),
+                (Some(43), Some(47))
+            ]
+        );
+
+        let bounds: Vec<_> = graph.split(true)[0]
+            .iter()
+            .map(|x| (x.lft, x.rgt))
+            .collect();
+        assert_eq!(
+            bounds,
+            vec![
+                (Some(10), Some(40)),
+                (Some(20), Some(30)),
+                (Some(33), Some(37))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_renumber() {
+        let mut graph = Graph::new(test_data()[..3].to_vec()).unwrap();
+        graph
+            .build_index_with(&NumberingOptions {
+                gap: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        graph.delete_subtree("3", false).unwrap();
+        graph.insert(Node::new("9", "9").with_parent("1")).unwrap();
+        graph.renumber().unwrap();
+
+        let nodes: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.pid, x.lft, x.rgt))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("1", Some(1), Some(1), Some(6)),
+                ("2", Some(2), Some(2), Some(3)),
+                ("9", Some(3), Some(4), Some(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_offsets() {
+        let mut graph = Graph::new(test_data()[..3].to_vec()).unwrap();
+        graph
+            .build_index_with(&NumberingOptions {
+                gap: 2,
+                lft_start: Some(101),
+                pid_offset: 50,
+                ..Default::default()
+            })
+            .unwrap();
+        graph.build_paths(&PathOptions::default());
+        let nodes: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|x| (x.pid, x.parent_id, x.lft, x.rgt, x.descendant_count))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                (Some(51), None, Some(101), Some(111), Some(2)),
+                (Some(52), Some(51), Some(103), Some(109), Some(1)),
+                (Some(53), Some(52), Some(105), Some(107), Some(0)),
+            ]
+        );
+        assert_eq!(graph.nodes[2].path(), Some("1/2/3"));
+
+        let options = NumberingOptions {
+            pid_from_id: true,
+            ..Default::default()
+        };
+        let nodes = vec![Node::new("30", "a"), Node::new("10", "b").with_parent("30")];
+        let mut graph = Graph::new(nodes).unwrap();
+        graph.build_index_with(&options).unwrap();
+        let pids: Vec<_> = graph.nodes.iter().map(|x| (x.pid, x.parent_id)).collect();
+        assert_eq!(pids, vec![(Some(10), Some(30)), (Some(30), None)]);
+
+        let mut graph = Graph::new(test_data()).unwrap().dag_to_tree().unwrap();
+        assert!(matches!(
+            graph.build_index_with(&options),
+            Err(crate::Error::NonIntegerIdError(x)) if x == "4__1"
+        ));
+    }
+
+    #[test]
+    fn test_complement_options() {
+        let options = ComplementOptions {
+            leaf_label: Some("{label} ({id}, unclassified)".to_owned()),
+            keep_id: KeepId::Classification,
+            ..Default::default()
+        };
+        let graph = Graph::new(test_data()[..2].to_vec()).unwrap();
+        let graph = graph.complement_leaf_with(&options).unwrap();
+        let nodes: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.parent(), x.label()))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("1", None, "1"),
+                ("c__1", Some("1"), "1 (1, unclassified)"),
+                ("2", Some("1"), "2"),
+                ("c__2", Some("2"), "2 (2, unclassified)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_complement_root_not_first() {
+        let nodes = vec![Node::new("2", "b").with_parent("1"), Node::new("1", "a")];
+        let mut graph = Graph::new(nodes).unwrap().complement_leaf().unwrap();
+        graph.build_index().unwrap();
+
+        let root = graph.nodes.iter().find(|x| x.id() == "c__1").unwrap();
+        assert_eq!((root.lft(), root.rgt()), (Some(1), Some(8)));
+    }
+
+    #[test]
+    fn test_complement_internal_only() {
+        let graph = Graph::new(test_data()[..3].to_vec()).unwrap();
+        for (keep_id, expected) in [
+            (
+                KeepId::Leaf,
+                vec![
+                    ("c__1", None),
+                    ("1", Some("c__1")),
+                    ("c__2", Some("c__1")),
+                    ("2", Some("c__2")),
+                    ("3", Some("c__2")),
+                ],
+            ),
+            (
+                KeepId::Classification,
+                vec![
+                    ("1", None),
+                    ("c__1", Some("1")),
+                    ("2", Some("1")),
+                    ("c__2", Some("2")),
+                    ("3", Some("2")),
+                ],
+            ),
+        ] {
+            let options = ComplementOptions {
+                keep_id,
+                internal_only: true,
+                ..Default::default()
+            };
+            let graph = graph.complement_leaf_with(&options).unwrap();
+            let nodes: Vec<_> = graph.nodes.iter().map(|x| (x.id(), x.parent())).collect();
+            assert_eq!(nodes, expected);
+        }
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let depth = 200_000;
+        let nodes = (0..depth)
+            .map(|i| {
+                let node = Node::new(i.to_string(), i.to_string());
+                if i == 0 {
+                    node
+                } else {
+                    node.with_parent((i - 1).to_string())
+                }
+            })
+            .collect();
+
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let root = graph.nodes.first().unwrap();
+        assert_eq!(root.lft, Some(1));
+        assert_eq!(root.rgt, Some(depth * 2));
+
+        let last = graph.nodes.last().unwrap();
+        assert_eq!(last.depth, Some(depth - 1));
+        assert_eq!(last.lft, Some(depth));
+        assert_eq!(last.rgt, Some(depth + 1));
+        assert_eq!(last.count, Some(0));
+    }
+
+    #[test]
+    fn test_forest() {
+        let nodes = vec![
+            Node::new("1", "1"),
+            Node::new("2", "2"),
+            Node::new("3", "3").with_parent("2"),
+        ];
+
+        let mut graph = Graph::from_forest(nodes, Node::new("root", "root")).unwrap();
+        let graph = graph.build_index().unwrap();
+        let nodes = &graph.nodes;
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(&*nodes[0].node, "root");
+        assert_eq!((nodes[0].lft, nodes[0].rgt), (Some(1), Some(8)));
+        assert_eq!(nodes[1].parent_id, Some(1));
+        assert_eq!(nodes[2].parent_id, Some(1));
+        assert_eq!((nodes[3].lft, nodes[3].rgt), (Some(5), Some(6)));
+        assert_eq!(nodes[0].descendant_count, Some(3));
+        assert_eq!(nodes[0].leaf_count, Some(2));
+        assert_eq!(nodes[2].leaf_count, Some(1));
+        assert_eq!(nodes[3].leaf_count, Some(0));
+
+        let nodes = vec![Node::new("1", "1"), Node::new("2", "2")];
+        assert!(Graph::from_forest(nodes, Node::new("1", "root")).is_err());
+
+        let nodes = vec![
+            Node::new("1", "a"),
+            Node::new("2", "b").with_parent("1"),
+            Node::new("3", "c"),
+        ];
+        match Graph::new(nodes) {
+            Err(crate::Error::MultipleRootNodeError(roots)) => {
+                let roots: Vec<_> = roots.iter().map(|x| (x.row, x.descendant_count)).collect();
+                assert_eq!(roots, vec![(1, 1), (3, 0)]);
+            }
+            x => panic!("unexpected result: {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let mut nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("1"),
+        ];
+        nodes[0].pid = Some(10);
+        nodes[1].pid = Some(5);
+        nodes[0].lft = Some(3);
+
+        let mut graph = Graph::new(nodes).unwrap();
+        graph.rebuild_index().unwrap();
+        let nodes: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|x| (x.pid, x.parent_id, x.lft, x.rgt))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                (Some(5), Some(10), Some(2), Some(3)),
+                (Some(10), None, Some(1), Some(6)),
+                (Some(11), Some(10), Some(4), Some(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "c").with_parent("2"),
+            Node::new("5", "d").with_parent("1"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let ids = |nodes: Vec<&Node>| nodes.iter().map(|x| x.id().to_owned()).collect::<Vec<_>>();
+        assert_eq!(ids(graph.ancestors("3").unwrap()), vec!["1", "2"]);
+        assert_eq!(ids(graph.descendants("2").unwrap()), vec!["3", "4"]);
+        assert_eq!(ids(graph.subtree("3").unwrap()), vec!["3"]);
+        assert!(graph.subtree("missing").is_err());
+
+        let lca = |ids: &[&str]| graph.lowest_common_ancestor(ids).unwrap().id().to_owned();
+        assert_eq!(lca(&["3", "4"]), "2");
+        assert_eq!(lca(&["3", "5"]), "1");
+        assert_eq!(lca(&["2", "3"]), "2");
+    }
+
+    #[test]
+    fn test_edit() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "c").with_parent("1"),
+        ];
+        let mut expected = Graph::new(nodes.to_owned()).unwrap();
+        expected.build_index().unwrap();
+
+        let bounds = |graph: &Graph| {
+            let mut nodes: Vec<_> = graph
+                .nodes
+                .iter()
+                .map(|x| (x.id().to_owned(), x.parent_id, x.lft, x.rgt, x.count))
+                .collect();
+            nodes.sort();
+            nodes
+        };
+
+        let mut graph = Graph::new(nodes[..3].to_vec()).unwrap();
+        graph.build_index().unwrap();
+        graph.insert(Node::new("4", "c").with_parent("1")).unwrap();
+        assert_eq!(bounds(&graph), bounds(&expected));
+        assert!(graph.insert(Node::new("4", "c").with_parent("1")).is_err());
+
+        graph.move_subtree("3", "4").unwrap();
+        graph.move_subtree("3", "2").unwrap();
+        assert_eq!(bounds(&graph), bounds(&expected));
+        assert!(graph.move_subtree("2", "3").is_err());
+
+        graph.delete_subtree("2", true).unwrap();
+        assert_eq!(graph.find("3").unwrap().parent(), Some("1"));
+        assert_eq!(graph.find("3").unwrap().lft(), Some(2));
+        graph.delete_subtree("4", false).unwrap();
+        assert_eq!(graph.find("1").unwrap().rgt(), Some(4));
+        assert!(graph.delete_subtree("1", false).is_err());
+
+        graph.rename("3", "B").unwrap();
+        assert_eq!(graph.find("3").unwrap().label(), "B");
+    }
+
+    #[test]
+    fn test_split() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "c").with_parent("1"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        graph.build_index().unwrap();
+
+        let bounds = |subtrees: Vec<Vec<Node>>| {
+            subtrees
+                .iter()
+                .map(|x| x.iter().map(|x| (x.lft, x.rgt)).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            bounds(graph.split(false)),
+            vec![
+                vec![(Some(2), Some(5)), (Some(3), Some(4))],
+                vec![(Some(6), Some(7))]
+            ]
+        );
+        assert_eq!(
+            bounds(graph.split(true)),
+            vec![
+                vec![(Some(1), Some(4)), (Some(2), Some(3))],
+                vec![(Some(1), Some(2))]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paths() {
+        let nodes = vec![
+            Node::new("1", "Animals"),
+            Node::new("3", "Big cats").with_parent("2"),
+            Node::new("2", "Mammals").with_parent("1"),
+        ];
+
+        let mut graph = Graph::new(nodes).unwrap();
+        graph.build_index().unwrap();
+
+        let nodes = &graph.build_paths(&PathOptions::default()).nodes;
+        assert_eq!(nodes[1].path(), Some("1/2/3"));
+
+        let options = PathOptions {
+            segment: PathSegment::Label,
+            ltree: true,
+            ..Default::default()
+        };
+        let nodes = &graph.build_paths(&options).nodes;
+        assert_eq!(nodes[0].path(), Some("Animals"));
+        assert_eq!(nodes[1].path(), Some("Animals.Mammals.Big_cats"));
+
+        let nodes = &graph.build_label_paths(" > ").nodes;
+        assert_eq!(nodes[1].label_path(), Some("Animals > Mammals > Big cats"));
+    }
+
+    #[test]
+    fn test_hashes() {
+        let hashes = |nodes: Vec<Node>| {
+            let mut graph = Graph::new(nodes).unwrap();
+            graph.build_index().unwrap();
+            let nodes = &graph.build_hashes().nodes;
+            nodes
+                .iter()
+                .map(|x| (x.id().to_owned(), x.hash().unwrap().to_owned()))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "c").with_parent("1"),
+        ];
+        let before = hashes(nodes.to_owned());
+        assert_eq!(before["1"].len(), 16);
+        assert_eq!(before, hashes(nodes.to_owned()));
+
+        let mut changed = nodes;
+        changed[3] = Node::new("4", "renamed").with_parent("1");
+        let after = hashes(changed);
+        assert_eq!(before["2"], after["2"]);
+        assert_ne!(before["4"], after["4"]);
+        assert_ne!(before["1"], after["1"]);
+    }
+
+    #[test]
+    fn test_column_mapping() {
+        let mapping = ColumnMapping {
+            id: "code".to_owned(),
+            parent: "parent_code".to_owned(),
+            label: "name".to_owned(),
+            pid: "id".to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(mapping.field_name("code"), "id");
+        assert_eq!(mapping.field_name("parent_code"), "parent");
+        assert_eq!(mapping.field_name("name"), "label");
+        assert_eq!(mapping.field_name("id"), "pid");
+        assert_eq!(mapping.field_name("pid"), "");
+        assert_eq!(mapping.field_name("leaf"), "leaf");
+
+        let default = ColumnMapping::default();
+        let output = [
+            "id",
+            "classification",
+            "classification_parent",
+            "classification_label",
+        ];
+        assert_eq!(*default.detect(output), ColumnMapping::output());
+        assert_eq!(*default.detect(["id", "parent", "label"]), default);
+        // the columns of an input which are named like the output are kept
+        let input = [
+            "classification",
+            "classification_parent",
+            "classification_label",
+            "label",
+        ];
+        assert_eq!(*default.detect(input), default);
+        assert_eq!(*mapping.detect(output), mapping);
+    }
+
     #[test]
     fn test_dag_complement_leaf() {
         let graph = Graph::new(test_data()).unwrap();
@@ -454,12 +2935,12 @@ mod tests {
         {
             let node = nodes.get(0).unwrap();
             assert_eq!(node.pid, Some(1));
-            assert_eq!(node.node, "c__1".to_owned());
+            assert_eq!(&*node.node, "c__1");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "1".to_owned());
+            assert_eq!(node.label(), "1");
             assert_eq!(node.parent_node, None);
             assert_eq!(node.parent_id, None);
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(1));
             assert_eq!(node.rgt, Some(32));
             assert_eq!(node.count, Some(3));
@@ -468,12 +2949,12 @@ mod tests {
         {
             let node = nodes.get(1).unwrap();
             assert_eq!(node.pid, Some(2));
-            assert_eq!(node.node, "1".to_owned());
+            assert_eq!(&*node.node, "1");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "1".to_owned());
-            assert_eq!(node.parent_node, Some("c__1".to_owned()));
+            assert_eq!(node.label(), "1");
+            assert_eq!(node.parent_node.as_deref(), Some("c__1"));
             assert_eq!(node.parent_id, Some(1));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(2));
             assert_eq!(node.rgt, Some(3));
             assert_eq!(node.count, Some(0));
@@ -482,12 +2963,12 @@ mod tests {
         {
             let node = nodes.get(2).unwrap();
             assert_eq!(node.pid, Some(3));
-            assert_eq!(node.node, "c__2".to_owned());
+            assert_eq!(&*node.node, "c__2");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "2".to_owned());
-            assert_eq!(node.parent_node, Some("c__1".to_owned()));
+            assert_eq!(node.label(), "2");
+            assert_eq!(node.parent_node.as_deref(), Some("c__1"));
             assert_eq!(node.parent_id, Some(1));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(4));
             assert_eq!(node.rgt, Some(23));
             assert_eq!(node.count, Some(2));
@@ -496,12 +2977,12 @@ mod tests {
         {
             let node = nodes.get(3).unwrap();
             assert_eq!(node.pid, Some(4));
-            assert_eq!(node.node, "c__4".to_owned());
+            assert_eq!(&*node.node, "c__4");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "4".to_owned());
-            assert_eq!(node.parent_node, Some("c__1".to_owned()));
+            assert_eq!(node.label(), "4");
+            assert_eq!(node.parent_node.as_deref(), Some("c__1"));
             assert_eq!(node.parent_id, Some(1));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(24));
             assert_eq!(node.rgt, Some(31));
             assert_eq!(node.count, Some(2));
@@ -510,12 +2991,12 @@ mod tests {
         {
             let node = nodes.get(4).unwrap();
             assert_eq!(node.pid, Some(5));
-            assert_eq!(node.node, "2".to_owned());
+            assert_eq!(&*node.node, "2");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "2".to_owned());
-            assert_eq!(node.parent_node, Some("c__2".to_owned()));
+            assert_eq!(node.label(), "2");
+            assert_eq!(node.parent_node.as_deref(), Some("c__2"));
             assert_eq!(node.parent_id, Some(3));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(5));
             assert_eq!(node.rgt, Some(6));
             assert_eq!(node.count, Some(0));
@@ -524,12 +3005,12 @@ mod tests {
         {
             let node = nodes.get(5).unwrap();
             assert_eq!(node.pid, Some(6));
-            assert_eq!(node.node, "c__3".to_owned());
+            assert_eq!(&*node.node, "c__3");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "3".to_owned());
-            assert_eq!(node.parent_node, Some("c__2".to_owned()));
+            assert_eq!(node.label(), "3");
+            assert_eq!(node.parent_node.as_deref(), Some("c__2"));
             assert_eq!(node.parent_id, Some(3));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(7));
             assert_eq!(node.rgt, Some(22));
             assert_eq!(node.count, Some(3));
@@ -538,12 +3019,12 @@ mod tests {
         {
             let node = nodes.get(6).unwrap();
             assert_eq!(node.pid, Some(7));
-            assert_eq!(node.node, "4".to_owned());
+            assert_eq!(&*node.node, "4");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "4".to_owned());
-            assert_eq!(node.parent_node, Some("c__4".to_owned()));
+            assert_eq!(node.label(), "4");
+            assert_eq!(node.parent_node.as_deref(), Some("c__4"));
             assert_eq!(node.parent_id, Some(4));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(25));
             assert_eq!(node.rgt, Some(26));
             assert_eq!(node.count, Some(0));
@@ -552,12 +3033,12 @@ mod tests {
         {
             let node = nodes.get(7).unwrap();
             assert_eq!(node.pid, Some(8));
-            assert_eq!(node.node, "c__5".to_owned());
+            assert_eq!(&*node.node, "c__5");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "5".to_owned());
-            assert_eq!(node.parent_node, Some("c__4".to_owned()));
+            assert_eq!(node.label(), "5");
+            assert_eq!(node.parent_node.as_deref(), Some("c__4"));
             assert_eq!(node.parent_id, Some(4));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(27));
             assert_eq!(node.rgt, Some(30));
             assert_eq!(node.count, Some(1));
@@ -566,12 +3047,12 @@ mod tests {
         {
             let node = nodes.get(8).unwrap();
             assert_eq!(node.pid, Some(9));
-            assert_eq!(node.node, "3".to_owned());
+            assert_eq!(&*node.node, "3");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "3".to_owned());
-            assert_eq!(node.parent_node, Some("c__3".to_owned()));
+            assert_eq!(node.label(), "3");
+            assert_eq!(node.parent_node.as_deref(), Some("c__3"));
             assert_eq!(node.parent_id, Some(6));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(8));
             assert_eq!(node.rgt, Some(9));
             assert_eq!(node.count, Some(0));
@@ -580,12 +3061,12 @@ mod tests {
         {
             let node = nodes.get(9).unwrap();
             assert_eq!(node.pid, Some(10));
-            assert_eq!(node.node, "c__4__1".to_owned());
-            assert_eq!(node.origin, Some("c__4".to_owned()));
-            assert_eq!(node.label, "4".to_owned());
-            assert_eq!(node.parent_node, Some("c__3".to_owned()));
+            assert_eq!(&*node.node, "c__4__1");
+            assert_eq!(node.origin.as_deref(), Some("c__4"));
+            assert_eq!(node.label(), "4");
+            assert_eq!(node.parent_node.as_deref(), Some("c__3"));
             assert_eq!(node.parent_id, Some(6));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(10));
             assert_eq!(node.rgt, Some(17));
             assert_eq!(node.count, Some(2));
@@ -594,12 +3075,12 @@ mod tests {
         {
             let node = nodes.get(10).unwrap();
             assert_eq!(node.pid, Some(11));
-            assert_eq!(node.node, "c__5__1".to_owned());
-            assert_eq!(node.origin, Some("c__5".to_owned()));
-            assert_eq!(node.label, "5".to_owned());
-            assert_eq!(node.parent_node, Some("c__3".to_owned()));
+            assert_eq!(&*node.node, "c__5__1");
+            assert_eq!(node.origin.as_deref(), Some("c__5"));
+            assert_eq!(node.label(), "5");
+            assert_eq!(node.parent_node.as_deref(), Some("c__3"));
             assert_eq!(node.parent_id, Some(6));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(18));
             assert_eq!(node.rgt, Some(21));
             assert_eq!(node.count, Some(1));
@@ -608,12 +3089,12 @@ mod tests {
         {
             let node = nodes.get(11).unwrap();
             assert_eq!(node.pid, Some(12));
-            assert_eq!(node.node, "5".to_owned());
+            assert_eq!(&*node.node, "5");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "5".to_owned());
-            assert_eq!(node.parent_node, Some("c__5".to_owned()));
+            assert_eq!(node.label(), "5");
+            assert_eq!(node.parent_node.as_deref(), Some("c__5"));
             assert_eq!(node.parent_id, Some(8));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(28));
             assert_eq!(node.rgt, Some(29));
             assert_eq!(node.count, Some(0));
@@ -622,12 +3103,12 @@ mod tests {
         {
             let node = nodes.get(12).unwrap();
             assert_eq!(node.pid, Some(13));
-            assert_eq!(node.node, "4".to_owned());
+            assert_eq!(&*node.node, "4");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "4".to_owned());
-            assert_eq!(node.parent_node, Some("c__4__1".to_owned()));
+            assert_eq!(node.label(), "4");
+            assert_eq!(node.parent_node.as_deref(), Some("c__4__1"));
             assert_eq!(node.parent_id, Some(10));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(11));
             assert_eq!(node.rgt, Some(12));
             assert_eq!(node.count, Some(0));
@@ -636,12 +3117,12 @@ mod tests {
         {
             let node = nodes.get(13).unwrap();
             assert_eq!(node.pid, Some(14));
-            assert_eq!(node.node, "c__5__2".to_owned());
-            assert_eq!(node.origin, Some("c__5".to_owned()));
-            assert_eq!(node.label, "5".to_owned());
-            assert_eq!(node.parent_node, Some("c__4__1".to_owned()));
+            assert_eq!(&*node.node, "c__5__2");
+            assert_eq!(node.origin.as_deref(), Some("c__5"));
+            assert_eq!(node.label(), "5");
+            assert_eq!(node.parent_node.as_deref(), Some("c__4__1"));
             assert_eq!(node.parent_id, Some(10));
-            assert_eq!(node.leaf, false);
+            assert_eq!(node.is_leaf(), false);
             assert_eq!(node.lft, Some(13));
             assert_eq!(node.rgt, Some(16));
             assert_eq!(node.count, Some(1));
@@ -650,12 +3131,12 @@ mod tests {
         {
             let node = nodes.get(14).unwrap();
             assert_eq!(node.pid, Some(15));
-            assert_eq!(node.node, "5".to_owned());
+            assert_eq!(&*node.node, "5");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "5".to_owned());
-            assert_eq!(node.parent_node, Some("c__5__1".to_owned()));
+            assert_eq!(node.label(), "5");
+            assert_eq!(node.parent_node.as_deref(), Some("c__5__1"));
             assert_eq!(node.parent_id, Some(11));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(19));
             assert_eq!(node.rgt, Some(20));
             assert_eq!(node.count, Some(0));
@@ -664,15 +3145,107 @@ mod tests {
         {
             let node = nodes.get(15).unwrap();
             assert_eq!(node.pid, Some(16));
-            assert_eq!(node.node, "5".to_owned());
+            assert_eq!(&*node.node, "5");
             assert_eq!(node.origin, None);
-            assert_eq!(node.label, "5".to_owned());
-            assert_eq!(node.parent_node, Some("c__5__2".to_owned()));
+            assert_eq!(node.label(), "5");
+            assert_eq!(node.parent_node.as_deref(), Some("c__5__2"));
             assert_eq!(node.parent_id, Some(14));
-            assert_eq!(node.leaf, true);
+            assert_eq!(node.is_leaf(), true);
             assert_eq!(node.lft, Some(14));
             assert_eq!(node.rgt, Some(15));
             assert_eq!(node.count, Some(0));
         }
     }
+
+    proptest! {
+        #[test]
+        fn test_index_invariants(nodes in trees(64)) {
+            let n = nodes.len();
+            let mut graph = Graph::new(nodes).unwrap();
+            let graph = graph.build_index().unwrap();
+
+            // lft and rgt use every number from 1 to 2n once
+            let mut bounds: Vec<_> = graph
+                .nodes
+                .iter()
+                .flat_map(|x| [x.lft().unwrap(), x.rgt().unwrap()])
+                .collect();
+            bounds.sort_unstable();
+            prop_assert_eq!(bounds, (1..=2 * n).collect::<Vec<_>>());
+
+            let nodes: HashMap<_, _> = graph.nodes.iter().map(|x| (x.pid(), x)).collect();
+            let mut children = HashMap::new();
+            for node in &graph.nodes {
+                prop_assert!(node.lft() < node.rgt());
+                if let Some(parent) = node.parent_id() {
+                    let parent = nodes[&Some(parent)];
+                    prop_assert!(parent.lft() < node.lft() && node.rgt() < parent.rgt());
+                    *children.entry(parent.pid()).or_insert(0) += 1;
+                }
+            }
+            for node in &graph.nodes {
+                prop_assert_eq!(node.count(), Some(*children.get(&node.pid()).unwrap_or(&0)));
+            }
+        }
+
+        #[test]
+        fn test_into_indexed(nodes in trees(64), reverse in any::<bool>()) {
+            let mut nodes = nodes;
+            if reverse {
+                // the nodes are entered in the reverse order of their pid
+                nodes.reverse();
+            }
+            let options = NumberingOptions {
+                gap: 3,
+                ..Default::default()
+            };
+            let values = |x: &Node| {
+                (
+                    x.pid,
+                    x.parent_id,
+                    x.lft,
+                    x.rgt,
+                    x.count,
+                    x.depth,
+                    x.descendant_count,
+                    x.leaf_count,
+                )
+            };
+
+            let mut graph = Graph::new(nodes.to_owned()).unwrap();
+            graph.build_index_with(&options).unwrap();
+            let expected: Vec<_> = graph.nodes.iter().map(values).collect();
+
+            let graph = Graph::new(nodes).unwrap();
+            let indexed = graph.into_indexed(&options).unwrap();
+            prop_assert_eq!(indexed.map(|x| values(&x)).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_into_indexed_pid_from_id() {
+        let nodes = vec![
+            Node::new("3", "c").with_parent("1"),
+            Node::new("1", "a"),
+            Node::new("2", "b").with_parent("3"),
+        ];
+        let options = NumberingOptions {
+            pid_from_id: true,
+            ..Default::default()
+        };
+        let graph = Graph::new(nodes).unwrap();
+        let nodes: Vec<_> = graph
+            .into_indexed(&options)
+            .unwrap()
+            .map(|x| (x.pid, x.lft, x.rgt, x.parent_id))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                (Some(1), Some(1), Some(6), None),
+                (Some(2), Some(3), Some(4), Some(3)),
+                (Some(3), Some(2), Some(5), Some(1)),
+            ]
+        );
+    }
 }
@@ -0,0 +1,179 @@
+//! Structural comparison of two hierarchies.
+
+use crate::data::Node;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Kind of a [`Change`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// The node is only in the new hierarchy.
+    Added,
+    /// The node is only in the old hierarchy.
+    Removed,
+    /// The parents of the node are different.
+    Moved,
+    /// The label of the node is different.
+    Relabeled,
+}
+
+/// A difference of a node between two hierarchies.
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub id: String,
+    /// Old parents or label, comma-separated if the node has multiple parents.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// New parents or label, comma-separated if the node has multiple parents.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+struct Entry<'a> {
+    label: &'a str,
+    parents: Vec<&'a str>,
+}
+
+/// Groups the nodes by id in the order of appearance, collecting the parents of every occurrence.
+fn entries(nodes: &[Node]) -> (Vec<&str>, HashMap<&str, Entry<'_>>) {
+    let mut ids = Vec::new();
+    let mut entries = HashMap::new();
+
+    for node in nodes {
+        let entry = entries.entry(node.id()).or_insert_with(|| {
+            ids.push(node.id());
+            Entry {
+                label: node.label(),
+                parents: Vec::new(),
+            }
+        });
+        if let Some(parent) = node.parent() {
+            entry.parents.push(parent);
+        }
+    }
+    for entry in entries.values_mut() {
+        entry.parents.sort_unstable();
+        entry.parents.dedup();
+    }
+
+    (ids, entries)
+}
+
+fn parents(entry: &Entry) -> Option<String> {
+    if entry.parents.is_empty() {
+        None
+    } else {
+        Some(entry.parents.join(","))
+    }
+}
+
+/// Compares the nodes of `old` and `new` by id, reporting the removed, moved and relabeled nodes
+/// in the order of `old` followed by the added nodes in the order of `new`.
+pub fn diff(old: &[Node], new: &[Node]) -> Vec<Change> {
+    let (old_ids, old) = entries(old);
+    let (new_ids, new) = entries(new);
+    let mut changes = Vec::new();
+
+    for id in old_ids {
+        let before = &old[id];
+        let after = match new.get(id) {
+            Some(x) => x,
+            None => {
+                changes.push(Change {
+                    kind: ChangeKind::Removed,
+                    id: id.to_owned(),
+                    from: parents(before),
+                    to: None,
+                });
+                continue;
+            }
+        };
+
+        if before.parents != after.parents {
+            changes.push(Change {
+                kind: ChangeKind::Moved,
+                id: id.to_owned(),
+                from: parents(before),
+                to: parents(after),
+            });
+        }
+        if before.label != after.label {
+            changes.push(Change {
+                kind: ChangeKind::Relabeled,
+                id: id.to_owned(),
+                from: Some(before.label.to_owned()),
+                to: Some(after.label.to_owned()),
+            });
+        }
+    }
+
+    for id in new_ids.into_iter().filter(|x| !old.contains_key(x)) {
+        changes.push(Change {
+            kind: ChangeKind::Added,
+            id: id.to_owned(),
+            from: None,
+            to: parents(&new[id]),
+        });
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, ChangeKind};
+    use crate::data::Node;
+
+    #[test]
+    fn test_diff() {
+        let old = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "c").with_parent("1"),
+        ];
+        let new = vec![
+            Node::new("1", "root"),
+            Node::new("2", "A").with_parent("1"),
+            Node::new("3", "b").with_parent("1"),
+            Node::new("5", "d").with_parent("3"),
+        ];
+
+        let changes: Vec<_> = diff(&old, &new)
+            .into_iter()
+            .map(|x| (x.kind, x.id, x.from, x.to))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    ChangeKind::Relabeled,
+                    "2".to_owned(),
+                    Some("a".to_owned()),
+                    Some("A".to_owned())
+                ),
+                (
+                    ChangeKind::Moved,
+                    "3".to_owned(),
+                    Some("2".to_owned()),
+                    Some("1".to_owned())
+                ),
+                (
+                    ChangeKind::Removed,
+                    "4".to_owned(),
+                    Some("1".to_owned()),
+                    None
+                ),
+                (
+                    ChangeKind::Added,
+                    "5".to_owned(),
+                    None,
+                    Some("3".to_owned())
+                ),
+            ]
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+}
@@ -1,21 +1,150 @@
+use crate::data::RootNode;
+use crate::format::Format;
+use crate::resolve::OrphanedComponent;
+use serde::Serialize;
 use std::io;
+use strum::IntoStaticStr;
 use thiserror::Error;
 
 pub type Result<T, E = Error> = core::result::Result<T, E>;
 
-#[derive(Error, Debug)]
+fn join_lines<T: std::fmt::Display>(errors: &[T]) -> String {
+    errors
+        .iter()
+        .map(|x| format!("  {}", x))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Maximum number of the roots listed in the message of [`Error::MultipleRootNodeError`].
+const MAX_ROOTS_SHOWN: usize = 20;
+
+fn list_roots(roots: &[RootNode]) -> String {
+    let shown = join_lines(&roots[..roots.len().min(MAX_ROOTS_SHOWN)]);
+    match roots.len().checked_sub(MAX_ROOTS_SHOWN) {
+        Some(more) if more > 0 => format!("{}\n  ... and {} more", shown, more),
+        _ => shown,
+    }
+}
+
+/// What went wrong, so that a caller can tell the errors apart without matching every variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    /// Options which can not be used together or are missing.
+    Usage,
+    /// The input could not be parsed.
+    Parse,
+    /// The input was parsed but does not make a hierarchy that can be processed.
+    Structure,
+    /// Reading or writing a file or a database failed.
+    Io,
+    /// Any other error.
+    Other,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Error, Debug, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
 pub enum Error {
     #[error("{0}")]
     RuntimeError(String),
 
-    #[error("Parent node not found: {0}")]
-    ParentNodeNotFoundError(String),
+    #[error("Parent node not found: {parent} of {id}")]
+    ParentNodeNotFoundError {
+        id: String,
+        parent: String,
+        /// Position of the node among the nodes, starting from 1, if it is one of them.
+        row: Option<usize>,
+    },
+
+    #[error("Node not found: {0}")]
+    NodeNotFoundError(String),
 
     #[error("Root node not found. Remove `\"parent\"` from root node or set it to `null`")]
     RootNodeNotFoundError(),
 
-    #[error("Multiple nodes with `\"parent\"` is null were found.")]
-    MultipleRootNodeError(),
+    #[error(
+        "Multiple nodes with `\"parent\"` is null were found:\n{}",
+        list_roots(.0)
+    )]
+    MultipleRootNodeError(Vec<RootNode>),
+
+    #[error("Nodes not reachable from the root were found:\n{}", join_lines(.0))]
+    OrphanedNodesError(Vec<OrphanedComponent>),
+
+    #[error("Virtual root node id is already used by another node: {0}")]
+    VirtualRootConflictError(String),
+
+    #[error("Node id is already used: {0}")]
+    NodeConflictError(String),
+
+    #[error("Node is its own parent: {}", .0.join(", "))]
+    SelfParentError(Vec<String>),
+
+    #[error("Nodes on a cycle of parent links were found: {}", .ids.join(", "))]
+    CycleError {
+        ids: Vec<String>,
+        /// Positions of the nodes among the nodes, starting from 1.
+        rows: Vec<usize>,
+    },
+
+    #[error("Node marked as a leaf is the parent of other nodes: {}", .0.join(", "))]
+    LeafParentError(Vec<String>),
+
+    #[error("Node appears more than once with the same parent: {0}")]
+    DuplicateNodeError(String),
+
+    #[error("Node is not indexed, `lft` and `rgt` are required: {0}")]
+    NotIndexedError(String),
+
+    #[error("Can not move {0} under itself or its descendant {1}")]
+    InvalidMoveError(String, String),
+
+    #[error("Can not delete the root node: {0}")]
+    DeleteRootError(String),
+
+    #[error("Node appears with a different parent or label in more than one input: {0}")]
+    MergeConflictError(String),
+
+    #[error("Only {0} node(s) fit in the given depth and number of children")]
+    GenerateError(usize),
+
+    #[error("Pid is used by more than one node: {0}")]
+    DuplicatePidError(usize),
+
+    #[error("Id is not an integer and can not be used as pid: {0}")]
+    NonIntegerIdError(String),
+
+    #[error("Unsupported format for this direction: {0:?}")]
+    UnsupportedFormatError(Format),
+
+    #[error("Output format needs lft/rgt and can not be written without indexing: {0:?}")]
+    IndexRequiredError(Format),
+
+    #[error("Input path is required for format: {0:?}")]
+    MissingInputError(Format),
+
+    #[error("Either a query or a table is required to read from a database")]
+    MissingQueryError(),
+
+    #[error("Output path is required for format: {0:?}")]
+    MissingOutputError(Format),
+
+    #[error("Unknown output column: {0}")]
+    UnknownColumnError(String),
+
+    #[error("{0}")]
+    UsageError(String),
+
+    #[error("Invalid record at line {line}: {message}")]
+    InvalidRecordError { line: u64, message: String },
+
+    #[error("{} invalid record(s) found:\n{}", .0.len(), join_lines(.0))]
+    InvalidRecordsError(Vec<Error>),
+
+    #[error("{0} problem(s) found")]
+    ValidationError(usize),
 
     #[error(transparent)]
     StdIoError(#[from] io::Error),
@@ -25,4 +154,168 @@ pub enum Error {
 
     #[error(transparent)]
     CsvError(#[from] csv::Error),
+
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[cfg(feature = "postgres")]
+    #[error(transparent)]
+    PostgresError(#[from] postgres::Error),
+
+    #[cfg(feature = "arrow")]
+    #[error(transparent)]
+    ArrowError(#[from] arrow::error::ArrowError),
+
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    ParquetError(#[from] parquet::errors::ParquetError),
+
+    #[cfg(feature = "xlsx")]
+    #[error(transparent)]
+    XlsxError(#[from] calamine::Error),
+
+    #[cfg(feature = "msgpack")]
+    #[error(transparent)]
+    MsgpackDecodeError(#[from] rmp_serde::decode::Error),
+
+    #[cfg(feature = "msgpack")]
+    #[error(transparent)]
+    MsgpackEncodeError(#[from] rmp_serde::encode::Error),
+
+    #[cfg(feature = "watch")]
+    #[error(transparent)]
+    NotifyError(#[from] notify::Error),
+}
+
+impl Error {
+    /// Name of the variant such as `parent_node_not_found_error`.
+    pub fn kind(&self) -> &'static str {
+        self.into()
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Error::RuntimeError(_) => ErrorClass::Other,
+            Error::ParentNodeNotFoundError { .. }
+            | Error::NodeNotFoundError(_)
+            | Error::RootNodeNotFoundError()
+            | Error::MultipleRootNodeError(_)
+            | Error::OrphanedNodesError(_)
+            | Error::VirtualRootConflictError(_)
+            | Error::NodeConflictError(_)
+            | Error::SelfParentError(_)
+            | Error::CycleError { .. }
+            | Error::LeafParentError(_)
+            | Error::DuplicateNodeError(_)
+            | Error::NotIndexedError(_)
+            | Error::InvalidMoveError(_, _)
+            | Error::DeleteRootError(_)
+            | Error::MergeConflictError(_)
+            | Error::DuplicatePidError(_)
+            | Error::NonIntegerIdError(_)
+            | Error::ValidationError(_) => ErrorClass::Structure,
+            Error::GenerateError(_)
+            | Error::UnsupportedFormatError(_)
+            | Error::IndexRequiredError(_)
+            | Error::MissingInputError(_)
+            | Error::MissingQueryError()
+            | Error::MissingOutputError(_)
+            | Error::UnknownColumnError(_)
+            | Error::UsageError(_) => ErrorClass::Usage,
+            Error::InvalidRecordError { .. }
+            | Error::InvalidRecordsError(_)
+            | Error::SerdeJsonError(_)
+            | Error::CsvError(_) => ErrorClass::Parse,
+            Error::StdIoError(_) => ErrorClass::Io,
+            #[cfg(feature = "sqlite")]
+            Error::SqliteError(_) => ErrorClass::Io,
+            #[cfg(feature = "postgres")]
+            Error::PostgresError(_) => ErrorClass::Io,
+            #[cfg(feature = "arrow")]
+            Error::ArrowError(_) => ErrorClass::Parse,
+            #[cfg(feature = "parquet")]
+            Error::ParquetError(_) => ErrorClass::Parse,
+            #[cfg(feature = "xlsx")]
+            Error::XlsxError(_) => ErrorClass::Parse,
+            #[cfg(feature = "msgpack")]
+            Error::MsgpackDecodeError(_) => ErrorClass::Parse,
+            #[cfg(feature = "msgpack")]
+            Error::MsgpackEncodeError(_) => ErrorClass::Other,
+            #[cfg(feature = "watch")]
+            Error::NotifyError(_) => ErrorClass::Io,
+        }
+    }
+
+    /// Id of the node the error is about, if any, such as the child of a missing parent or the
+    /// second root.
+    pub fn node_id(&self) -> Option<&str> {
+        match self {
+            Error::ParentNodeNotFoundError { id, .. } => Some(id),
+            Error::MultipleRootNodeError(roots) => roots.get(1).map(|x| x.id.as_str()),
+            Error::CycleError { ids, .. } => ids.first().map(|x| x.as_str()),
+            Error::NodeNotFoundError(x)
+            | Error::VirtualRootConflictError(x)
+            | Error::NodeConflictError(x)
+            | Error::DuplicateNodeError(x)
+            | Error::NotIndexedError(x)
+            | Error::InvalidMoveError(x, _)
+            | Error::DeleteRootError(x)
+            | Error::MergeConflictError(x)
+            | Error::NonIntegerIdError(x) => Some(x),
+            Error::SelfParentError(x) | Error::LeafParentError(x) => x.first().map(|x| x.as_str()),
+            Error::OrphanedNodesError(x) => x.first().map(|x| x.id.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Position of the node of [`Error::node_id`] among the nodes, starting from 1, if known.
+    pub fn row(&self) -> Option<usize> {
+        match self {
+            Error::ParentNodeNotFoundError { row, .. } => *row,
+            Error::MultipleRootNodeError(roots) => roots.get(1).map(|x| x.row),
+            Error::CycleError { rows, .. } => rows.first().copied(),
+            _ => None,
+        }
+    }
+
+    /// Line of the input the error is about, if any.
+    pub fn line(&self) -> Option<u64> {
+        match self {
+            Error::InvalidRecordError { line, .. } => Some(*line),
+            _ => None,
+        }
+    }
+
+    /// Errors collected into this error, such as the records of [`Error::InvalidRecordsError`].
+    pub fn causes(&self) -> &[Error] {
+        match self {
+            Error::InvalidRecordsError(errors) => errors,
+            _ => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorClass};
+
+    #[test]
+    fn test_class() {
+        let e = Error::ParentNodeNotFoundError {
+            id: "2".to_owned(),
+            parent: "9".to_owned(),
+            row: Some(2),
+        };
+        assert_eq!(e.kind(), "parent_node_not_found_error");
+        assert_eq!(e.class(), ErrorClass::Structure);
+        assert_eq!((e.node_id(), e.row()), (Some("2"), Some(2)));
+
+        let e = Error::InvalidRecordsError(vec![Error::InvalidRecordError {
+            line: 3,
+            message: "missing id".to_owned(),
+        }]);
+        assert_eq!(e.class(), ErrorClass::Parse);
+        assert_eq!(e.causes()[0].line(), Some(3));
+    }
 }
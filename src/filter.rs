@@ -0,0 +1,238 @@
+//! Extraction and exclusion of subtrees before indexing.
+
+use crate::data::Node;
+use crate::error;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Returns the rows of `nodes` by the id of their parent.
+fn children(nodes: &[Node]) -> HashMap<&str, Vec<usize>> {
+    let mut children = HashMap::<&str, Vec<usize>>::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if let Some(parent) = node.parent() {
+            children.entry(parent).or_default().push(i);
+        }
+    }
+    children
+}
+
+/// Returns the rows reachable from `starts` through the rows not matching `skip`.
+fn reachable<F: Fn(&Node) -> bool>(nodes: &[Node], starts: Vec<usize>, skip: F) -> Vec<bool> {
+    let children = children(nodes);
+    let mut keep = vec![false; nodes.len()];
+    let mut expanded = HashSet::new();
+    let mut queue: VecDeque<usize> = starts.into();
+
+    while let Some(i) = queue.pop_front() {
+        if keep[i] || skip(&nodes[i]) {
+            continue;
+        }
+        keep[i] = true;
+        if expanded.insert(nodes[i].id()) {
+            if let Some(x) = children.get(nodes[i].id()) {
+                queue.extend(x);
+            }
+        }
+    }
+
+    keep
+}
+
+fn retain(nodes: Vec<Node>, keep: Vec<bool>) -> Vec<Node> {
+    nodes
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(node, keep)| keep.then_some(node))
+        .collect()
+}
+
+/// Returns the node `root` with its descendants, making it the root.
+pub fn subtree(nodes: Vec<Node>, root: &str) -> error::Result<Vec<Node>> {
+    let start = nodes
+        .iter()
+        .position(|x| x.id() == root)
+        .ok_or_else(|| error::Error::NodeNotFoundError(root.to_owned()))?;
+
+    let keep = reachable(&nodes, vec![start], |_| false);
+    let mut nodes = nodes;
+    nodes[start].set_parent(None);
+
+    // other rows of the root are the parents which are not extracted
+    let keep = keep
+        .into_iter()
+        .enumerate()
+        .map(|(i, keep)| keep && (i == start || nodes[i].id() != root))
+        .collect();
+
+    Ok(retain(nodes, keep))
+}
+
+/// Drops the nodes matching `predicate` with their descendants, unless a descendant also has a
+/// parent which is kept.
+pub fn exclude<F: Fn(&Node) -> bool>(nodes: Vec<Node>, predicate: F) -> Vec<Node> {
+    let roots = (0..nodes.len())
+        .filter(|i| nodes[*i].parent().is_none())
+        .collect();
+    let keep = reachable(&nodes, roots, predicate);
+
+    retain(nodes, keep)
+}
+
+/// Keeps only the nodes matching `predicate` with their ancestors and descendants.
+pub fn include<F: Fn(&Node) -> bool>(nodes: Vec<Node>, predicate: F) -> Vec<Node> {
+    let matched: Vec<usize> = (0..nodes.len()).filter(|i| predicate(&nodes[*i])).collect();
+
+    let mut rows = HashMap::<&str, Vec<usize>>::new();
+    for (i, node) in nodes.iter().enumerate() {
+        rows.entry(node.id()).or_default().push(i);
+    }
+
+    let mut ids: HashSet<&str> = HashSet::new();
+    let mut queue: VecDeque<&str> = matched.iter().map(|i| nodes[*i].id()).collect();
+    while let Some(id) = queue.pop_front() {
+        if ids.insert(id) {
+            queue.extend(rows[id].iter().filter_map(|i| nodes[*i].parent()));
+        }
+    }
+    for i in reachable(&nodes, matched, |_| false)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, x)| x.then_some(i))
+    {
+        ids.insert(nodes[i].id());
+    }
+
+    let keep = nodes
+        .iter()
+        .map(|x| ids.contains(x.id()) && x.parent().is_none_or(|x| ids.contains(x)))
+        .collect();
+
+    retain(nodes, keep)
+}
+
+/// Keeps only the roots and the nodes matching `predicate`, such as of the selected ranks of a
+/// taxonomy, reparenting each kept node to its nearest kept ancestors.
+pub fn collapse<F: Fn(&Node) -> bool>(nodes: Vec<Node>, predicate: F) -> Vec<Node> {
+    let mut rows = HashMap::<&str, Vec<usize>>::new();
+    for (i, node) in nodes.iter().enumerate() {
+        rows.entry(node.id()).or_default().push(i);
+    }
+    let kept: HashSet<&str> = rows
+        .iter()
+        .filter(|(_, x)| {
+            x.iter()
+                .any(|i| nodes[*i].parent().is_none() || predicate(&nodes[*i]))
+        })
+        .map(|(id, _)| *id)
+        .collect();
+
+    // the nearest kept ancestors of every id, resolved from the roots down in topological order
+    let children = children(&nodes);
+    let mut parents: HashMap<&str, usize> = rows
+        .iter()
+        .map(|(id, x)| {
+            (
+                *id,
+                x.iter().filter(|i| nodes[**i].parent().is_some()).count(),
+            )
+        })
+        .collect();
+    let mut queue: VecDeque<&str> = parents
+        .iter()
+        .filter_map(|(id, count)| (*count == 0).then_some(*id))
+        .collect();
+    let mut ancestors = HashMap::<&str, Vec<&str>>::new();
+    while let Some(id) = queue.pop_front() {
+        let resolved = if kept.contains(id) {
+            vec![id]
+        } else {
+            let mut resolved = Vec::new();
+            for parent in rows[id].iter().filter_map(|i| nodes[*i].parent()) {
+                for x in ancestors.get(parent).map_or(&[parent][..], |x| x) {
+                    if !resolved.contains(x) {
+                        resolved.push(*x);
+                    }
+                }
+            }
+            resolved
+        };
+        ancestors.insert(id, resolved);
+
+        for i in children.get(id).into_iter().flatten() {
+            let count = parents.get_mut(nodes[*i].id()).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                queue.push_back(nodes[*i].id());
+            }
+        }
+    }
+
+    // ids in a cycle are left as they are for the validation
+    let mut edges = HashSet::new();
+    let mut collapsed = Vec::new();
+    for node in &nodes {
+        if !kept.contains(node.id()) {
+            continue;
+        }
+        let parent = match node.parent() {
+            Some(x) => x,
+            None => {
+                collapsed.push(node.to_owned());
+                continue;
+            }
+        };
+        let resolved = match ancestors.get(parent) {
+            Some(x) => x.to_owned(),
+            None => vec![parent],
+        };
+        for x in resolved {
+            if edges.insert((node.id().to_owned(), x.to_owned())) {
+                let mut copy = node.to_owned();
+                copy.set_parent(Some(x.to_owned()));
+                collapsed.push(copy);
+            }
+        }
+    }
+
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collapse, exclude, include, subtree};
+    use crate::data::Node;
+
+    #[test]
+    fn test_filter() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "c").with_parent("1"),
+            Node::new("5", "d").with_parent("4"),
+        ];
+        let ids = |nodes: Vec<Node>| nodes.iter().map(|x| x.id().to_owned()).collect::<Vec<_>>();
+
+        let extracted = subtree(nodes.to_owned(), "2").unwrap();
+        assert_eq!(ids(extracted.to_owned()), vec!["2", "3"]);
+        assert_eq!(extracted[0].parent(), None);
+        assert!(subtree(nodes.to_owned(), "9").is_err());
+
+        assert_eq!(
+            ids(exclude(nodes.to_owned(), |x| x.label() == "a")),
+            vec!["1", "4", "5"]
+        );
+        assert_eq!(
+            ids(include(nodes.to_owned(), |x| x.label() == "c")),
+            vec!["1", "4", "5"]
+        );
+
+        let ranked = nodes
+            .iter()
+            .cloned()
+            .chain(Some(Node::new("3", "b").with_parent("4")))
+            .collect();
+        let collapsed = collapse(ranked, |x| ["b", "d"].contains(&x.label()));
+        let edges: Vec<_> = collapsed.iter().map(|x| (x.id(), x.parent())).collect();
+        assert_eq!(edges, vec![("1", None), ("3", Some("1")), ("5", Some("1"))]);
+    }
+}
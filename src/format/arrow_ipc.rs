@@ -0,0 +1,97 @@
+use crate::data::{ColumnMapping, Node};
+use crate::error;
+use crate::format::record_batch::{read_batch, schema, to_batches};
+use crate::format::{OutputColumns, Parsed};
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use std::io;
+use std::io::{Cursor, Write};
+
+/// Reads nodes from an Arrow IPC file, converting the columns the same way as for Parquet.
+pub fn read<R: io::Read>(mut input: R, columns: &ColumnMapping) -> error::Result<Parsed> {
+    // IPC files are read from the footer, so the whole input is needed
+    let mut buffer = Vec::new();
+    input.read_to_end(&mut buffer)?;
+
+    let mut nodes = Vec::new();
+    let mut rejected = Vec::new();
+    for batch in FileReader::try_new(Cursor::new(buffer), None)? {
+        let batch = batch?;
+        let offset = nodes.len() + rejected.len();
+        read_batch(&batch, offset, columns, &mut nodes, &mut rejected)?;
+    }
+
+    Ok(Parsed { nodes, rejected })
+}
+
+/// Writes an Arrow IPC file, which can be loaded by pandas and polars as Feather.
+pub fn write<W: io::Write>(
+    output: W,
+    nodes: &[Node],
+    columns: &OutputColumns,
+) -> error::Result<()> {
+    let records = columns.records(nodes)?;
+    let schema = schema(&records);
+
+    let mut writer = FileWriter::try_new_buffered(output, &schema)?;
+    for batch in to_batches(&records, &schema)? {
+        writer.write(&batch)?;
+    }
+    writer.finish()?;
+    writer.into_inner()?.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{ColumnMapping, Graph, Node};
+    use crate::format::{read_nodes, write_nodes, Format, ReadOptions, WriteOptions};
+    use arrow::array::AsArray;
+    use arrow::datatypes::Int64Type;
+    use arrow::ipc::reader::FileReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_arrow() {
+        let nodes = vec![Node::new("1", "a"), Node::new("2", "b").with_parent("1")];
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let mut output = Vec::new();
+        write_nodes(
+            &Format::Arrow,
+            &mut output,
+            &graph.nodes,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let mut reader = FileReader::try_new(Cursor::new(&output), None).unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+
+        let rgt = batch
+            .column_by_name("rgt")
+            .unwrap()
+            .as_primitive::<Int64Type>();
+        assert_eq!(rgt.values(), &[4, 3]);
+        let label = batch
+            .column_by_name("classification_label")
+            .unwrap()
+            .as_string::<i32>();
+        assert_eq!(label.value(1), "b");
+
+        let options = ReadOptions {
+            columns: ColumnMapping {
+                id: "classification".to_owned(),
+                parent: "classification_parent".to_owned(),
+                label: "classification_label".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let parsed = read_nodes(&Format::Arrow, &output[..], &options).unwrap();
+        assert_eq!(parsed.nodes[1].parent(), Some("1"));
+    }
+}
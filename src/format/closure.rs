@@ -0,0 +1,260 @@
+use crate::data::Node;
+use crate::error;
+use crate::format::Parsed;
+use csv::{Reader, Writer};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::io::{BufReader, BufWriter};
+
+/// Reads a closure table of `ancestor_id` and `descendant_id`, labelling nodes with their ids.
+///
+/// The parent of a node is its ancestor at `depth` 1, or its deepest ancestor other than itself if
+/// there is no `depth` column.
+pub fn read<R: io::Read>(input: R) -> error::Result<Parsed> {
+    let mut reader = Reader::from_reader(BufReader::new(input));
+    let headers = reader.headers()?.to_owned();
+
+    let position = |name: &str| headers.iter().position(|x| x == name);
+    let (ancestor, descendant) = match (position("ancestor_id"), position("descendant_id")) {
+        (Some(x), Some(y)) => (x, y),
+        _ => Err(error::Error::InvalidRecordError {
+            line: 1,
+            message: "expected ancestor_id and descendant_id columns".to_owned(),
+        })?,
+    };
+    let depth = position("depth");
+
+    let mut ids: Vec<String> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut ancestors: HashMap<String, Vec<(String, Option<usize>)>> = HashMap::new();
+    let mut rejected = Vec::new();
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(x) => x,
+            Err(e) if e.is_io_error() => Err(e)?,
+            Err(e) => {
+                rejected.push(error::Error::InvalidRecordError {
+                    line: e.position().map_or(0, |x| x.line()),
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let line = record.position().map_or(0, |x| x.line());
+        let depth = match depth.map(|i| record[i].parse::<usize>()) {
+            Some(Ok(x)) => Some(x),
+            Some(Err(e)) => {
+                rejected.push(error::Error::InvalidRecordError {
+                    line,
+                    message: format!("invalid depth: {}", e),
+                });
+                continue;
+            }
+            None => None,
+        };
+
+        let (ancestor, descendant) = (&record[ancestor], &record[descendant]);
+        for id in [ancestor, descendant] {
+            if seen.insert(id.to_owned()) {
+                ids.push(id.to_owned());
+            }
+        }
+        if ancestor != descendant {
+            ancestors
+                .entry(descendant.to_owned())
+                .or_default()
+                .push((ancestor.to_owned(), depth));
+        }
+    }
+
+    let count = |id: &str| ancestors.get(id).map_or(0, |x| x.len());
+
+    let nodes = ids
+        .iter()
+        .map(|id| {
+            let parent = ancestors.get(id).and_then(|list| match depth {
+                Some(_) => list.iter().find(|(_, depth)| *depth == Some(1)),
+                None => list.iter().max_by_key(|(x, _)| count(x)),
+            });
+
+            match parent {
+                Some((parent, _)) => Node::new(id.as_str(), id.as_str()).with_parent(parent),
+                None => Node::new(id.as_str(), id.as_str()),
+            }
+        })
+        .collect();
+
+    Ok(Parsed { nodes, rejected })
+}
+
+/// Writes a closure table of an indexed tree as CSV with every `ancestor_id`, `descendant_id` and
+/// `depth` between them, including each node as its own ancestor at depth 0. The ids are the
+/// `id` column of the other formats.
+///
+/// Nodes which are not indexed, such as of a directed acyclic graph, are written by
+/// [`write_links`] instead.
+pub fn write<W: io::Write>(output: W, nodes: &[Node]) -> error::Result<()> {
+    if nodes.iter().all(|x| x.lft().is_none()) {
+        return write_links(output, nodes);
+    }
+
+    let mut writer = Writer::from_writer(BufWriter::new(output));
+    writer.write_record(["ancestor_id", "descendant_id", "depth"])?;
+
+    let mut order: Vec<&Node> = nodes.iter().collect();
+    order.sort_by_key(|x| x.lft());
+
+    // ancestors of the current node from the root
+    let mut ancestors: Vec<&Node> = Vec::new();
+    for node in order {
+        while ancestors.last().is_some_and(|x| x.rgt() < node.lft()) {
+            ancestors.pop();
+        }
+        ancestors.push(node);
+
+        let descendant = node.pid().unwrap_or_default().to_string();
+        for (depth, ancestor) in ancestors.iter().rev().enumerate() {
+            let ancestor = ancestor.pid().unwrap_or_default().to_string();
+            writer.write_record([ancestor.as_str(), descendant.as_str(), &depth.to_string()])?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes a closure table of the parent links of nodes which may have multiple parents, with the
+/// ids of the input and the shortest `depth` between an ancestor and a descendant.
+fn write_links<W: io::Write>(output: W, nodes: &[Node]) -> error::Result<()> {
+    let mut writer = Writer::from_writer(BufWriter::new(output));
+    writer.write_record(["ancestor_id", "descendant_id", "depth"])?;
+
+    let mut ids = Vec::new();
+    let mut parents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for node in nodes {
+        let list = parents.entry(node.id()).or_insert_with(|| {
+            ids.push(node.id());
+            Vec::new()
+        });
+        list.extend(node.parent());
+    }
+
+    for id in ids {
+        // breadth-first so that every ancestor is first reached at its shortest depth
+        let mut visited = HashSet::from([id]);
+        let mut queue = VecDeque::from([(id, 0)]);
+        while let Some((ancestor, depth)) = queue.pop_front() {
+            writer.write_record([ancestor, id, &depth.to_string()])?;
+            for parent in parents.get(ancestor).into_iter().flatten() {
+                if visited.insert(parent) {
+                    queue.push_back((parent, depth + 1));
+                }
+            }
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{Graph, Node};
+    use crate::format::{read_nodes, write_nodes, Format, ReadOptions, WriteOptions};
+
+    #[test]
+    fn test_closure() {
+        let nodes = vec![
+            Node::new("a", "a"),
+            Node::new("b", "b").with_parent("a"),
+            Node::new("c", "c").with_parent("b"),
+            Node::new("d", "d").with_parent("a"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let mut output = Vec::new();
+        write_nodes(
+            &Format::Closure,
+            &mut output,
+            &graph.nodes,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output.to_owned()).unwrap(),
+            "ancestor_id,descendant_id,depth\n\
+             1,1,0\n\
+             2,2,0\n\
+             1,2,1\n\
+             3,3,0\n\
+             2,3,1\n\
+             1,3,2\n\
+             4,4,0\n\
+             1,4,1\n"
+        );
+
+        let parsed = read_nodes(&Format::Closure, &output[..], &ReadOptions::default()).unwrap();
+        let nodes: Vec<_> = parsed.nodes.iter().map(|x| (x.id(), x.parent())).collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("1", None),
+                ("2", Some("1")),
+                ("3", Some("2")),
+                ("4", Some("1"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_closure_of_dag() {
+        let nodes = vec![
+            Node::new("a", "a"),
+            Node::new("b", "b").with_parent("a"),
+            Node::new("c", "c").with_parent("b"),
+            Node::new("c", "c").with_parent("a"),
+        ];
+
+        let mut output = Vec::new();
+        write_nodes(
+            &Format::Closure,
+            &mut output,
+            &nodes,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "ancestor_id,descendant_id,depth\n\
+             a,a,0\n\
+             b,b,0\n\
+             a,b,1\n\
+             c,c,0\n\
+             b,c,1\n\
+             a,c,1\n"
+        );
+    }
+
+    #[test]
+    fn test_closure_without_depth() {
+        let input = "descendant_id,ancestor_id\nc,a\nc,b\nb,a\nd,a\nc,c\n";
+
+        let parsed =
+            read_nodes(&Format::Closure, input.as_bytes(), &ReadOptions::default()).unwrap();
+        let nodes: Vec<_> = parsed.nodes.iter().map(|x| (x.id(), x.parent())).collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("a", None),
+                ("c", Some("b")),
+                ("b", Some("a")),
+                ("d", Some("a"))
+            ]
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use crate::data::{IndexedNodes, Node, OUTPUT_FIELDS};
+use crate::error;
+use serde_json::{Map, Value};
+use std::borrow::Borrow;
+use std::slice;
+
+/// Columns which are emitted only if requested by [`OutputColumns::optional`] or
+/// [`OutputColumns::select`].
+pub const OPTIONAL_COLUMNS: &[&str] = &["depth", "descendant_count", "leaf_count"];
+
+/// Selection and renaming of the output columns.
+#[derive(Debug, Clone, Default)]
+pub struct OutputColumns {
+    /// Columns to emit in this order, by their original names. All the columns if empty.
+    pub select: Vec<String>,
+    /// Columns in [`OPTIONAL_COLUMNS`] to emit when `select` is empty.
+    pub optional: Vec<String>,
+    /// Pairs of an original column name and the name to emit it as.
+    pub rename: Vec<(String, String)>,
+}
+
+/// Output records with the same columns in the same order.
+#[derive(Debug)]
+pub struct Records {
+    pub headers: Vec<String>,
+    pub rows: Vec<Map<String, Value>>,
+}
+
+fn record(node: &Node) -> error::Result<Map<String, Value>> {
+    let mut record = match serde_json::to_value(node)? {
+        Value::Object(x) => x,
+        _ => unreachable!("node is serialized as a map"),
+    };
+
+    for (k, v) in node.extra() {
+        if !record.contains_key(k) {
+            record.insert(k.to_owned(), v.to_owned());
+        }
+    }
+
+    Ok(record)
+}
+
+impl OutputColumns {
+    fn rename<'a>(&'a self, column: &'a str) -> &'a str {
+        self.rename
+            .iter()
+            .find(|(from, _)| from == column)
+            .map_or(column, |(_, to)| to)
+    }
+
+    /// Returns the original names of the output columns of `nodes`, the fields of
+    /// [`OUTPUT_FIELDS`] for which `has_field` is true followed by the extra columns.
+    fn columns<F: Fn(&str) -> bool>(
+        &self,
+        nodes: &[Node],
+        has_field: F,
+    ) -> error::Result<Vec<String>> {
+        let mut columns: Vec<String> = OUTPUT_FIELDS
+            .iter()
+            .filter(|x| has_field(x))
+            .map(|x| (*x).to_owned())
+            .collect();
+        for node in nodes {
+            for k in node.extra().keys() {
+                if !columns.contains(k) {
+                    columns.push(k.to_owned());
+                }
+            }
+        }
+
+        if !self.select.is_empty() {
+            if let Some(x) = self.select.iter().find(|x| !columns.contains(x)) {
+                Err(error::Error::UnknownColumnError(x.to_owned()))?
+            }
+            columns = self.select.to_owned();
+        } else {
+            columns
+                .retain(|x| !OPTIONAL_COLUMNS.contains(&x.as_str()) || self.optional.contains(x));
+        }
+
+        Ok(columns)
+    }
+
+    fn record_stream<'a, I>(&'a self, columns: Vec<String>, nodes: I) -> RecordStream<'a, I> {
+        RecordStream {
+            headers: columns.iter().map(|x| self.rename(x).to_owned()).collect(),
+            columns,
+            output: self,
+            nodes,
+        }
+    }
+
+    /// Converts `nodes` to output records one at a time, so that the rows of a large hierarchy
+    /// are not all in memory at once.
+    ///
+    /// Extra columns missing in some of the nodes are filled with `null`.
+    pub fn stream<'a>(
+        &'a self,
+        nodes: &'a [Node],
+    ) -> error::Result<RecordStream<'a, slice::Iter<'a, Node>>> {
+        let columns = self.columns(nodes, |x| nodes.iter().any(|y| y.has_output_field(x)))?;
+
+        Ok(self.record_stream(columns, nodes.iter()))
+    }
+
+    /// Converts the nodes of `indexed` to output records as they are numbered, like
+    /// [`OutputColumns::stream`].
+    pub fn stream_indexed(
+        &self,
+        indexed: IndexedNodes,
+    ) -> error::Result<RecordStream<'_, IndexedNodes>> {
+        let columns = self.columns(indexed.pending(), |x| indexed.has_output_field(x))?;
+
+        Ok(self.record_stream(columns, indexed))
+    }
+
+    /// Converts `nodes` to output records.
+    ///
+    /// Extra columns missing in some of the nodes are filled with `null`.
+    pub fn records(&self, nodes: &[Node]) -> error::Result<Records> {
+        let mut stream = self.stream(nodes)?;
+        let headers = std::mem::take(&mut stream.headers);
+        let rows = stream.collect::<error::Result<Vec<_>>>()?;
+
+        Ok(Records { headers, rows })
+    }
+}
+
+/// Output records converted from the nodes one at a time by [`OutputColumns::stream`] or
+/// [`OutputColumns::stream_indexed`].
+#[derive(Debug)]
+pub struct RecordStream<'a, I> {
+    pub headers: Vec<String>,
+    columns: Vec<String>,
+    output: &'a OutputColumns,
+    nodes: I,
+}
+
+impl<I> Iterator for RecordStream<'_, I>
+where
+    I: Iterator,
+    I::Item: Borrow<Node>,
+{
+    type Item = error::Result<Map<String, Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = match record(self.nodes.next()?.borrow()) {
+            Ok(x) => x,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let row = self
+            .columns
+            .iter()
+            .map(|k| {
+                let v = record.remove(k).unwrap_or(Value::Null);
+                (self.output.rename(k).to_owned(), v)
+            })
+            .collect();
+        Some(Ok(row))
+    }
+}
+
+/// Type of a column inferred from its values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnType {
+    Integer,
+    Real,
+    Boolean,
+    Text,
+}
+
+impl ColumnType {
+    /// Infers the type from the first non-null value. Columns of only nulls are text.
+    pub fn infer<'a, I: Iterator<Item = &'a Value>>(mut values: I) -> Self {
+        match values.find(|x| !x.is_null()) {
+            Some(Value::Bool(_)) => ColumnType::Boolean,
+            Some(Value::Number(x)) if x.is_f64() => ColumnType::Real,
+            Some(Value::Number(_)) => ColumnType::Integer,
+            _ => ColumnType::Text,
+        }
+    }
+}
+
+/// Formats a value of a record as a field of CSV/TSV.
+pub fn to_field(value: &Value) -> String {
+    match value {
+        Value::Null => "".to_owned(),
+        Value::String(x) => x.to_owned(),
+        x => x.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::record;
+    use crate::data::{Graph, Node, PathOptions, OUTPUT_FIELDS};
+    use crate::format::OutputColumns;
+    use serde_json::Value;
+
+    #[test]
+    fn test_select_and_rename() {
+        let columns = OutputColumns {
+            select: vec!["lft".to_owned(), "classification".to_owned()],
+            rename: vec![("classification".to_owned(), "code".to_owned())],
+            ..Default::default()
+        };
+
+        let records = columns.records(&[Node::new("1", "a")]).unwrap();
+        assert_eq!(records.headers, vec!["lft", "code"]);
+        assert_eq!(
+            records.rows[0].keys().collect::<Vec<_>>(),
+            vec!["lft", "code"]
+        );
+        assert_eq!(records.rows[0]["code"], "1");
+
+        let columns = OutputColumns {
+            select: vec!["unknown".to_owned()],
+            ..Default::default()
+        };
+        assert!(columns.records(&[Node::new("1", "a")]).is_err());
+    }
+
+    #[test]
+    fn test_extra_columns() {
+        let nodes = [
+            Node::new("1", "a").with_extra("rank", "kingdom"),
+            Node::new("2", "b").with_extra("color", "red"),
+        ];
+
+        let records = OutputColumns::default().records(&nodes).unwrap();
+        assert_eq!(
+            records.headers[records.headers.len() - 2..],
+            ["rank", "color"]
+        );
+        assert_eq!(records.rows[0]["color"], Value::Null);
+        assert_eq!(records.rows[1]["color"], "red");
+    }
+
+    #[test]
+    fn test_stream() {
+        let nodes = [
+            Node::new("1", "a"),
+            Node::new("2", "b").with_extra("color", "red"),
+        ];
+
+        let columns = OutputColumns::default();
+        let stream = columns.stream(&nodes).unwrap();
+        assert_eq!(stream.headers.last().map(|x| x.as_str()), Some("color"));
+        let rows: Vec<_> = stream.map(|x| x.unwrap()).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["color"], Value::Null);
+    }
+
+    #[test]
+    fn test_output_fields() {
+        let nodes = vec![
+            Node::new("1", "a"),
+            Node::new("2", "b")
+                .with_parent("1")
+                .with_extra("rank", "genus"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap().complement_leaf().unwrap();
+        graph.build_index().unwrap();
+        graph.build_paths(&PathOptions::default());
+        graph.build_label_paths("/");
+        graph.build_hashes();
+
+        // the fields are listed in the order they are serialized in
+        for node in &graph.nodes {
+            let fields: Vec<_> = OUTPUT_FIELDS
+                .iter()
+                .filter(|x| node.has_output_field(x))
+                .collect();
+            let record = record(node).unwrap();
+            assert_eq!(record.keys().take(fields.len()).collect::<Vec<_>>(), fields);
+        }
+    }
+
+    #[test]
+    fn test_optional_columns() {
+        let mut graph = Graph::new(vec![Node::new("1", "a")]).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let records = OutputColumns::default().records(&graph.nodes).unwrap();
+        assert!(!records.headers.contains(&"depth".to_owned()));
+
+        let columns = OutputColumns {
+            optional: vec!["depth".to_owned()],
+            ..Default::default()
+        };
+        let records = columns.records(&graph.nodes).unwrap();
+        assert_eq!(records.rows[0]["depth"], 0);
+        // the input id follows the columns of the index
+        assert_eq!(
+            records.headers.last().map(|x| x.as_str()),
+            Some("source_id")
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Transparent compression of the inputs and outputs.
+
+use crate::error;
+use std::io;
+use std::io::BufRead;
+use std::path::Path;
+use strum::{EnumString, EnumVariantNames};
+
+#[derive(Debug, Clone, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum Compression {
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the compression of a file by its extension, `.gz` or `.zst`.
+    pub fn from_path(path: &Path) -> Option<Compression> {
+        match path.extension()?.to_str()? {
+            #[cfg(feature = "gzip")]
+            "gz" => Some(Compression::Gzip),
+            #[cfg(feature = "zstd")]
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Returns the extension of the files with the compression, without the leading `.`.
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => "gz",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// Returns the compression of a stream by its magic number.
+    pub(crate) fn from_magic(bytes: &[u8]) -> Option<Compression> {
+        match bytes {
+            #[cfg(feature = "gzip")]
+            [0x1f, 0x8b, ..] => Some(Compression::Gzip),
+            #[cfg(feature = "zstd")]
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Wraps `input` in a decoder if it starts with the magic number of a supported compression.
+pub fn decoder<'a, R: io::Read + 'a>(input: R) -> io::Result<Box<dyn io::Read + 'a>> {
+    let mut input = io::BufReader::new(input);
+
+    match Compression::from_magic(input.fill_buf()?) {
+        #[cfg(feature = "gzip")]
+        Some(Compression::Gzip) => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(input))),
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd) => Ok(Box::new(zstd::Decoder::with_buffer(input)?)),
+        None => Ok(Box::new(input)),
+    }
+}
+
+/// Calls `write` with `output` compressed by `compression`, finishing the compressed stream after.
+pub fn encode<W, F>(output: W, compression: Option<Compression>, write: F) -> error::Result<()>
+where
+    W: io::Write,
+    F: FnOnce(&mut dyn io::Write) -> error::Result<()>,
+{
+    match compression {
+        #[cfg(feature = "gzip")]
+        Some(Compression::Gzip) => {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            write(&mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd) => {
+            let mut encoder = zstd::Encoder::new(output, 0)?;
+            write(&mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        None => {
+            let mut output = output;
+            write(&mut output)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "gzip", feature = "zstd"))]
+mod tests {
+    use super::Compression;
+    use crate::data::{ColumnMapping, Graph, Node};
+    use crate::format::{read_nodes, write_nodes, Format, ReadOptions, WriteOptions};
+    use std::path::Path;
+
+    #[test]
+    fn test_compression() {
+        assert_eq!(
+            Compression::from_path(Path::new("tree.csv.gz")),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(Compression::from_path(Path::new("tree.csv")), None);
+        assert!(matches!(
+            Format::from_path(Path::new("tree.tsv.zst")),
+            Some(Format::Tsv)
+        ));
+
+        let nodes = vec![Node::new("1", "a"), Node::new("2", "b").with_parent("1")];
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let read_options = ReadOptions {
+            columns: ColumnMapping {
+                id: "classification".to_owned(),
+                parent: "classification_parent".to_owned(),
+                label: "classification_label".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let options = WriteOptions {
+                compression: Some(compression),
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            write_nodes(&Format::Csv, &mut output, &graph.nodes, &options).unwrap();
+            assert!(!output.starts_with(b"pid"));
+
+            let parsed = read_nodes(&Format::Csv, &output[..], &read_options).unwrap();
+            assert_eq!(parsed.nodes.len(), 2);
+            assert_eq!(parsed.nodes[1].parent(), Some("1"));
+        }
+    }
+}
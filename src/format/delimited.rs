@@ -0,0 +1,400 @@
+use crate::data::{BorrowedNode, ColumnMapping, Node, INPUT_FIELDS};
+use crate::error;
+use crate::format::columns::{self, OutputColumns, RecordStream};
+use crate::format::Parsed;
+use csv::{ReaderBuilder, StringRecord, Trim, WriterBuilder};
+use serde_json::Value;
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::io::{BufReader, BufWriter};
+use std::{io, mem};
+
+fn rejected(e: csv::Error) -> error::Error {
+    let line = e.position().map_or(0, |x| x.line());
+    // the messages leave out the positions, which are relative to the chunk in a chunked read
+    let message = match e.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => format!(
+            "found record with {} fields, but the header has {} fields",
+            len, expected_len
+        ),
+        csv::ErrorKind::Utf8 { err, .. } => format!("invalid UTF-8: {}", err),
+        _ => e.to_string(),
+    };
+
+    error::Error::InvalidRecordError { line, message }
+}
+
+pub fn read<R: io::Read>(
+    input: R,
+    delimiter: u8,
+    columns: &ColumnMapping,
+) -> error::Result<Parsed> {
+    // the reader is buffered by itself
+    let mut reader = ReaderBuilder::new().delimiter(delimiter).from_reader(input);
+
+    let original = reader.headers()?.to_owned();
+    let columns = columns.detect(&original);
+    let headers: StringRecord = original.iter().map(|x| columns.field_name(x)).collect();
+    let extra: Vec<(usize, &str)> = headers
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| !INPUT_FIELDS.contains(x))
+        .map(|(i, _)| (i, &original[i]))
+        .collect();
+
+    let mut nodes = Vec::new();
+    let mut rejected = Vec::new();
+
+    // one record is reused for all the rows, whose fields are borrowed from it until the row is
+    // known to be valid and converted to a node
+    let mut record = StringRecord::new();
+    loop {
+        let node = match reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => record.deserialize(Some(&headers)).map(|x: BorrowedNode| {
+                let mut node = Node::from(x);
+                for (i, k) in &extra {
+                    let v = record.get(*i).unwrap_or_default();
+                    node.extra_mut().insert((*k).to_owned(), Value::from(v));
+                }
+                node
+            }),
+            Err(e) => Err(e),
+        };
+
+        match node {
+            Ok(node) => nodes.push(node),
+            Err(e) if e.is_io_error() => Err(e)?,
+            Err(e) => rejected.push(self::rejected(e)),
+        }
+    }
+
+    Ok(Parsed { nodes, rejected })
+}
+
+/// Reads CSV/TSV from `bytes` like [`read`], splitting the records after the header into chunks
+/// of about `chunk_size` bytes which are parsed in parallel.
+///
+/// The nodes keep the order of the input, and the lines of the rejected records are counted from
+/// the start of `bytes`. The bytes parsed so far are reported within the current span.
+#[cfg(feature = "mmap")]
+pub fn read_chunked(
+    bytes: &[u8],
+    delimiter: u8,
+    columns: &ColumnMapping,
+    chunk_size: usize,
+) -> error::Result<Parsed> {
+    use crate::format::progress;
+    use rayon::prelude::*;
+    use std::io::Read;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let bounds = record_bounds(bytes, delimiter, chunk_size);
+    if bounds.len() <= 3 {
+        return read(bytes, delimiter, columns);
+    }
+
+    // every chunk is read after a copy of the header, so that the columns are mapped and the
+    // number of fields is checked as in a single read
+    let header = &bytes[..bounds[1]];
+    let (span, done) = (
+        tracing::Span::current(),
+        AtomicU64::new(header.len() as u64),
+    );
+    let chunks: Vec<_> = bounds[1..]
+        .par_windows(2)
+        .map(|x| {
+            let chunk = &bytes[x[0]..x[1]];
+            let parsed = read(header.chain(chunk), delimiter, columns)?;
+            let len = chunk.len() as u64;
+            progress::report(&span, done.fetch_add(len, Ordering::Relaxed) + len);
+            Ok((parsed, count_lines(chunk)))
+        })
+        .collect::<error::Result<_>>()?;
+
+    let header_lines = count_lines(header);
+    let mut lines = header_lines;
+    let mut nodes = Vec::with_capacity(chunks.iter().map(|(x, _)| x.nodes.len()).sum());
+    let mut rejected = Vec::new();
+    for (mut parsed, chunk_lines) in chunks {
+        nodes.append(&mut parsed.nodes);
+        for mut e in parsed.rejected {
+            if let error::Error::InvalidRecordError { line, .. } = &mut e {
+                if *line > 0 {
+                    *line = *line - header_lines + lines;
+                }
+            }
+            rejected.push(e);
+        }
+        lines += chunk_lines;
+    }
+
+    Ok(Parsed { nodes, rejected })
+}
+
+#[cfg(feature = "mmap")]
+fn count_lines(bytes: &[u8]) -> u64 {
+    bytes.iter().filter(|x| **x == b'\n').count() as u64
+}
+
+/// Returns the offsets of the ends of the header and of every record ending a chunk of at least
+/// `chunk_size` bytes, after a leading 0 and up to `bytes.len()`.
+///
+/// The records are only tokenized, so that quoted line breaks are not taken for boundaries.
+#[cfg(feature = "mmap")]
+fn record_bounds(bytes: &[u8], delimiter: u8, chunk_size: usize) -> Vec<usize> {
+    let mut reader = csv_core::ReaderBuilder::new().delimiter(delimiter).build();
+    // the fields are not needed, so the output is overwritten whenever it is full
+    let mut output = vec![0; 1 << 16];
+    let mut ends = vec![0; 1 << 10];
+
+    let mut bounds = vec![0];
+    let mut pos = 0;
+    let mut next = 0;
+    loop {
+        let (result, read, _, _) = reader.read_record(&bytes[pos..], &mut output, &mut ends);
+        pos += read;
+        match result {
+            csv_core::ReadRecordResult::Record if pos >= next => {
+                bounds.push(pos);
+                next = pos + chunk_size;
+            }
+            csv_core::ReadRecordResult::End => break,
+            _ => {}
+        }
+    }
+    if bounds.last() != Some(&bytes.len()) {
+        bounds.push(bytes.len());
+    }
+
+    bounds
+}
+
+/// Reads headerless `child,parent` rows, labelling nodes with their ids.
+///
+/// Rows without a parent are roots, and parents never listed as children are added as roots.
+/// Lines starting with `#` are ignored.
+pub fn read_edges<R: io::Read>(input: R, delimiter: u8) -> error::Result<Parsed> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .trim(Trim::All)
+        .comment(Some(b'#'))
+        .from_reader(BufReader::new(input));
+
+    let mut nodes = Vec::new();
+    let mut rejected = Vec::new();
+    let mut ids = HashSet::new();
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(x) => x,
+            Err(e) if e.is_io_error() => Err(e)?,
+            Err(e) => {
+                rejected.push(self::rejected(e));
+                continue;
+            }
+        };
+
+        match (record.get(0), record.get(1), record.len()) {
+            (Some(child), parent, 1..=2) if !child.is_empty() => {
+                let mut node = Node::new(child, child);
+                if let Some(parent) = parent.filter(|x| !x.is_empty()) {
+                    node = node.with_parent(parent);
+                }
+                ids.insert(child.to_owned());
+                nodes.push(node);
+            }
+            _ => rejected.push(error::Error::InvalidRecordError {
+                line: record.position().map_or(0, |x| x.line()),
+                message: "expected child and optional parent".to_owned(),
+            }),
+        }
+    }
+
+    let mut roots = Vec::new();
+    for parent in nodes.iter().filter_map(|x| x.parent()) {
+        if ids.insert(parent.to_owned()) {
+            roots.push(Node::new(parent, parent));
+        }
+    }
+    roots.append(&mut nodes);
+
+    Ok(Parsed {
+        nodes: roots,
+        rejected,
+    })
+}
+
+pub fn write<W: io::Write>(
+    output: W,
+    delimiter: u8,
+    nodes: &[Node],
+    columns: &OutputColumns,
+) -> error::Result<()> {
+    write_rows(output, delimiter, columns.stream(nodes)?)
+}
+
+/// Writes the rows of `records` as they are converted, with the headers unless there is no row.
+pub fn write_rows<W, I>(output: W, delimiter: u8, mut records: RecordStream<I>) -> error::Result<()>
+where
+    W: io::Write,
+    I: Iterator,
+    I::Item: Borrow<Node>,
+{
+    let mut writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(BufWriter::new(output));
+
+    let mut headers = Some(mem::take(&mut records.headers));
+    for row in records {
+        if let Some(headers) = headers.take() {
+            writer.write_record(&headers)?;
+        }
+        writer.write_record(row?.values().map(columns::to_field))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::ColumnMapping;
+    use crate::error::Error;
+    use crate::format::{read_nodes, Format, ReadOptions};
+
+    const INPUT: &str = "id,label,parent,leaf\n1,a,,\n2,b,1,maybe\n3,c,1,true\n";
+
+    #[test]
+    fn test_lenient() {
+        let parsed = read_nodes(&Format::Csv, INPUT.as_bytes(), &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.nodes.len(), 2);
+        assert_eq!(parsed.rejected.len(), 1);
+
+        match &parsed.rejected[0] {
+            Error::InvalidRecordError { line, .. } => assert_eq!(*line, 3),
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_strict() {
+        let options = ReadOptions {
+            strict: true,
+            ..Default::default()
+        };
+
+        match read_nodes(&Format::Csv, INPUT.as_bytes(), &options) {
+            Err(Error::InvalidRecordsError(errors)) => assert_eq!(errors.len(), 1),
+            _ => panic!("strict mode should fail"),
+        }
+    }
+
+    #[test]
+    fn test_column_mapping() {
+        let input = "code,name,parent_code,id\nA,a,,1\nB,b,A,2\n";
+        let options = ReadOptions {
+            columns: ColumnMapping {
+                id: "code".to_owned(),
+                parent: "parent_code".to_owned(),
+                label: "name".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let parsed = read_nodes(&Format::Csv, input.as_bytes(), &options).unwrap();
+        assert_eq!(parsed.nodes.len(), 2);
+        assert_eq!(parsed.nodes[1].id(), "B");
+        assert_eq!(parsed.nodes[1].label(), "b");
+        assert_eq!(parsed.nodes[1].parent(), Some("A"));
+        assert_eq!(parsed.nodes[1].extra()["id"], "2");
+    }
+
+    #[test]
+    fn test_output_columns() {
+        let input = "id,classification,classification_origin,classification_label,\
+            classification_parent,lft,rgt\n1,A,,a,,1,4\n2,B__1,B,b,A,2,3\n";
+
+        let parsed = read_nodes(&Format::Csv, input.as_bytes(), &ReadOptions::default()).unwrap();
+        assert!(parsed.rejected.is_empty());
+        assert_eq!(parsed.nodes[1].pid(), Some(2));
+        assert_eq!(parsed.nodes[1].id(), "B__1");
+        assert_eq!(parsed.nodes[1].origin(), Some("B"));
+        assert_eq!(parsed.nodes[1].parent(), Some("A"));
+        assert!(parsed.nodes[1].extra().is_empty());
+    }
+
+    #[test]
+    fn test_extra_columns() {
+        let input = "id,label,parent,rank,code\n1,a,,kingdom,007\n";
+
+        let parsed = read_nodes(&Format::Csv, input.as_bytes(), &ReadOptions::default()).unwrap();
+        let extra = parsed.nodes[0].extra();
+        assert_eq!(extra.keys().collect::<Vec<_>>(), vec!["rank", "code"]);
+        assert_eq!(extra["code"], "007");
+    }
+
+    #[test]
+    fn test_quoted_fields() {
+        let input = "id,label,parent,source_id\n1,\"a, \"\"b\"\"\",,\n2,c,1,x\n";
+
+        let parsed = read_nodes(&Format::Csv, input.as_bytes(), &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.nodes[0].label(), "a, \"b\"");
+        assert_eq!(parsed.nodes[0].parent(), None);
+        assert_eq!(parsed.nodes[1].source_id(), "x");
+    }
+
+    #[test]
+    fn test_edges() {
+        let input = "# child,parent\nb, a\nc,b\nd\ne,a,x\n";
+
+        let parsed = read_nodes(&Format::Edges, input.as_bytes(), &ReadOptions::default()).unwrap();
+        let nodes: Vec<_> = parsed
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.label(), x.parent()))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("a", "a", None),
+                ("b", "b", Some("a")),
+                ("c", "c", Some("b")),
+                ("d", "d", None),
+            ]
+        );
+        assert_eq!(parsed.rejected.len(), 1);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_read_chunked() {
+        let input = "id,label,parent\r\n1,a,\r\n2,\"b\nc\",1\r\n3,c\r\n4,d,2\r\n5,e,2";
+        let columns = ColumnMapping::default();
+        let expected = super::read(input.as_bytes(), b',', &columns).unwrap();
+
+        for chunk_size in 1..input.len() {
+            let parsed = super::read_chunked(input.as_bytes(), b',', &columns, chunk_size).unwrap();
+            let ids: Vec<_> = parsed.nodes.iter().map(|x| x.id()).collect();
+            assert_eq!(ids, ["1", "2", "4", "5"]);
+            assert_eq!(parsed.nodes[1].label(), "b\nc");
+            assert_eq!(parsed.rejected.len(), 1);
+            assert_eq!(
+                parsed.rejected[0].to_string(),
+                expected.rejected[0].to_string()
+            );
+            assert_eq!(
+                parsed.rejected[0].line(),
+                expected.rejected[0].line(),
+                "{}",
+                chunk_size
+            );
+        }
+    }
+}
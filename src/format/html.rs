@@ -0,0 +1,141 @@
+use crate::data::Node;
+use crate::error;
+use std::io;
+use std::io::{BufWriter, Write};
+
+/// Levels of the tree expanded when the page is opened.
+const EXPANDED_DEPTH: usize = 2;
+
+const HEADER: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Nested set</title>
+<style>
+body { font-family: sans-serif; }
+ul { list-style: none; padding-left: 1.5em; }
+li { margin: 0.15em 0; }
+summary { cursor: pointer; }
+.index { color: #888; font-size: smaller; }
+</style>
+</head>
+<body>
+<button onclick="toggle(true)">Expand all</button>
+<button onclick="toggle(false)">Collapse all</button>
+<ul>
+"#;
+
+const FOOTER: &str = r#"</ul>
+<script>
+function toggle(open) {
+  document.querySelectorAll("details").forEach(function (x) { x.open = open; });
+}
+</script>
+</body>
+</html>
+"#;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn name(node: &Node) -> String {
+    let label = match node.label() {
+        "" => node.id(),
+        x => x,
+    };
+    let index = |x: Option<usize>| x.map_or("-".to_owned(), |x| x.to_string());
+
+    format!(
+        "<span title=\"{}\">{}</span> <span class=\"index\">[{}, {}]</span>",
+        escape(node.id()),
+        escape(label),
+        index(node.lft()),
+        index(node.rgt())
+    )
+}
+
+/// Writes an indexed tree as a self-contained HTML page with a collapsible tree view.
+pub fn write<W: io::Write>(output: W, nodes: &[Node]) -> error::Result<()> {
+    let mut writer = BufWriter::new(output);
+    writer.write_all(HEADER.as_bytes())?;
+
+    let mut order: Vec<&Node> = nodes.iter().collect();
+    order.sort_by_key(|x| x.lft());
+
+    // `rgt` of the ancestors of the current node
+    let mut ancestors: Vec<Option<usize>> = Vec::new();
+    for (k, node) in order.iter().copied().enumerate() {
+        while ancestors.last().is_some_and(|rgt| *rgt < node.lft()) {
+            ancestors.pop();
+            writer.write_all(b"</ul></details></li>\n")?;
+        }
+
+        match (node.lft(), node.rgt()) {
+            // a node has children if the next node starts inside it, whatever the gap of the values
+            (Some(_), Some(rgt)) if order.get(k + 1).is_some_and(|x| x.lft() < Some(rgt)) => {
+                let open = if ancestors.len() < EXPANDED_DEPTH {
+                    " open"
+                } else {
+                    ""
+                };
+                writeln!(
+                    writer,
+                    "<li><details{}><summary>{}</summary><ul>",
+                    open,
+                    name(node)
+                )?;
+                ancestors.push(node.rgt());
+            }
+            _ => writeln!(writer, "<li>{}</li>", name(node))?,
+        }
+    }
+    for _ in ancestors {
+        writer.write_all(b"</ul></details></li>\n")?;
+    }
+
+    writer.write_all(FOOTER.as_bytes())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{Graph, Node};
+    use crate::format::{write_nodes, Format, WriteOptions};
+
+    #[test]
+    fn test_html() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "<a>").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "c").with_parent("1"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let mut output = Vec::new();
+        write_nodes(
+            &Format::Html,
+            &mut output,
+            &graph.nodes,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("<details").count(), 2);
+        assert_eq!(
+            output.matches("<details").count(),
+            output.matches("</details>").count()
+        );
+        assert!(output.contains("<span title=\"2\">&lt;a&gt;</span>"));
+        assert!(output
+            .contains("<li><span title=\"4\">c</span> <span class=\"index\">[6, 7]</span></li>"));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::data::{ColumnMapping, Node, INPUT_FIELDS};
+use crate::error;
+use crate::format::{OutputColumns, Parsed, RecordStream};
+use serde::ser::{self, SerializeSeq};
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+/// Converts an object to a node, keeping the keys other than the fields of the node as extra
+/// columns. Numeric ids and labels are converted to strings.
+pub fn to_node(object: Map<String, Value>, columns: &ColumnMapping) -> serde_json::Result<Node> {
+    let columns = columns.detect(object.keys().map(|x| x.as_str()));
+    let (fields, extra): (Map<String, Value>, Map<String, Value>) = object
+        .into_iter()
+        .map(|(k, v)| match columns.field_name(&k) {
+            name @ ("id" | "parent" | "label") if v.is_number() => {
+                (name.to_owned(), Value::String(v.to_string()))
+            }
+            name if INPUT_FIELDS.contains(&name) => (name.to_owned(), v),
+            _ => (k, v),
+        })
+        .partition(|(k, _)| INPUT_FIELDS.contains(&k.as_str()));
+
+    let mut node: Node = serde_json::from_value(Value::Object(fields))?;
+    *node.extra_mut() = extra;
+
+    Ok(node)
+}
+
+pub fn read<R: io::Read>(input: R, columns: &ColumnMapping) -> error::Result<Parsed> {
+    let objects: Vec<Map<String, Value>> = serde_json::from_reader(BufReader::new(input))?;
+
+    let nodes = objects
+        .into_iter()
+        .map(|x| to_node(x, columns))
+        .collect::<serde_json::Result<_>>()?;
+
+    Ok(Parsed {
+        nodes,
+        rejected: Vec::new(),
+    })
+}
+
+/// Reads a nested object, or an array of them, with the children in a `children` array.
+///
+/// The parent of each nested node is set to the node it is nested in.
+pub fn read_tree<R: io::Read>(input: R, columns: &ColumnMapping) -> error::Result<Parsed> {
+    let roots = match serde_json::from_reader(BufReader::new(input))? {
+        Value::Array(x) => x,
+        x => vec![x],
+    };
+
+    let mut nodes = Vec::new();
+    let mut stack: Vec<(Value, Option<String>)> =
+        roots.into_iter().rev().map(|x| (x, None)).collect();
+
+    while let Some((value, parent)) = stack.pop() {
+        let mut object: Map<String, Value> = serde_json::from_value(value)?;
+        let children = match object.remove("children") {
+            Some(x) => serde_json::from_value(x)?,
+            None => Vec::new(),
+        };
+
+        let mut node = to_node(object, columns)?;
+        if let Some(parent) = parent {
+            node = node.with_parent(parent);
+        }
+
+        let id = node.id().to_owned();
+        stack.extend(children.into_iter().rev().map(|x| (x, Some(id.to_owned()))));
+        nodes.push(node);
+    }
+
+    Ok(Parsed {
+        nodes,
+        rejected: Vec::new(),
+    })
+}
+
+/// Reads JSON Lines, one object per line. Blank lines are ignored.
+pub fn read_lines<R: io::Read>(input: R, columns: &ColumnMapping) -> error::Result<Parsed> {
+    let mut nodes = Vec::new();
+    let mut rejected = Vec::new();
+
+    for (i, line) in BufReader::new(input).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str(&line).and_then(|x| to_node(x, columns)) {
+            Ok(node) => nodes.push(node),
+            Err(e) => rejected.push(error::Error::InvalidRecordError {
+                line: i as u64 + 1,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    Ok(Parsed { nodes, rejected })
+}
+
+pub fn write<W: io::Write>(
+    output: W,
+    nodes: &[Node],
+    columns: &OutputColumns,
+) -> error::Result<()> {
+    write_rows(output, columns.stream(nodes)?)
+}
+
+/// Writes the rows of `records` as an array while they are converted.
+pub fn write_rows<W, I>(output: W, records: RecordStream<I>) -> error::Result<()>
+where
+    W: io::Write,
+    I: Iterator,
+    I::Item: Borrow<Node>,
+{
+    let mut writer = BufWriter::new(output);
+    serde_json::to_writer_pretty(&mut writer, &Rows(RefCell::new(records)))?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Rows serialized as an array while they are converted.
+struct Rows<'a, I>(RefCell<RecordStream<'a, I>>);
+
+impl<I> Serialize for Rows<'_, I>
+where
+    I: Iterator,
+    I::Item: Borrow<Node>,
+{
+    fn serialize<S: Serializer>(&self, se: S) -> Result<S::Ok, S::Error> {
+        let mut seq = se.serialize_seq(None)?;
+        for row in self.0.borrow_mut().by_ref() {
+            seq.serialize_element(&row.map_err(ser::Error::custom)?)?;
+        }
+        seq.end()
+    }
+}
+
+/// Writes JSON Lines, one object per line.
+pub fn write_lines<W: io::Write>(
+    output: W,
+    nodes: &[Node],
+    columns: &OutputColumns,
+) -> error::Result<()> {
+    write_row_lines(output, columns.stream(nodes)?)
+}
+
+/// Writes the rows of `records` as JSON Lines while they are converted.
+pub fn write_row_lines<W, I>(output: W, records: RecordStream<I>) -> error::Result<()>
+where
+    W: io::Write,
+    I: Iterator,
+    I::Item: Borrow<Node>,
+{
+    let mut writer = BufWriter::new(output);
+    for row in records {
+        serde_json::to_writer(&mut writer, &row?)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes an indexed tree as a nested object with the children in a `children` array.
+pub fn write_tree<W: io::Write>(
+    output: W,
+    nodes: &[Node],
+    columns: &OutputColumns,
+) -> error::Result<()> {
+    let records = columns.records(nodes)?;
+
+    let index: HashMap<usize, usize> = nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, x)| x.pid().map(|pid| (pid, i)))
+        .collect();
+
+    // build objects from the deepest ones without recursion, visiting nodes in reverse pre-order
+    // so that all the children of a node are built before the node itself
+    let mut order: Vec<usize> = (0..nodes.len()).collect();
+    order.sort_by_key(|i| std::cmp::Reverse(nodes[*i].lft()));
+
+    let mut children: Vec<Vec<Value>> = vec![Vec::new(); nodes.len()];
+    let mut rows: Vec<Option<Map<String, Value>>> = records.rows.into_iter().map(Some).collect();
+    let mut root = None;
+
+    for i in order {
+        let mut object = rows[i].take().unwrap_or_default();
+        let mut list = std::mem::take(&mut children[i]);
+        list.reverse();
+        object.insert("children".to_owned(), Value::Array(list));
+
+        match nodes[i].parent_id().and_then(|x| index.get(&x)) {
+            Some(p) => children[*p].push(Value::Object(object)),
+            None => root = Some(Value::Object(object)),
+        }
+    }
+
+    let mut writer = BufWriter::new(output);
+    serde_json::to_writer_pretty(&mut writer, &root.unwrap_or(Value::Null))?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{Graph, Node};
+    use crate::format::{
+        read_nodes, write_nodes, Format, OutputColumns, ReadOptions, WriteOptions,
+    };
+
+    #[test]
+    fn test_json_lines() {
+        let input = "{\"id\":\"1\",\"label\":\"a\"}\n\n{\"id\":\"2\",\"label\":\"b\",\"parent\":\"1\"}\n{\"id\":\n";
+
+        let parsed = read_nodes(&Format::Jsonl, input.as_bytes(), &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.nodes.len(), 2);
+        assert_eq!(parsed.rejected.len(), 1);
+
+        let mut output = Vec::new();
+        write_nodes(
+            &Format::Jsonl,
+            &mut output,
+            &parsed.nodes,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.starts_with("{\"id\":null,\"classification\":\"1\","));
+    }
+
+    #[test]
+    fn test_numeric_ids() {
+        let input = "[{\"id\":1,\"label\":\"a\"},{\"id\":2,\"label\":\"b\",\"parent\":1}]";
+
+        let parsed = read_nodes(&Format::Json, input.as_bytes(), &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.nodes[1].id(), "2");
+        assert_eq!(parsed.nodes[1].parent(), Some("1"));
+    }
+
+    #[test]
+    fn test_json_tree() {
+        let nodes = vec![
+            Node::new("1", "a"),
+            Node::new("2", "b").with_parent("1"),
+            Node::new("3", "c").with_parent("2"),
+            Node::new("4", "d").with_parent("1"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let options = WriteOptions {
+            columns: OutputColumns {
+                select: vec![
+                    "classification".to_owned(),
+                    "classification_label".to_owned(),
+                    "lft".to_owned(),
+                ],
+                rename: vec![
+                    ("classification".to_owned(), "id".to_owned()),
+                    ("classification_label".to_owned(), "label".to_owned()),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        write_nodes(&Format::JsonTree, &mut output, &graph.nodes, &options).unwrap();
+
+        let tree: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(tree["id"], "1");
+        assert_eq!(tree["children"][0]["id"], "2");
+        assert_eq!(tree["children"][0]["children"][0]["lft"], 3);
+        assert_eq!(tree["children"][1]["id"], "4");
+        assert_eq!(tree["children"][1]["children"], serde_json::json!([]));
+
+        let parsed = read_nodes(&Format::JsonTree, &output[..], &ReadOptions::default()).unwrap();
+        let ids: Vec<_> = parsed.nodes.iter().map(|x| (x.id(), x.parent())).collect();
+        assert_eq!(
+            ids,
+            vec![
+                ("1", None),
+                ("2", Some("1")),
+                ("3", Some("2")),
+                ("4", Some("1"))
+            ]
+        );
+    }
+}
@@ -0,0 +1,663 @@
+//! Reading and writing node lists in the supported formats.
+
+use crate::data::{ColumnMapping, IndexedNodes, Node};
+use crate::error;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use strum::{EnumString, EnumVariantNames, IntoStaticStr};
+
+#[cfg(feature = "arrow")]
+mod arrow_ipc;
+mod closure;
+mod columns;
+mod compression;
+mod delimited;
+mod html;
+mod json;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+mod newick;
+mod obo;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod paths;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod progress;
+#[cfg(feature = "arrow")]
+mod record_batch;
+mod skos;
+mod sql;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod taxdump;
+mod tree;
+#[cfg(feature = "xlsx")]
+mod xlsx;
+
+pub use columns::{OutputColumns, RecordStream, OPTIONAL_COLUMNS};
+pub use compression::Compression;
+pub use sql::Dialect;
+
+#[derive(Debug, Clone, EnumString, EnumVariantNames, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum Format {
+    Csv,
+    Tsv,
+    Json,
+    Jsonl,
+    JsonTree,
+    /// Headerless `child,parent` rows.
+    Edges,
+    /// One delimited path such as `Animals/Mammals/Cats` per line.
+    Path,
+    /// Closure table of `ancestor_id`, `descendant_id` and `depth` as CSV.
+    Closure,
+    /// Hierarchy drawn with box-drawing characters.
+    Tree,
+    /// Self-contained HTML page with a collapsible tree view.
+    Html,
+    Newick,
+    Obo,
+    /// Directory of the NCBI taxonomy dump with `nodes.dmp` and `names.dmp`.
+    #[strum(serialize = "ncbi-taxdump")]
+    NcbiTaxdump,
+    Skos,
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// Arrow IPC file, also known as Feather v2.
+    #[cfg(feature = "arrow")]
+    Arrow,
+    #[cfg(feature = "msgpack")]
+    Msgpack,
+    /// Excel or OpenDocument spreadsheet.
+    #[cfg(feature = "xlsx")]
+    Xlsx,
+    Sql,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    #[cfg(feature = "postgres")]
+    Postgres,
+}
+
+impl Format {
+    /// Returns the database format of a connection URL such as `postgres://localhost/db`.
+    pub fn from_url(url: &str) -> Option<Format> {
+        let (scheme, _) = url.split_once("://")?;
+
+        match scheme {
+            #[cfg(feature = "postgres")]
+            "postgres" | "postgresql" => Some(Format::Postgres),
+            _ => None,
+        }
+    }
+
+    /// Returns the format of a file by its extension, ignoring the extension of the compression
+    /// such as `.gz` in `tree.csv.gz`, or the format of a database URL.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        if let Some(format) = path.to_str().and_then(Format::from_url) {
+            return Some(format);
+        }
+
+        let path = match Compression::from_path(path) {
+            Some(_) => Path::new(path.file_stem()?),
+            None => path,
+        };
+        Format::from_str(path.extension()?.to_str()?).ok()
+    }
+
+    /// Whether the format is written from `lft` and `rgt`, so that the nodes must be indexed.
+    pub fn needs_index(&self) -> bool {
+        matches!(
+            self,
+            Format::JsonTree | Format::Closure | Format::Tree | Format::Html | Format::Newick
+        )
+    }
+
+    /// Whether the format is written one row at a time, so that [`write_indexed_to`] can write
+    /// the nodes as they are numbered.
+    pub fn streams(&self) -> bool {
+        matches!(
+            self,
+            Format::Csv | Format::Tsv | Format::Json | Format::Jsonl
+        )
+    }
+}
+
+/// What to do with an existing table when writing to a database.
+#[derive(Debug, Clone, Default, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum IfExists {
+    /// Drop the table and create it again.
+    Replace,
+    /// Delete all the rows of the table.
+    #[default]
+    Truncate,
+    /// Keep the rows of the table.
+    Append,
+}
+
+/// Options for [`read_nodes`].
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// Fail if any record can not be parsed instead of skipping it.
+    pub strict: bool,
+    /// Names of the input columns for `id`, `parent` and `label`.
+    pub columns: ColumnMapping,
+    /// Query to read the nodes with for database inputs.
+    pub query: Option<String>,
+    /// Table to read the nodes from for database inputs, if `query` is not given.
+    pub table: Option<String>,
+    /// Sheet to read the nodes from for spreadsheet inputs (default: the first one).
+    pub sheet: Option<String>,
+    /// Separator of the segments of paths for the `path` format (default: `/`).
+    pub path_separator: Option<String>,
+}
+
+impl ReadOptions {
+    fn path_separator(&self) -> &str {
+        self.path_separator.as_deref().unwrap_or("/")
+    }
+
+    /// Returns the query for database inputs.
+    #[cfg(any(feature = "sqlite", feature = "postgres"))]
+    fn query(&self, dialect: &Dialect) -> error::Result<String> {
+        match (&self.query, &self.table) {
+            (Some(query), _) => Ok(query.to_owned()),
+            (None, Some(table)) => Ok(format!("SELECT * FROM {}", dialect.quote(table))),
+            (None, None) => Err(error::Error::MissingQueryError()),
+        }
+    }
+}
+
+/// Nodes read from the input.
+#[derive(Debug)]
+pub struct Parsed {
+    pub nodes: Vec<Node>,
+    /// Records skipped because they could not be parsed. Always empty in strict mode.
+    pub rejected: Vec<error::Error>,
+}
+
+/// Reads nodes from `input`.
+///
+/// Gzip and Zstandard compressed inputs are decompressed transparently. Malformed CSV/TSV/JSON
+/// Lines records and OBO terms are collected in [`Parsed::rejected`], or fail the whole read with
+/// [`error::Error::InvalidRecordsError`] if [`ReadOptions::strict`] is set.
+#[tracing::instrument(name = "parse", level = "debug", skip_all)]
+pub fn read_nodes<R: io::Read>(
+    format: &Format,
+    input: R,
+    options: &ReadOptions,
+) -> error::Result<Parsed> {
+    read_input(format, input, options)
+}
+
+fn read_input<R: io::Read>(
+    format: &Format,
+    input: R,
+    options: &ReadOptions,
+) -> error::Result<Parsed> {
+    let input = compression::decoder(progress::Counted::new(input))?;
+    let parsed = match format {
+        Format::Csv => delimited::read(input, b',', &options.columns)?,
+        Format::Tsv => delimited::read(input, b'\t', &options.columns)?,
+        Format::Json => json::read(input, &options.columns)?,
+        Format::Jsonl => json::read_lines(input, &options.columns)?,
+        Format::JsonTree => json::read_tree(input, &options.columns)?,
+        Format::Edges => delimited::read_edges(input, b',')?,
+        Format::Path => paths::read(input, options.path_separator())?,
+        Format::Closure => closure::read(input)?,
+        Format::Newick => newick::read(input)?,
+        Format::Obo => obo::read(input)?,
+        Format::NcbiTaxdump => taxdump::read(input, None::<io::Empty>)?,
+        Format::Skos => skos::read(input)?,
+        #[cfg(feature = "parquet")]
+        Format::Parquet => parquet::read(input, &options.columns)?,
+        #[cfg(feature = "arrow")]
+        Format::Arrow => arrow_ipc::read(input, &options.columns)?,
+        #[cfg(feature = "msgpack")]
+        Format::Msgpack => msgpack::read(input, &options.columns)?,
+        #[cfg(feature = "xlsx")]
+        Format::Xlsx => xlsx::read(input, options.sheet.as_deref(), &options.columns)?,
+        Format::Tree | Format::Html | Format::Sql => {
+            Err(error::Error::UnsupportedFormatError(format.to_owned()))?
+        }
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => Err(error::Error::UnsupportedFormatError(format.to_owned()))?,
+        #[cfg(feature = "postgres")]
+        Format::Postgres => Err(error::Error::UnsupportedFormatError(format.to_owned()))?,
+    };
+
+    check_rejected(parsed, options)
+}
+
+fn check_rejected(parsed: Parsed, options: &ReadOptions) -> error::Result<Parsed> {
+    if options.strict && !parsed.rejected.is_empty() {
+        return Err(error::Error::InvalidRecordsError(parsed.rejected));
+    }
+
+    Ok(parsed)
+}
+
+/// Reads nodes from the file at `path`, or from stdin if `None`.
+///
+/// Unlike [`read_nodes`], this also supports the database formats which need a path. For the
+/// `postgres` format, `path` is a connection URL such as `postgres://user@localhost/db`.
+#[tracing::instrument(
+    name = "parse",
+    level = "debug",
+    skip_all,
+    fields(total_bytes = tracing::field::Empty)
+)]
+pub fn read_nodes_from(
+    format: &Format,
+    path: Option<&Path>,
+    options: &ReadOptions,
+) -> error::Result<Parsed> {
+    match (format, path) {
+        #[cfg(feature = "sqlite")]
+        (Format::Sqlite, Some(path)) => {
+            let query = options.query(&Dialect::Sqlite)?;
+            check_rejected(sqlite::read(path, &query, &options.columns)?, options)
+        }
+        #[cfg(feature = "postgres")]
+        (Format::Postgres, Some(url)) => {
+            let query = options.query(&Dialect::Postgres)?;
+            let parsed = postgres::read(&url.to_string_lossy(), &query, &options.columns)?;
+            check_rejected(parsed, options)
+        }
+        #[cfg(feature = "sqlite")]
+        (Format::Sqlite, None) => Err(error::Error::MissingInputError(format.to_owned())),
+        #[cfg(feature = "postgres")]
+        (Format::Postgres, None) => Err(error::Error::MissingInputError(format.to_owned())),
+        (Format::NcbiTaxdump, Some(path)) => check_rejected(taxdump::read_path(path)?, options),
+        #[cfg(feature = "mmap")]
+        (Format::Csv, Some(path)) if is_regular_file(path) => {
+            read_delimited_file(format, path, b',', options)
+        }
+        #[cfg(feature = "mmap")]
+        (Format::Tsv, Some(path)) if is_regular_file(path) => {
+            read_delimited_file(format, path, b'\t', options)
+        }
+        #[cfg(feature = "mmap")]
+        (_, Some(path)) if is_regular_file(path) => {
+            read_input(format, &map_file(path)?[..], options)
+        }
+        (_, Some(path)) => read_input(format, open_input(path)?, options),
+        (_, None) => read_input(format, io::stdin().lock(), options),
+    }
+}
+
+/// The size of the chunks of an uncompressed CSV/TSV file parsed in parallel.
+#[cfg(feature = "mmap")]
+const CHUNK_SIZE: usize = 4 << 20;
+
+/// Reads nodes from the regular CSV/TSV file at `path`, parsing chunks of the records in parallel unless
+/// the file is compressed or there is a single thread.
+#[cfg(feature = "mmap")]
+fn read_delimited_file(
+    format: &Format,
+    path: &Path,
+    delimiter: u8,
+    options: &ReadOptions,
+) -> error::Result<Parsed> {
+    let bytes = map_file(path)?;
+    if compression::Compression::from_magic(&bytes).is_some() || rayon::current_num_threads() == 1 {
+        return read_input(format, &bytes[..], options);
+    }
+
+    let parsed = delimited::read_chunked(&bytes, delimiter, &options.columns, CHUNK_SIZE)?;
+    check_rejected(parsed, options)
+}
+
+/// Whether `path` is a regular file, which can be mapped into memory unlike a pipe such as of a
+/// process substitution.
+#[cfg(feature = "mmap")]
+fn is_regular_file(path: &Path) -> bool {
+    path.metadata().is_ok_and(|x| x.is_file())
+}
+
+/// Opens the input file at `path`, recording its size on the parse span for the progress if it is
+/// a regular file.
+fn open_input(path: &Path) -> io::Result<File> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if metadata.is_file() {
+        tracing::Span::current().record("total_bytes", metadata.len());
+    }
+    Ok(file)
+}
+
+/// Maps the file at `path` into memory, so that a large input is parsed without copying it
+/// through reads.
+#[cfg(feature = "mmap")]
+fn map_file(path: &Path) -> io::Result<memmap2::Mmap> {
+    let file = open_input(path)?;
+    // SAFETY: the mapping is only read, and the input is not expected to be truncated by another
+    // process while it is parsed
+    unsafe { memmap2::Mmap::map(&file) }
+}
+
+/// Options for [`write_nodes`].
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    pub columns: OutputColumns,
+    /// Name of the table for database and SQL outputs.
+    pub table: String,
+    /// Dialect of SQL output.
+    pub dialect: Dialect,
+    /// What to do with an existing table for database outputs. Tables are created if missing.
+    pub if_exists: IfExists,
+    /// Compression of the output. Files are also compressed by their extension such as `.gz`.
+    pub compression: Option<Compression>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            columns: OutputColumns::default(),
+            table: "nested_set".to_owned(),
+            dialect: Dialect::default(),
+            if_exists: IfExists::default(),
+            compression: None,
+        }
+    }
+}
+
+/// Writes nodes to `output`, compressed by [`WriteOptions::compression`].
+#[tracing::instrument(name = "write", level = "debug", skip_all, fields(rows = nodes.len()))]
+pub fn write_nodes<W: io::Write>(
+    format: &Format,
+    output: W,
+    nodes: &[Node],
+    options: &WriteOptions,
+) -> error::Result<()> {
+    compression::encode(
+        progress::Counted::new(output),
+        options.compression.to_owned(),
+        |output| write_uncompressed(format, output, nodes, options),
+    )
+}
+
+fn write_uncompressed<W: io::Write>(
+    format: &Format,
+    output: W,
+    nodes: &[Node],
+    options: &WriteOptions,
+) -> error::Result<()> {
+    match format {
+        Format::Csv => delimited::write(output, b',', nodes, &options.columns),
+        Format::Tsv => delimited::write(output, b'\t', nodes, &options.columns),
+        Format::Json => json::write(output, nodes, &options.columns),
+        Format::Jsonl => json::write_lines(output, nodes, &options.columns),
+        Format::JsonTree => json::write_tree(output, nodes, &options.columns),
+        Format::Closure => closure::write(output, nodes),
+        Format::Tree => tree::write(output, nodes),
+        Format::Html => html::write(output, nodes),
+        Format::Newick => newick::write(output, nodes),
+        Format::Skos => skos::write(output, nodes),
+        #[cfg(feature = "parquet")]
+        Format::Parquet => parquet::write(output, nodes, &options.columns),
+        #[cfg(feature = "arrow")]
+        Format::Arrow => arrow_ipc::write(output, nodes, &options.columns),
+        #[cfg(feature = "msgpack")]
+        Format::Msgpack => msgpack::write(output, nodes, &options.columns),
+        #[cfg(feature = "xlsx")]
+        Format::Xlsx => Err(error::Error::UnsupportedFormatError(format.to_owned())),
+        Format::Edges | Format::Path | Format::Obo | Format::NcbiTaxdump => {
+            Err(error::Error::UnsupportedFormatError(format.to_owned()))
+        }
+        Format::Sql => sql::write(
+            output,
+            &options.table,
+            &options.dialect,
+            nodes,
+            &options.columns,
+        ),
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => Err(error::Error::UnsupportedFormatError(format.to_owned())),
+        #[cfg(feature = "postgres")]
+        Format::Postgres => Err(error::Error::UnsupportedFormatError(format.to_owned())),
+    }
+}
+
+/// Writes nodes to the file at `path`, or to stdout if `None`.
+///
+/// Unlike [`write_nodes`], this also supports the database formats which need a path. For the
+/// `postgres` format, `path` is a connection URL such as `postgres://user@localhost/db`.
+#[tracing::instrument(name = "write", level = "debug", skip_all, fields(rows = nodes.len()))]
+pub fn write_nodes_to(
+    format: &Format,
+    path: Option<&Path>,
+    nodes: &[Node],
+    options: &WriteOptions,
+) -> error::Result<()> {
+    match (format, path) {
+        #[cfg(feature = "sqlite")]
+        (Format::Sqlite, Some(path)) => sqlite::write(
+            path,
+            &options.table,
+            &options.if_exists,
+            nodes,
+            &options.columns,
+        ),
+        #[cfg(feature = "postgres")]
+        (Format::Postgres, Some(url)) => postgres::write(
+            &url.to_string_lossy(),
+            &options.table,
+            &options.if_exists,
+            nodes,
+            &options.columns,
+        ),
+        #[cfg(feature = "sqlite")]
+        (Format::Sqlite, None) => Err(error::Error::MissingOutputError(format.to_owned())),
+        #[cfg(feature = "postgres")]
+        (Format::Postgres, None) => Err(error::Error::MissingOutputError(format.to_owned())),
+        (_, Some(path)) => {
+            let compression = options
+                .compression
+                .to_owned()
+                .or_else(|| Compression::from_path(path));
+            compression::encode(
+                progress::Counted::new(File::create(path)?),
+                compression,
+                |output| write_uncompressed(format, output, nodes, options),
+            )
+        }
+        (_, None) => {
+            let output = progress::Counted::new(io::stdout().lock());
+            compression::encode(output, options.compression.to_owned(), |x| {
+                write_uncompressed(format, x, nodes, options)
+            })
+        }
+    }
+}
+
+/// Writes the nodes of `indexed` to the file at `path`, or to stdout if `None`, as they are
+/// numbered by [`Graph::into_indexed`] instead of after numbering all of them. Only the formats
+/// of [`Format::streams`] are supported.
+///
+/// [`Graph::into_indexed`]: crate::data::Graph::into_indexed
+#[tracing::instrument(name = "write", level = "debug", skip_all)]
+pub fn write_indexed_to(
+    format: &Format,
+    path: Option<&Path>,
+    indexed: IndexedNodes,
+    options: &WriteOptions,
+) -> error::Result<()> {
+    if !format.streams() {
+        Err(error::Error::UnsupportedFormatError(format.to_owned()))?
+    }
+    let records = options.columns.stream_indexed(indexed)?;
+    let write = |output: &mut dyn io::Write| match format {
+        Format::Csv => delimited::write_rows(output, b',', records),
+        Format::Tsv => delimited::write_rows(output, b'\t', records),
+        Format::Json => json::write_rows(output, records),
+        _ => json::write_row_lines(output, records),
+    };
+
+    match path {
+        Some(path) => {
+            let compression = options
+                .compression
+                .to_owned()
+                .or_else(|| Compression::from_path(path));
+            compression::encode(
+                progress::Counted::new(File::create(path)?),
+                compression,
+                write,
+            )
+        }
+        None => {
+            let output = progress::Counted::new(io::stdout().lock());
+            compression::encode(output, options.compression.to_owned(), write)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{ColumnMapping, Graph, Node};
+    use crate::format::{
+        read_nodes, read_nodes_from, write_nodes, Format, ReadOptions, WriteOptions,
+    };
+    use crate::generate::trees;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+
+    /// Formats which are both read and written.
+    fn formats() -> Vec<Format> {
+        vec![
+            Format::Csv,
+            Format::Tsv,
+            Format::Json,
+            Format::Jsonl,
+            Format::JsonTree,
+            Format::Closure,
+            Format::Newick,
+            Format::Skos,
+            #[cfg(feature = "parquet")]
+            Format::Parquet,
+            #[cfg(feature = "arrow")]
+            Format::Arrow,
+            #[cfg(feature = "msgpack")]
+            Format::Msgpack,
+        ]
+    }
+
+    /// Returns the indexed graph of `nodes`.
+    fn index(nodes: Vec<Node>) -> Graph {
+        let mut graph = Graph::new(nodes).unwrap();
+        graph.build_index().unwrap();
+        graph
+    }
+
+    /// Returns a canonical form of the shape of the tree, independent of the ids, the labels and
+    /// the order of the siblings.
+    fn shape(graph: &Graph) -> String {
+        fn walk(pid: usize, children: &HashMap<usize, Vec<usize>>) -> String {
+            let mut shapes: Vec<_> = children
+                .get(&pid)
+                .map_or(&[][..], |x| &x[..])
+                .iter()
+                .map(|x| walk(*x, children))
+                .collect();
+            shapes.sort_unstable();
+            format!("({})", shapes.concat())
+        }
+
+        let mut root = None;
+        let mut children = HashMap::new();
+        for node in &graph.nodes {
+            match node.parent_id() {
+                Some(parent) => children
+                    .entry(parent)
+                    .or_insert_with(Vec::new)
+                    .push(node.pid().unwrap()),
+                None => root = node.pid(),
+            }
+        }
+        walk(root.unwrap(), &children)
+    }
+
+    /// Returns the sorted paths of labels of the nodes, independent of the ids and the order of
+    /// the siblings.
+    fn label_paths(mut graph: Graph) -> Vec<String> {
+        let mut paths: Vec<_> = graph
+            .build_label_paths("\u{1f}")
+            .nodes
+            .iter()
+            .map(|x| x.label_path().unwrap().to_owned())
+            .collect();
+        paths.sort_unstable();
+        paths
+    }
+
+    /// Returns a path reading `input` from a pipe, like a process substitution of a shell.
+    #[cfg(unix)]
+    fn pipe(input: &str) -> (std::io::PipeReader, std::path::PathBuf) {
+        use std::io::Write;
+        use std::os::fd::AsRawFd;
+
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        writer.write_all(input.as_bytes()).unwrap();
+        let path = format!("/dev/fd/{}", reader.as_raw_fd()).into();
+        (reader, path)
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_read_pipe() {
+        let input = r#"[{"id": "1", "label": "a"}, {"id": "2", "label": "b", "parent": "1"}]"#;
+        let (_reader, path) = pipe(input);
+        let parsed = read_nodes_from(&Format::Json, Some(&path), &ReadOptions::default()).unwrap();
+        assert_eq!(parsed.nodes.len(), 2);
+
+        for (format, input) in [
+            (Format::Csv, "id,label,parent\n1,a,\n2,b,1\n"),
+            (Format::Tsv, "id\tlabel\tparent\n1\ta\t\n2\tb\t1\n"),
+        ] {
+            let (_reader, path) = pipe(input);
+            let parsed = read_nodes_from(&format, Some(&path), &ReadOptions::default()).unwrap();
+            assert_eq!(parsed.nodes.len(), 2, "{:?}", format);
+        }
+    }
+
+    proptest! {
+        // every case is written and read in all the formats
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_roundtrip(nodes in trees(32)) {
+            let graph = index(nodes);
+            let expected = (shape(&graph), label_paths(index(graph.nodes.to_owned())));
+
+            let options = ReadOptions {
+                columns: ColumnMapping {
+                    id: "classification".to_owned(),
+                    parent: "classification_parent".to_owned(),
+                    label: "classification_label".to_owned(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+
+            for format in formats() {
+                let mut output = Vec::new();
+                write_nodes(&format, &mut output, &graph.nodes, &WriteOptions::default()).unwrap();
+                let parsed = read_nodes(&format, &output[..], &options).unwrap();
+
+                prop_assert!(parsed.rejected.is_empty(), "{:?}: {:?}", format, parsed.rejected);
+
+                let actual = index(parsed.nodes);
+                prop_assert_eq!(shape(&actual), expected.0.to_owned(), "{:?}", format);
+                // the closure table has only the ids, which are replaced with the pids
+                if !matches!(format, Format::Closure) {
+                    prop_assert_eq!(label_paths(actual), expected.1.to_owned(), "{:?}", format);
+                }
+            }
+        }
+    }
+}
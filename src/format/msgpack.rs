@@ -0,0 +1,82 @@
+use crate::data::{ColumnMapping, Node};
+use crate::error;
+use crate::format::{json, OutputColumns, Parsed};
+use serde_json::{Map, Value};
+use std::io;
+use std::io::{BufReader, BufWriter, Write};
+
+/// Reads an array of maps, the same structure as the `json` format.
+pub fn read<R: io::Read>(input: R, columns: &ColumnMapping) -> error::Result<Parsed> {
+    let objects: Vec<Map<String, Value>> = rmp_serde::from_read(BufReader::new(input))?;
+
+    let mut nodes = Vec::new();
+    let mut rejected = Vec::new();
+
+    for (i, object) in objects.into_iter().enumerate() {
+        match json::to_node(object, columns) {
+            Ok(node) => nodes.push(node),
+            Err(e) => rejected.push(error::Error::InvalidRecordError {
+                line: i as u64 + 1,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    Ok(Parsed { nodes, rejected })
+}
+
+/// Writes an array of maps with the column names as keys.
+pub fn write<W: io::Write>(
+    output: W,
+    nodes: &[Node],
+    columns: &OutputColumns,
+) -> error::Result<()> {
+    let records = columns.records(nodes)?;
+
+    let mut writer = BufWriter::new(output);
+    rmp_serde::encode::write_named(&mut writer, &records.rows)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{ColumnMapping, Graph, Node};
+    use crate::format::{read_nodes, write_nodes, Format, ReadOptions, WriteOptions};
+
+    #[test]
+    fn test_msgpack() {
+        let nodes = vec![
+            Node::new("1", "a").with_extra("score", 0.5),
+            Node::new("2", "b").with_parent("1"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let mut output = Vec::new();
+        write_nodes(
+            &Format::Msgpack,
+            &mut output,
+            &graph.nodes,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let options = ReadOptions {
+            columns: ColumnMapping {
+                id: "classification".to_owned(),
+                parent: "classification_parent".to_owned(),
+                label: "classification_label".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let parsed = read_nodes(&Format::Msgpack, &output[..], &options).unwrap();
+
+        assert_eq!(parsed.nodes.len(), 2);
+        assert_eq!(parsed.nodes[1].parent(), Some("1"));
+        assert_eq!(parsed.nodes[1].rgt(), Some(3));
+        assert_eq!(parsed.nodes[0].extra()["score"], 0.5);
+    }
+}
@@ -0,0 +1,327 @@
+use crate::data::Node;
+use crate::error;
+use crate::format::columns::to_field;
+use crate::format::Parsed;
+use serde_json::Number;
+use std::io;
+use std::io::{BufWriter, Write};
+
+/// Extra column holding the branch lengths.
+const LENGTH: &str = "length";
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Comma,
+    Colon,
+    Semicolon,
+    Label(String),
+}
+
+fn invalid(line: u64, message: &str) -> error::Error {
+    error::Error::InvalidRecordError {
+        line,
+        message: message.to_owned(),
+    }
+}
+
+/// Splits Newick text into tokens with their line numbers, skipping whitespace and comments.
+fn tokenize(text: &str) -> error::Result<Vec<(Token, u64)>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '[' => {
+                for c in chars.by_ref() {
+                    match c {
+                        ']' => break,
+                        '\n' => line += 1,
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            '\'' => {
+                let mut label = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            label.push('\'');
+                        }
+                        Some('\'') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            label.push(c);
+                        }
+                        None => return Err(invalid(line, "unterminated quoted label")),
+                    }
+                }
+                Token::Label(label)
+            }
+            c => {
+                let mut label = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()[]':;,".contains(c) {
+                        break;
+                    }
+                    label.push(c);
+                    chars.next();
+                }
+                Token::Label(label.replace('_', " "))
+            }
+        };
+        tokens.push((token, line));
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Default)]
+struct Entry {
+    parent: Option<usize>,
+    label: Option<String>,
+    length: Option<Number>,
+}
+
+/// Reads one or more Newick trees, each terminated by `;`.
+///
+/// Nodes are numbered in pre-order from 1 as their ids since internal nodes are often unnamed.
+/// Names become labels and branch lengths are kept in the `length` column.
+pub fn read<R: io::Read>(mut input: R) -> error::Result<Parsed> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut stack: Vec<usize> = Vec::new();
+    // the node that a following label or length belongs to
+    let mut current = None;
+    // whether a node is expected, that is, the start of a tree or just after `(` or `,`
+    let mut pending = true;
+    let mut started = false;
+
+    let mut tokens = tokenize(&text)?.into_iter();
+    while let Some((token, line)) = tokens.next() {
+        if pending
+            && matches!(
+                token,
+                Token::Label(_) | Token::Colon | Token::Comma | Token::Close
+            )
+        {
+            entries.push(Entry {
+                parent: stack.last().copied(),
+                ..Default::default()
+            });
+            current = Some(entries.len() - 1);
+            pending = false;
+            started = true;
+        }
+
+        match token {
+            Token::Open if pending => {
+                entries.push(Entry {
+                    parent: stack.last().copied(),
+                    ..Default::default()
+                });
+                stack.push(entries.len() - 1);
+                current = None;
+                started = true;
+            }
+            Token::Close => {
+                current = Some(stack.pop().ok_or_else(|| invalid(line, "unbalanced `)`"))?);
+            }
+            Token::Comma if !stack.is_empty() => {
+                current = None;
+                pending = true;
+            }
+            Token::Label(label) => match current.map(|x| &mut entries[x]) {
+                Some(entry @ Entry { label: None, .. }) => entry.label = Some(label),
+                _ => return Err(invalid(line, &format!("unexpected label `{}`", label))),
+            },
+            Token::Colon => {
+                let length = match (tokens.next(), current) {
+                    (Some((Token::Label(x), _)), Some(_)) => x.parse().ok(),
+                    _ => None,
+                };
+                match (length, current) {
+                    (Some(length), Some(i)) => entries[i].length = Some(length),
+                    _ => return Err(invalid(line, "invalid branch length")),
+                }
+            }
+            Token::Semicolon if stack.is_empty() => {
+                current = None;
+                pending = true;
+                started = false;
+            }
+            Token::Open => return Err(invalid(line, "unexpected `(`")),
+            Token::Comma => return Err(invalid(line, "unexpected `,` outside of parentheses")),
+            Token::Semicolon => return Err(invalid(line, "unbalanced `(`")),
+        }
+    }
+
+    if started && !stack.is_empty() {
+        return Err(invalid(
+            text.lines().count() as u64,
+            "unexpected end of input",
+        ));
+    }
+
+    let nodes = entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let mut node = Node::new((i + 1).to_string(), entry.label.unwrap_or_default());
+            if let Some(parent) = entry.parent {
+                node = node.with_parent((parent + 1).to_string());
+            }
+            if let Some(length) = entry.length {
+                node = node.with_extra(LENGTH, length);
+            }
+            node
+        })
+        .collect();
+
+    Ok(Parsed {
+        nodes,
+        rejected: Vec::new(),
+    })
+}
+
+fn quote(label: &str) -> String {
+    if label
+        .chars()
+        .any(|c| c.is_whitespace() || "()[]':;,_".contains(c))
+    {
+        format!("'{}'", label.replace('\'', "''"))
+    } else {
+        label.to_owned()
+    }
+}
+
+/// Writes an indexed tree in Newick with the labels as names and the `length` column as branch
+/// lengths.
+pub fn write<W: io::Write>(output: W, nodes: &[Node]) -> error::Result<()> {
+    let mut writer = BufWriter::new(output);
+
+    let write_node = |writer: &mut BufWriter<W>, node: &Node| -> io::Result<()> {
+        write!(writer, "{}", quote(node.label()))?;
+        match node.extra().get(LENGTH) {
+            Some(length) if !length.is_null() => write!(writer, ":{}", to_field(length)),
+            _ => Ok(()),
+        }
+    };
+
+    let mut order: Vec<&Node> = nodes.iter().collect();
+    order.sort_by_key(|x| x.lft());
+
+    // nodes whose children are being written, closed once a node outside of them comes
+    let mut open: Vec<&Node> = Vec::new();
+    let mut sibling = false;
+
+    for (k, node) in order.iter().copied().enumerate() {
+        while let Some(parent) = open.last() {
+            if parent.rgt() > node.lft() {
+                break;
+            }
+            writer.write_all(b")")?;
+            write_node(&mut writer, parent)?;
+            open.pop();
+        }
+
+        if sibling {
+            writer.write_all(b",")?;
+        }
+
+        match (node.lft(), node.rgt()) {
+            // a node has children if the next node starts inside it, whatever the gap of the values
+            (Some(_), Some(rgt)) if order.get(k + 1).is_some_and(|x| x.lft() < Some(rgt)) => {
+                writer.write_all(b"(")?;
+                open.push(node);
+                sibling = false;
+            }
+            _ => {
+                write_node(&mut writer, node)?;
+                sibling = true;
+            }
+        }
+    }
+
+    while let Some(parent) = open.pop() {
+        writer.write_all(b")")?;
+        write_node(&mut writer, parent)?;
+    }
+    writer.write_all(b";\n")?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::Graph;
+    use crate::format::{read_nodes, write_nodes, Format, ReadOptions, WriteOptions};
+
+    #[test]
+    fn test_newick() {
+        let input = "((A:0.1,'B c':0.2)E:0.5,D_d[comment],)F;\n";
+
+        let parsed =
+            read_nodes(&Format::Newick, input.as_bytes(), &ReadOptions::default()).unwrap();
+        let nodes: Vec<_> = parsed
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.label(), x.parent()))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("1", "F", None),
+                ("2", "E", Some("1")),
+                ("3", "A", Some("2")),
+                ("4", "B c", Some("2")),
+                ("5", "D d", Some("1")),
+                ("6", "", Some("1")),
+            ]
+        );
+        assert_eq!(parsed.nodes[1].extra()["length"], 0.5);
+
+        let mut graph = Graph::new(parsed.nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let mut output = Vec::new();
+        write_nodes(
+            &Format::Newick,
+            &mut output,
+            &graph.nodes,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "((A:0.1,'B c':0.2)E:0.5,'D d',)F;\n"
+        );
+    }
+
+    #[test]
+    fn test_newick_invalid() {
+        for input in ["(A,B;", "(A,B));", "(A,B)C:x;", "(A)B C;"] {
+            let result = read_nodes(&Format::Newick, input.as_bytes(), &ReadOptions::default());
+            assert!(result.is_err(), "{}", input);
+        }
+    }
+}
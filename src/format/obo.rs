@@ -0,0 +1,159 @@
+use crate::data::Node;
+use crate::error;
+use crate::format::Parsed;
+use std::io;
+use std::io::{BufRead, BufReader};
+
+/// Tags of a `[Term]` stanza used to build nodes.
+#[derive(Debug, Default)]
+struct Term {
+    line: u64,
+    id: Option<String>,
+    name: Option<String>,
+    namespace: Option<String>,
+    is_a: Vec<String>,
+    obsolete: bool,
+}
+
+impl Term {
+    /// Returns one node for each `is_a` relation, or a single root node if there is none.
+    fn into_nodes(self) -> error::Result<Vec<Node>> {
+        let id = self.id.ok_or(error::Error::InvalidRecordError {
+            line: self.line,
+            message: "missing id tag".to_owned(),
+        })?;
+
+        let mut node = Node::new(id, self.name.unwrap_or_default());
+        if let Some(namespace) = self.namespace {
+            node = node.with_extra("namespace", namespace);
+        }
+
+        if self.is_a.is_empty() {
+            return Ok(vec![node]);
+        }
+
+        Ok(self
+            .is_a
+            .into_iter()
+            .map(|parent| node.to_owned().with_parent(parent))
+            .collect())
+    }
+}
+
+/// Strips a trailing `! comment` and `{qualifiers}` from a tag value.
+fn tag_value(value: &str) -> &str {
+    let value = value.split_once(" !").map_or(value, |(x, _)| x);
+    let value = match value.trim_end().strip_suffix('}') {
+        Some(x) => x.rsplit_once('{').map_or(value, |(x, _)| x),
+        None => value,
+    };
+    value.trim()
+}
+
+/// Reads the terms of an OBO ontology such as GO or ChEBI.
+///
+/// Each `is_a` relation of a term becomes a node, so terms with several parents make a DAG.
+/// Obsolete terms and stanzas other than `[Term]` are skipped, and the `namespace` tag is kept as
+/// an extra column.
+pub fn read<R: io::Read>(input: R) -> error::Result<Parsed> {
+    let mut nodes = Vec::new();
+    let mut rejected = Vec::new();
+
+    let mut push = |term: Option<Term>| match term {
+        Some(term) if !term.obsolete => match term.into_nodes() {
+            Ok(x) => nodes.extend(x),
+            Err(e) => rejected.push(e),
+        },
+        _ => {}
+    };
+
+    let mut term: Option<Term> = None;
+    for (i, line) in BufReader::new(input).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.starts_with('[') {
+            push(term.take());
+            if line == "[Term]" {
+                term = Some(Term {
+                    line: i as u64 + 1,
+                    ..Default::default()
+                });
+            }
+            continue;
+        }
+
+        let (term, (tag, value)) = match (term.as_mut(), line.split_once(':')) {
+            (Some(term), Some(x)) => (term, x),
+            _ => continue,
+        };
+        let value = tag_value(value);
+
+        match tag {
+            "id" => term.id = Some(value.to_owned()),
+            "name" => term.name = Some(value.to_owned()),
+            "namespace" => term.namespace = Some(value.to_owned()),
+            "is_a" => term.is_a.push(value.to_owned()),
+            "is_obsolete" => term.obsolete = value == "true",
+            _ => {}
+        }
+    }
+    push(term);
+
+    Ok(Parsed { nodes, rejected })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{read_nodes, Format, ReadOptions};
+
+    #[test]
+    fn test_obo() {
+        let input = "format-version: 1.2
+ontology: go
+
+[Term]
+id: GO:0000001
+name: mitochondrion inheritance
+namespace: biological_process
+is_a: GO:0048308 ! organelle inheritance
+is_a: GO:0048311 {source=\"GOC:mah\"} ! mitochondrion distribution
+
+[Term]
+id: GO:0000002
+name: obsolete term
+is_obsolete: true
+
+[Term]
+name: no id
+
+[Typedef]
+id: part_of
+name: part of
+";
+
+        let parsed = read_nodes(&Format::Obo, input.as_bytes(), &ReadOptions::default()).unwrap();
+        let nodes: Vec<_> = parsed
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.label(), x.parent()))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                (
+                    "GO:0000001",
+                    "mitochondrion inheritance",
+                    Some("GO:0048308")
+                ),
+                (
+                    "GO:0000001",
+                    "mitochondrion inheritance",
+                    Some("GO:0048311")
+                ),
+            ]
+        );
+        assert_eq!(parsed.nodes[0].extra()["namespace"], "biological_process");
+        assert_eq!(parsed.rejected.len(), 1);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::data::{ColumnMapping, Node};
+use crate::error;
+use crate::format::record_batch::{read_batch, schema, to_batches};
+use crate::format::{OutputColumns, Parsed};
+use bytes::Bytes;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use std::io;
+
+/// Reads nodes from a Parquet file, converting integer, floating point and boolean columns to
+/// numbers and booleans and the other columns to strings.
+pub fn read<R: io::Read>(mut input: R, columns: &ColumnMapping) -> error::Result<Parsed> {
+    // Parquet files are read from the footer, so the whole input is needed
+    let mut buffer = Vec::new();
+    input.read_to_end(&mut buffer)?;
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(buffer))?.build()?;
+
+    let mut nodes = Vec::new();
+    let mut rejected = Vec::new();
+    for batch in reader {
+        let batch = batch?;
+        let offset = nodes.len() + rejected.len();
+        read_batch(&batch, offset, columns, &mut nodes, &mut rejected)?;
+    }
+
+    Ok(Parsed { nodes, rejected })
+}
+
+pub fn write<W: io::Write>(
+    mut output: W,
+    nodes: &[Node],
+    columns: &OutputColumns,
+) -> error::Result<()> {
+    let records = columns.records(nodes)?;
+    let schema = schema(&records);
+
+    // the writer needs a `Send` output, which stdout is not
+    let mut writer = ArrowWriter::try_new(Vec::new(), schema.to_owned(), None)?;
+    for batch in to_batches(&records, &schema)? {
+        writer.write(&batch)?;
+    }
+
+    output.write_all(&writer.into_inner()?)?;
+    output.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{ColumnMapping, Graph, Node};
+    use crate::format::{read_nodes, write_nodes, Format, ReadOptions, WriteOptions};
+
+    #[test]
+    fn test_parquet() {
+        let nodes = vec![
+            Node::new("1", "a").with_extra("score", 0.5),
+            Node::new("2", "b")
+                .with_parent("1")
+                .with_extra("code", "007"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let mut output = Vec::new();
+        write_nodes(
+            &Format::Parquet,
+            &mut output,
+            &graph.nodes,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+        assert!(output.starts_with(b"PAR1"));
+
+        let options = ReadOptions {
+            columns: ColumnMapping {
+                id: "classification".to_owned(),
+                parent: "classification_parent".to_owned(),
+                label: "classification_label".to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let parsed = read_nodes(&Format::Parquet, &output[..], &options).unwrap();
+
+        assert_eq!(parsed.nodes.len(), 2);
+        assert_eq!(parsed.nodes[1].parent(), Some("1"));
+        assert_eq!(parsed.nodes[1].label(), "b");
+        assert_eq!(parsed.nodes[0].extra()["score"], 0.5);
+        assert_eq!(parsed.nodes[1].extra()["code"], "007");
+        assert_eq!(parsed.nodes[0].extra()["code"], serde_json::Value::Null);
+    }
+}
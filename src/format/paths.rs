@@ -0,0 +1,82 @@
+use crate::data::Node;
+use crate::error;
+use crate::format::Parsed;
+use std::collections::HashSet;
+use std::io;
+use std::io::{BufRead, BufReader};
+
+/// Reads one path such as `Animals/Mammals/Cats` per line, delimited by `separator`.
+///
+/// A node is created for every prefix of the paths, with the prefix as its id and the last
+/// segment as its label, so `Animals/Mammals` is the parent of `Animals/Mammals/Cats`. Empty
+/// segments and blank lines are ignored.
+pub fn read<R: io::Read>(input: R, separator: &str) -> error::Result<Parsed> {
+    let mut nodes = Vec::new();
+    let mut ids = HashSet::new();
+
+    for line in BufReader::new(input).lines() {
+        let line = line?;
+
+        let mut parent: Option<String> = None;
+        for segment in line
+            .split(separator)
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+        {
+            let id = match &parent {
+                Some(parent) => format!("{}{}{}", parent, separator, segment),
+                None => segment.to_owned(),
+            };
+
+            if ids.insert(id.to_owned()) {
+                let mut node = Node::new(id.as_str(), segment);
+                if let Some(parent) = parent {
+                    node = node.with_parent(parent);
+                }
+                nodes.push(node);
+            }
+            parent = Some(id);
+        }
+    }
+
+    Ok(Parsed {
+        nodes,
+        rejected: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{read_nodes, Format, ReadOptions};
+
+    #[test]
+    fn test_paths() {
+        let input =
+            "Animals/Mammals/Cats\n\n/Animals//Birds/\nAnimals/Mammals\nAnimals/Mammals/Dogs\n";
+
+        let parsed = read_nodes(&Format::Path, input.as_bytes(), &ReadOptions::default()).unwrap();
+        let nodes: Vec<_> = parsed
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.label(), x.parent()))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("Animals", "Animals", None),
+                ("Animals/Mammals", "Mammals", Some("Animals")),
+                ("Animals/Mammals/Cats", "Cats", Some("Animals/Mammals")),
+                ("Animals/Birds", "Birds", Some("Animals")),
+                ("Animals/Mammals/Dogs", "Dogs", Some("Animals/Mammals")),
+            ]
+        );
+
+        let options = ReadOptions {
+            path_separator: Some(" > ".to_owned()),
+            ..Default::default()
+        };
+        let parsed = read_nodes(&Format::Path, "a > b > c".as_bytes(), &options).unwrap();
+        assert_eq!(parsed.nodes[2].id(), "a > b > c");
+        assert_eq!(parsed.nodes[2].label(), "c");
+    }
+}
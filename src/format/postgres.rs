@@ -0,0 +1,135 @@
+use crate::data::{ColumnMapping, Node};
+use crate::error;
+use crate::format::{json, sql, Dialect, IfExists, OutputColumns, Parsed};
+use postgres::types::Type;
+use postgres::{Client, NoTls, Row};
+use serde_json::{Map, Value};
+use std::io::Write;
+
+fn from_sql(row: &Row, i: usize) -> Result<Value, postgres::Error> {
+    let value = match *row.columns()[i].type_() {
+        Type::BOOL => row.get::<_, Option<bool>>(i).map(Value::from),
+        Type::INT2 => row.get::<_, Option<i16>>(i).map(Value::from),
+        Type::INT4 => row.get::<_, Option<i32>>(i).map(Value::from),
+        Type::INT8 => row.get::<_, Option<i64>>(i).map(Value::from),
+        Type::FLOAT4 => row.get::<_, Option<f32>>(i).map(Value::from),
+        Type::FLOAT8 => row.get::<_, Option<f64>>(i).map(Value::from),
+        _ => row.try_get::<_, Option<String>>(i)?.map(Value::from),
+    };
+
+    Ok(value.unwrap_or(Value::Null))
+}
+
+/// Reads nodes from the rows returned by `query` on the PostgreSQL database at `url`.
+///
+/// Columns of types other than booleans, numbers and strings should be cast to `text` in the query.
+pub fn read(url: &str, query: &str, columns: &ColumnMapping) -> error::Result<Parsed> {
+    let mut client = Client::connect(url, NoTls)?;
+
+    let mut nodes = Vec::new();
+    let mut rejected = Vec::new();
+
+    for (i, row) in client.query(query, &[])?.iter().enumerate() {
+        let mut object = Map::new();
+        for (j, column) in row.columns().iter().enumerate() {
+            object.insert(column.name().to_owned(), from_sql(row, j)?);
+        }
+
+        match json::to_node(object, columns) {
+            Ok(node) => nodes.push(node),
+            Err(e) => rejected.push(error::Error::InvalidRecordError {
+                line: i as u64 + 1,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    Ok(Parsed { nodes, rejected })
+}
+
+/// Formats a value as a field of `COPY ... FROM STDIN` in the text format.
+fn to_field(value: &Value) -> String {
+    let escape = |x: &str| {
+        x.replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+    };
+
+    match value {
+        Value::Null => "\\N".to_owned(),
+        Value::String(x) => escape(x),
+        x => escape(&x.to_string()),
+    }
+}
+
+/// Writes nodes to `table` of the PostgreSQL database at `url` with `COPY`, creating the table if
+/// missing.
+pub fn write(
+    url: &str,
+    table: &str,
+    if_exists: &IfExists,
+    nodes: &[Node],
+    columns: &OutputColumns,
+) -> error::Result<()> {
+    let records = columns.records(nodes)?;
+    let dialect = Dialect::Postgres;
+
+    let mut client = Client::connect(url, NoTls)?;
+    let mut tx = client.transaction()?;
+
+    if let IfExists::Replace = if_exists {
+        tx.batch_execute(&format!("DROP TABLE IF EXISTS {}", dialect.quote(table)))?;
+    }
+    tx.batch_execute(&sql::create_table(&dialect, table, &records, true))?;
+    if let IfExists::Truncate = if_exists {
+        tx.batch_execute(&format!("TRUNCATE {}", dialect.quote(table)))?;
+    }
+
+    let names = records
+        .headers
+        .iter()
+        .map(|x| dialect.quote(x))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut writer =
+        tx.copy_in(format!("COPY {} ({}) FROM STDIN", dialect.quote(table), names).as_str())?;
+
+    for row in &records.rows {
+        let fields = row.values().map(to_field).collect::<Vec<_>>();
+        writeln!(writer, "{}", fields.join("\t"))?;
+    }
+    writer.finish()?;
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::postgres::to_field;
+    use crate::format::Format;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_field() {
+        assert_eq!(to_field(&json!(null)), "\\N");
+        assert_eq!(to_field(&json!("a\tb\\c\nd")), "a\\tb\\\\c\\nd");
+        assert_eq!(to_field(&json!(true)), "true");
+        assert_eq!(to_field(&json!(1.5)), "1.5");
+    }
+
+    #[test]
+    fn test_from_url() {
+        assert!(matches!(
+            Format::from_url("postgres://localhost/db"),
+            Some(Format::Postgres)
+        ));
+        assert!(matches!(
+            Format::from_url("postgresql://u@localhost/db"),
+            Some(Format::Postgres)
+        ));
+        assert!(Format::from_url("output.csv").is_none());
+    }
+}
@@ -0,0 +1,59 @@
+//! Progress of the parse and write phases, reported as trace events of the bytes passed so far
+//! within the span of the phase.
+
+use std::io;
+use tracing::Span;
+
+/// Number of bytes between two reports of a [`Counted`] reader or writer.
+const STEP: u64 = 1 << 20;
+
+/// Reports that `bytes` have been read or written so far in the phase of `span`.
+pub(crate) fn report(span: &Span, bytes: u64) {
+    tracing::trace!(parent: span, bytes = bytes);
+}
+
+/// Reader or writer reporting the bytes passed through it within the current span, every
+/// [`STEP`] bytes.
+pub(crate) struct Counted<T> {
+    inner: T,
+    bytes: u64,
+    span: Span,
+}
+
+impl<T> Counted<T> {
+    pub(crate) fn new(inner: T) -> Self {
+        Counted {
+            inner,
+            bytes: 0,
+            span: Span::current(),
+        }
+    }
+
+    fn advance(&mut self, n: usize) {
+        let step = self.bytes / STEP;
+        self.bytes += n as u64;
+        if self.bytes / STEP > step {
+            report(&self.span, self.bytes);
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.advance(n);
+        Ok(n)
+    }
+}
+
+impl<W: io::Write> io::Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.advance(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
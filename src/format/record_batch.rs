@@ -0,0 +1,136 @@
+//! Conversion between records and Arrow record batches for the columnar formats.
+
+use crate::data::{ColumnMapping, Node};
+use crate::error;
+use crate::format::columns::{to_field, ColumnType, Records};
+use crate::format::json;
+use arrow::array::{Array, ArrayRef, AsArray, BooleanArray, Float64Array, Int64Array, StringArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Float64Type, Int64Type, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+/// Number of rows of each record batch.
+pub const BATCH_SIZE: usize = 65536;
+
+/// Returns the schema of `records` with the column types inferred from the values.
+pub fn schema(records: &Records) -> SchemaRef {
+    let fields: Vec<Field> = records
+        .headers
+        .iter()
+        .map(|header| {
+            let data_type = match ColumnType::infer(records.rows.iter().map(|x| &x[header])) {
+                ColumnType::Integer => DataType::Int64,
+                ColumnType::Real => DataType::Float64,
+                ColumnType::Boolean => DataType::Boolean,
+                ColumnType::Text => DataType::Utf8,
+            };
+            Field::new(header, data_type, true)
+        })
+        .collect();
+
+    Arc::new(Schema::new(fields))
+}
+
+/// Converts `records` to record batches of [`BATCH_SIZE`] rows.
+pub fn to_batches(records: &Records, schema: &SchemaRef) -> error::Result<Vec<RecordBatch>> {
+    records
+        .rows
+        .chunks(BATCH_SIZE)
+        .map(|rows| {
+            let columns: Vec<ArrayRef> = schema
+                .fields()
+                .iter()
+                .map(|field| {
+                    let values = rows.iter().map(|x| &x[field.name()]);
+                    let array: ArrayRef = match field.data_type() {
+                        DataType::Int64 => {
+                            Arc::new(values.map(|x| x.as_i64()).collect::<Int64Array>())
+                        }
+                        DataType::Float64 => {
+                            Arc::new(values.map(|x| x.as_f64()).collect::<Float64Array>())
+                        }
+                        DataType::Boolean => {
+                            Arc::new(values.map(|x| x.as_bool()).collect::<BooleanArray>())
+                        }
+                        _ => Arc::new(
+                            values
+                                .map(|x| (!x.is_null()).then(|| to_field(x)))
+                                .collect::<StringArray>(),
+                        ),
+                    };
+                    array
+                })
+                .collect();
+
+            Ok(RecordBatch::try_new(schema.to_owned(), columns)?)
+        })
+        .collect()
+}
+
+/// Converts a column to booleans, 64-bit numbers or strings, failing on nested types.
+fn normalize(array: &ArrayRef) -> error::Result<ArrayRef> {
+    let data_type = match array.data_type() {
+        x if x.is_integer() => DataType::Int64,
+        x if x.is_floating()
+            || matches!(x, DataType::Decimal128(..) | DataType::Decimal256(..)) =>
+        {
+            DataType::Float64
+        }
+        DataType::Boolean => DataType::Boolean,
+        _ => DataType::Utf8,
+    };
+
+    Ok(cast(array, &data_type)?)
+}
+
+fn value(array: &ArrayRef, i: usize) -> Value {
+    if array.is_null(i) {
+        return Value::Null;
+    }
+
+    match array.data_type() {
+        DataType::Int64 => Value::from(array.as_primitive::<Int64Type>().value(i)),
+        DataType::Float64 => Value::from(array.as_primitive::<Float64Type>().value(i)),
+        DataType::Boolean => Value::from(array.as_boolean().value(i)),
+        _ => Value::from(array.as_string::<i32>().value(i)),
+    }
+}
+
+/// Converts the rows of a record batch to nodes, collecting the rows that can not be converted.
+///
+/// `offset` is the number of rows of the preceding batches, to report the row numbers.
+pub fn read_batch(
+    batch: &RecordBatch,
+    offset: usize,
+    columns: &ColumnMapping,
+    nodes: &mut Vec<Node>,
+    rejected: &mut Vec<error::Error>,
+) -> error::Result<()> {
+    let schema = batch.schema();
+    let arrays = batch
+        .columns()
+        .iter()
+        .map(normalize)
+        .collect::<error::Result<Vec<_>>>()?;
+
+    for i in 0..batch.num_rows() {
+        let object: Map<String, Value> = schema
+            .fields()
+            .iter()
+            .zip(arrays.iter())
+            .map(|(field, array)| (field.name().to_owned(), value(array, i)))
+            .collect();
+
+        match json::to_node(object, columns) {
+            Ok(node) => nodes.push(node),
+            Err(e) => rejected.push(error::Error::InvalidRecordError {
+                line: (offset + i) as u64 + 1,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,594 @@
+use crate::data::Node;
+use crate::error;
+use crate::format::Parsed;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::{BufWriter, Write};
+
+const SKOS: &str = "http://www.w3.org/2004/02/skos/core#";
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Iri(String),
+    /// Prefixed names, blank node labels, `a`, numbers and booleans.
+    Name(String),
+    Literal(String),
+    /// Language tags and the `@prefix` and `@base` directives.
+    At(String),
+    DataType,
+    Punct(char),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Iri(String),
+    Blank(String),
+    Literal(String, Option<String>),
+    Other,
+}
+
+impl Term {
+    /// Returns the id of a concept named by this term.
+    fn id(&self) -> Option<&str> {
+        match self {
+            Term::Iri(x) | Term::Blank(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(line: u64, message: &str) -> error::Error {
+    error::Error::InvalidRecordError {
+        line,
+        message: message.to_owned(),
+    }
+}
+
+/// Splits Turtle text into tokens with their line numbers, skipping whitespace and comments.
+fn tokenize(text: &str) -> error::Result<Vec<(Token, u64)>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = line;
+        i += 1;
+
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '<' => {
+                let mut iri = String::new();
+                loop {
+                    match chars.get(i) {
+                        Some('>') => break,
+                        Some(c) => iri.push(*c),
+                        None => return Err(invalid(start, "unterminated IRI")),
+                    }
+                    i += 1;
+                }
+                i += 1;
+                Token::Iri(iri)
+            }
+            '"' | '\'' => {
+                let long = chars.get(i) == Some(&c) && chars.get(i + 1) == Some(&c);
+                if long {
+                    i += 2;
+                }
+
+                let mut value = String::new();
+                loop {
+                    match chars.get(i) {
+                        Some(x) if *x == c && !long => break,
+                        Some(x) if *x == c && chars[i..].starts_with(&[c, c, c]) => {
+                            i += 2;
+                            break;
+                        }
+                        Some('\\') => {
+                            i += 1;
+                            match chars.get(i) {
+                                Some('n') => value.push('\n'),
+                                Some('r') => value.push('\r'),
+                                Some('t') => value.push('\t'),
+                                Some('u') | Some('U') => {
+                                    let len = if chars[i] == 'u' { 4 } else { 8 };
+                                    let hex: String = chars.iter().skip(i + 1).take(len).collect();
+                                    let c = u32::from_str_radix(&hex, 16)
+                                        .ok()
+                                        .and_then(char::from_u32)
+                                        .ok_or_else(|| invalid(line, "invalid escape"))?;
+                                    value.push(c);
+                                    i += len;
+                                }
+                                Some(x) => value.push(*x),
+                                None => return Err(invalid(start, "unterminated string")),
+                            }
+                        }
+                        Some(x) => {
+                            if *x == '\n' {
+                                line += 1;
+                            }
+                            value.push(*x);
+                        }
+                        None => return Err(invalid(start, "unterminated string")),
+                    }
+                    i += 1;
+                }
+                i += 1;
+                Token::Literal(value)
+            }
+            '@' => {
+                let mut word = String::new();
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '-') {
+                    word.push(chars[i]);
+                    i += 1;
+                }
+                Token::At(word)
+            }
+            '^' if chars.get(i) == Some(&'^') => {
+                i += 1;
+                Token::DataType
+            }
+            '.' | ';' | ',' | '[' | ']' | '(' | ')' => Token::Punct(c),
+            c => {
+                let mut name = c.to_string();
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !"<>\"'{}|^`;,[]()#".contains(chars[i])
+                {
+                    name.push(chars[i]);
+                    i += 1;
+                }
+
+                // a name can not end with `.`, which ends the statement instead
+                let dots = name.len() - name.trim_end_matches('.').len();
+                name.truncate(name.len() - dots);
+                tokens.push((Token::Name(name), start));
+                tokens.extend((0..dots).map(|_| (Token::Punct('.'), start)));
+                continue;
+            }
+        };
+        tokens.push((token, start));
+    }
+
+    Ok(tokens)
+}
+
+/// Parser of the subset of Turtle used by SKOS vocabularies, collecting all the triples.
+#[derive(Default)]
+struct Parser {
+    tokens: Vec<(Token, u64)>,
+    pos: usize,
+    prefixes: HashMap<String, String>,
+    base: String,
+    blanks: usize,
+    triples: Vec<(Term, String, Term)>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(x, _)| x)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(x, _)| x.to_owned());
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, message: &str) -> error::Error {
+        let line = self
+            .tokens
+            .get(self.pos.saturating_sub(1))
+            .map_or(0, |(_, x)| *x);
+        invalid(line, message)
+    }
+
+    fn expect(&mut self, c: char) -> error::Result<()> {
+        match self.next() {
+            Some(Token::Punct(x)) if x == c => Ok(()),
+            _ => Err(self.error(&format!("expected `{}`", c))),
+        }
+    }
+
+    fn resolve(&self, iri: String) -> String {
+        if iri.contains(':') {
+            iri
+        } else {
+            format!("{}{}", self.base, iri)
+        }
+    }
+
+    fn parse(&mut self) -> error::Result<()> {
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::At(x) if x == "prefix" || x == "base" => {
+                    self.next();
+                    self.directive(&x)?;
+                    self.expect('.')?;
+                }
+                Token::Name(x)
+                    if x.eq_ignore_ascii_case("prefix") || x.eq_ignore_ascii_case("base") =>
+                {
+                    self.next();
+                    self.directive(&x.to_lowercase())?;
+                }
+                _ => {
+                    let subject = self.term(false)?;
+                    if !(matches!(subject, Term::Blank(_))
+                        && self.peek() == Some(&Token::Punct('.')))
+                    {
+                        self.predicate_objects(&subject)?;
+                    }
+                    self.expect('.')?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn directive(&mut self, name: &str) -> error::Result<()> {
+        let prefix = match name {
+            "prefix" => match self.next() {
+                Some(Token::Name(x)) if x.ends_with(':') => {
+                    Some(x.trim_end_matches(':').to_owned())
+                }
+                _ => return Err(self.error("expected a prefix name")),
+            },
+            _ => None,
+        };
+
+        let iri = match self.next() {
+            Some(Token::Iri(x)) => self.resolve(x),
+            _ => return Err(self.error("expected an IRI")),
+        };
+
+        match prefix {
+            Some(prefix) => self.prefixes.insert(prefix, iri),
+            None => Some(std::mem::replace(&mut self.base, iri)),
+        };
+
+        Ok(())
+    }
+
+    fn name(&self, name: &str) -> error::Result<Term> {
+        if let Some(label) = name.strip_prefix("_:") {
+            return Ok(Term::Blank(format!("_:{}", label)));
+        }
+
+        match name.split_once(':') {
+            Some((prefix, local)) => match self.prefixes.get(prefix) {
+                Some(iri) => Ok(Term::Iri(format!("{}{}", iri, local))),
+                None => Err(self.error(&format!("undefined prefix `{}`", prefix))),
+            },
+            None => Ok(Term::Other),
+        }
+    }
+
+    /// Parses a subject, or an object if `object` is set.
+    fn term(&mut self, object: bool) -> error::Result<Term> {
+        match self.next() {
+            Some(Token::Iri(x)) => Ok(Term::Iri(self.resolve(x))),
+            Some(Token::Name(x)) => self.name(&x),
+            Some(Token::Punct('[')) => {
+                self.blanks += 1;
+                let blank = Term::Blank(format!("_:b{}", self.blanks));
+                if self.peek() != Some(&Token::Punct(']')) {
+                    self.predicate_objects(&blank)?;
+                }
+                self.expect(']')?;
+                Ok(blank)
+            }
+            Some(Token::Punct('(')) => {
+                while self.peek() != Some(&Token::Punct(')')) {
+                    self.term(true)?;
+                }
+                self.expect(')')?;
+                Ok(Term::Other)
+            }
+            Some(Token::Literal(value)) if object => {
+                let lang = match self.peek() {
+                    Some(Token::At(x)) => Some(x.to_owned()),
+                    Some(Token::DataType) => {
+                        self.next();
+                        self.term(true)?;
+                        None
+                    }
+                    _ => None,
+                };
+                if lang.is_some() {
+                    self.next();
+                }
+                Ok(Term::Literal(value, lang))
+            }
+            _ => Err(self.error("unexpected token")),
+        }
+    }
+
+    fn predicate_objects(&mut self, subject: &Term) -> error::Result<()> {
+        loop {
+            let predicate = match self.next() {
+                Some(Token::Name(x)) if x == "a" => RDF_TYPE.to_owned(),
+                Some(Token::Iri(x)) => self.resolve(x),
+                Some(Token::Name(x)) => match self.name(&x)? {
+                    Term::Iri(x) => x,
+                    _ => return Err(self.error("expected a predicate")),
+                },
+                _ => return Err(self.error("expected a predicate")),
+            };
+
+            loop {
+                let object = self.term(true)?;
+                self.triples
+                    .push((subject.to_owned(), predicate.to_owned(), object));
+                if self.peek() != Some(&Token::Punct(',')) {
+                    break;
+                }
+                self.next();
+            }
+
+            if self.peek() != Some(&Token::Punct(';')) {
+                return Ok(());
+            }
+            while self.peek() == Some(&Token::Punct(';')) {
+                self.next();
+            }
+            if matches!(self.peek(), Some(Token::Punct('.' | ']')) | None) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Reads the concepts of a SKOS vocabulary in Turtle.
+///
+/// Concepts are the subjects typed `skos:Concept` and the concepts related by `skos:broader` or
+/// `skos:narrower`, identified by their full IRIs. Each broader concept becomes a node, so
+/// concepts with several broader ones make a DAG. A `skos:prefLabel` without a language tag is
+/// preferred as the label, otherwise the first one is used.
+pub fn read<R: io::Read>(mut input: R) -> error::Result<Parsed> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+
+    let mut parser = Parser {
+        tokens: tokenize(&text)?,
+        ..Default::default()
+    };
+    parser.parse()?;
+
+    let mut concepts: Vec<String> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+    let mut labels: HashMap<String, (String, bool)> = HashMap::new();
+
+    let mut add = |id: &str| {
+        if seen.insert(id.to_owned()) {
+            concepts.push(id.to_owned());
+        }
+    };
+
+    for (subject, predicate, object) in &parser.triples {
+        let (subject, object_id) = match (subject.id(), object.id()) {
+            (Some(x), y) => (x, y),
+            _ => continue,
+        };
+
+        match (predicate.strip_prefix(SKOS), object_id) {
+            _ if predicate == RDF_TYPE && object == &Term::Iri(format!("{}Concept", SKOS)) => {
+                add(subject)
+            }
+            (Some("broader"), Some(object)) | (Some("narrower"), Some(object)) => {
+                add(subject);
+                add(object);
+
+                let (child, parent) = match predicate.ends_with("broader") {
+                    true => (subject, object),
+                    false => (object, subject),
+                };
+                let list = parents.entry(child.to_owned()).or_default();
+                if !list.iter().any(|x| x == parent) {
+                    list.push(parent.to_owned());
+                }
+            }
+            (Some("prefLabel"), None) => {
+                if let Term::Literal(value, lang) = object {
+                    let preferred = lang.is_none();
+                    match labels.get(subject) {
+                        Some((_, true)) => {}
+                        Some(_) if !preferred => {}
+                        _ => {
+                            labels.insert(subject.to_owned(), (value.to_owned(), preferred));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut nodes = Vec::new();
+    for id in concepts {
+        let label = labels.get(&id).map_or("", |(x, _)| x.as_str());
+        let node = Node::new(id.as_str(), label);
+
+        match parents.get(&id) {
+            Some(list) => nodes.extend(list.iter().map(|x| node.to_owned().with_parent(x))),
+            None => nodes.push(node),
+        }
+    }
+
+    Ok(Parsed {
+        nodes,
+        rejected: Vec::new(),
+    })
+}
+
+/// Writes an IRI, percent-encoding the characters not allowed in Turtle.
+fn iri(id: &str) -> String {
+    let mut iri = String::from("<");
+    for c in id.chars() {
+        if c <= ' ' || "<>\"{}|^`\\".contains(c) {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                iri.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            iri.push(c);
+        }
+    }
+    iri.push('>');
+    iri
+}
+
+fn string(value: &str) -> String {
+    let value = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t");
+    format!("\"{}\"", value)
+}
+
+/// Writes the nodes as SKOS concepts in Turtle, with their parents as `skos:broader`.
+///
+/// Nodes duplicated from a DAG are written once with their original ids.
+pub fn write<W: io::Write>(output: W, nodes: &[Node]) -> error::Result<()> {
+    let original: HashMap<&str, &str> = nodes
+        .iter()
+        .map(|x| (x.id(), x.origin().unwrap_or_else(|| x.id())))
+        .collect();
+
+    let mut concepts: Vec<(&str, &str, Vec<&str>)> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+
+    for node in nodes {
+        let id = original[node.id()];
+        let i = *index.entry(id).or_insert_with(|| {
+            concepts.push((id, node.label(), Vec::new()));
+            concepts.len() - 1
+        });
+
+        if let Some(parent) = node.parent() {
+            let parent = original.get(parent).copied().unwrap_or(parent);
+            if !concepts[i].2.contains(&parent) {
+                concepts[i].2.push(parent);
+            }
+        }
+    }
+
+    let mut writer = BufWriter::new(output);
+    writeln!(writer, "@prefix skos: <{}> .", SKOS)?;
+
+    for (id, label, broader) in concepts {
+        write!(writer, "\n{} a skos:Concept", iri(id))?;
+        if !label.is_empty() {
+            write!(writer, " ;\n    skos:prefLabel {}", string(label))?;
+        }
+        if !broader.is_empty() {
+            let broader: Vec<String> = broader.into_iter().map(iri).collect();
+            write!(writer, " ;\n    skos:broader {}", broader.join(", "))?;
+        }
+        writeln!(writer, " .")?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{read_nodes, write_nodes, Format, ReadOptions, WriteOptions};
+
+    #[test]
+    fn test_skos() {
+        let input = r#"
+@prefix skos: <http://www.w3.org/2004/02/skos/core#> .
+PREFIX ex: <http://example.org/>
+@base <http://example.org/> .
+
+ex:scheme a skos:ConceptScheme ; skos:hasTopConcept ex:animals .
+
+# concepts
+ex:animals a skos:Concept ;
+    skos:prefLabel "Tiere"@de, "animals" ;
+    skos:narrower <mammals>, ex:birds .
+ex:mammals skos:prefLabel """mammals"""@en ; skos:broader ex:animals .
+ex:bats skos:prefLabel 'bats' ;
+    skos:broader ex:mammals, ex:birds ;
+    skos:notation "B1"^^ex:code ;
+    skos:note [ a ex:Note ] .
+"#;
+
+        let parsed = read_nodes(&Format::Skos, input.as_bytes(), &ReadOptions::default()).unwrap();
+        let nodes: Vec<_> = parsed
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.label(), x.parent()))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("http://example.org/animals", "animals", None),
+                (
+                    "http://example.org/mammals",
+                    "mammals",
+                    Some("http://example.org/animals")
+                ),
+                (
+                    "http://example.org/birds",
+                    "",
+                    Some("http://example.org/animals")
+                ),
+                (
+                    "http://example.org/bats",
+                    "bats",
+                    Some("http://example.org/mammals")
+                ),
+                (
+                    "http://example.org/bats",
+                    "bats",
+                    Some("http://example.org/birds")
+                ),
+            ]
+        );
+
+        let mut output = Vec::new();
+        write_nodes(
+            &Format::Skos,
+            &mut output,
+            &parsed.nodes,
+            &WriteOptions::default(),
+        )
+        .unwrap();
+
+        let reparsed = read_nodes(&Format::Skos, &output[..], &ReadOptions::default()).unwrap();
+        let renodes: Vec<_> = reparsed
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.label(), x.parent()))
+            .collect();
+        assert_eq!(renodes, nodes);
+    }
+
+    #[test]
+    fn test_skos_invalid() {
+        for input in ["ex:a ex:b ex:c .", "<a> <b> \"c", "<a> <b> <c>"] {
+            let result = read_nodes(&Format::Skos, input.as_bytes(), &ReadOptions::default());
+            assert!(result.is_err(), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::data::Node;
+use crate::error;
+use crate::format::columns::ColumnType;
+use crate::format::columns::Records;
+use crate::format::OutputColumns;
+use serde_json::Value;
+use std::io;
+use std::io::{BufWriter, Write};
+use strum::{EnumString, EnumVariantNames};
+
+/// Number of rows in an `INSERT` statement.
+const BATCH_SIZE: usize = 1000;
+
+/// SQL dialect controlling quoting and column types.
+#[derive(Debug, Clone, Default, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum Dialect {
+    #[default]
+    Postgres,
+    Mysql,
+    Sqlite,
+}
+
+impl Dialect {
+    pub fn quote(&self, identifier: &str) -> String {
+        match self {
+            Dialect::Mysql => format!("`{}`", identifier.replace('`', "``")),
+            _ => format!("\"{}\"", identifier.replace('"', "\"\"")),
+        }
+    }
+
+    pub fn type_name(&self, kind: &ColumnType) -> &'static str {
+        match (self, kind) {
+            (Dialect::Sqlite, ColumnType::Integer | ColumnType::Boolean) => "INTEGER",
+            (Dialect::Sqlite, ColumnType::Real) => "REAL",
+            (Dialect::Mysql, ColumnType::Real) => "DOUBLE",
+            (_, ColumnType::Integer) => "BIGINT",
+            (_, ColumnType::Real) => "DOUBLE PRECISION",
+            (_, ColumnType::Boolean) => "BOOLEAN",
+            (_, ColumnType::Text) => "TEXT",
+        }
+    }
+
+    fn literal(&self, value: &Value) -> String {
+        match value {
+            Value::Null => "NULL".to_owned(),
+            Value::Bool(x) => match self {
+                Dialect::Sqlite => (*x as u8).to_string(),
+                _ => x.to_string().to_uppercase(),
+            },
+            Value::Number(x) => x.to_string(),
+            Value::String(x) => self.string(x),
+            x => self.string(&x.to_string()),
+        }
+    }
+
+    fn string(&self, value: &str) -> String {
+        match self {
+            Dialect::Mysql => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "''")),
+            _ => format!("'{}'", value.replace('\'', "''")),
+        }
+    }
+}
+
+/// Builds a `CREATE TABLE` statement with the column types inferred from `records`.
+pub fn create_table(
+    dialect: &Dialect,
+    table: &str,
+    records: &Records,
+    if_not_exists: bool,
+) -> String {
+    let definitions = records
+        .headers
+        .iter()
+        .map(|k| {
+            let kind = ColumnType::infer(records.rows.iter().map(|x| &x[k]));
+            format!("  {} {}", dialect.quote(k), dialect.type_name(&kind))
+        })
+        .collect::<Vec<_>>()
+        .join(",\n");
+
+    format!(
+        "CREATE TABLE {}{} (\n{}\n)",
+        if if_not_exists { "IF NOT EXISTS " } else { "" },
+        dialect.quote(table),
+        definitions
+    )
+}
+
+/// Writes a `CREATE TABLE` statement and batched `INSERT` statements in a transaction.
+pub fn write<W: io::Write>(
+    output: W,
+    table: &str,
+    dialect: &Dialect,
+    nodes: &[Node],
+    columns: &OutputColumns,
+) -> error::Result<()> {
+    let records = columns.records(nodes)?;
+
+    let mut writer = BufWriter::new(output);
+    writeln!(
+        writer,
+        "{};\n",
+        create_table(dialect, table, &records, false)
+    )?;
+
+    let table = dialect.quote(table);
+
+    let names = records
+        .headers
+        .iter()
+        .map(|x| dialect.quote(x))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    writeln!(writer, "BEGIN;")?;
+    for batch in records.rows.chunks(BATCH_SIZE) {
+        writeln!(writer, "INSERT INTO {} ({}) VALUES", table, names)?;
+        for (i, row) in batch.iter().enumerate() {
+            let values = row
+                .values()
+                .map(|x| dialect.literal(x))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let end = if i + 1 == batch.len() { ";" } else { "," };
+            writeln!(writer, "  ({}){}", values, end)?;
+        }
+    }
+    writeln!(writer, "COMMIT;")?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{Graph, Node};
+    use crate::format::{write_nodes, Dialect, Format, OutputColumns, WriteOptions};
+
+    fn script(dialect: Dialect) -> String {
+        let nodes = vec![
+            Node::new("1", "it's"),
+            Node::new("2", "b\\c").with_parent("1"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let options = WriteOptions {
+            columns: OutputColumns {
+                select: vec![
+                    "id".to_owned(),
+                    "classification_label".to_owned(),
+                    "leaf".to_owned(),
+                ],
+                ..Default::default()
+            },
+            dialect,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        write_nodes(&Format::Sql, &mut output, &graph.nodes, &options).unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_postgres() {
+        let script = script(Dialect::Postgres);
+        assert!(
+            script.contains("\"id\" BIGINT,\n  \"classification_label\" TEXT,\n  \"leaf\" BOOLEAN")
+        );
+        assert!(script.contains("  (1, 'it''s', FALSE),\n  (2, 'b\\c', FALSE);"));
+    }
+
+    #[test]
+    fn test_mysql() {
+        let script = script(Dialect::Mysql);
+        assert!(script
+            .contains("INSERT INTO `nested_set` (`id`, `classification_label`, `leaf`) VALUES"));
+        assert!(script.contains("(2, 'b\\\\c', FALSE);"));
+    }
+
+    #[test]
+    fn test_sqlite() {
+        let script = script(Dialect::Sqlite);
+        assert!(script.contains("\"leaf\" INTEGER"));
+        assert!(script.contains("(1, 'it''s', 0),"));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::data::{ColumnMapping, Node};
+use crate::error;
+use crate::format::{json, sql, Dialect, IfExists, OutputColumns, Parsed};
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+fn quote(identifier: &str) -> String {
+    Dialect::Sqlite.quote(identifier)
+}
+
+fn to_sql(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(x) => SqlValue::Integer(*x as i64),
+        Value::Number(x) => match x.as_i64() {
+            Some(x) => SqlValue::Integer(x),
+            None => SqlValue::Real(x.as_f64().unwrap_or_default()),
+        },
+        Value::String(x) => SqlValue::Text(x.to_owned()),
+        x => SqlValue::Text(x.to_string()),
+    }
+}
+
+fn from_sql(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(x) => Value::from(x),
+        ValueRef::Real(x) => Value::from(x),
+        ValueRef::Text(x) | ValueRef::Blob(x) => Value::from(String::from_utf8_lossy(x)),
+    }
+}
+
+/// Reads nodes from the rows returned by `query` on the SQLite database at `path`.
+pub fn read(path: &Path, query: &str, columns: &ColumnMapping) -> error::Result<Parsed> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = conn.prepare(query)?;
+    let names: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(|x| x.to_owned())
+        .collect();
+
+    let mut nodes = Vec::new();
+    let mut rejected = Vec::new();
+
+    let mut rows = statement.query([])?;
+    let mut line = 0;
+    while let Some(row) = rows.next()? {
+        line += 1;
+
+        let mut object = Map::new();
+        for (i, name) in names.iter().enumerate() {
+            object.insert(name.to_owned(), from_sql(row.get_ref(i)?));
+        }
+
+        match json::to_node(object, columns) {
+            Ok(node) => nodes.push(node),
+            Err(e) => rejected.push(error::Error::InvalidRecordError {
+                line,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    Ok(Parsed { nodes, rejected })
+}
+
+/// Writes nodes to `table` of the SQLite database at `path`, creating the table if missing.
+pub fn write(
+    path: &Path,
+    table: &str,
+    if_exists: &IfExists,
+    nodes: &[Node],
+    columns: &OutputColumns,
+) -> error::Result<()> {
+    let records = columns.records(nodes)?;
+
+    let mut conn = Connection::open(path)?;
+    let tx = conn.transaction()?;
+
+    if let IfExists::Replace = if_exists {
+        tx.execute_batch(&format!("DROP TABLE IF EXISTS {}", quote(table)))?;
+    }
+    tx.execute_batch(&sql::create_table(&Dialect::Sqlite, table, &records, true))?;
+    if let IfExists::Truncate = if_exists {
+        tx.execute_batch(&format!("DELETE FROM {}", quote(table)))?;
+    }
+
+    {
+        let mut statement = tx.prepare(&format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            quote(table),
+            records
+                .headers
+                .iter()
+                .map(|x| quote(x))
+                .collect::<Vec<_>>()
+                .join(", "),
+            vec!["?"; records.headers.len()].join(", ")
+        ))?;
+
+        for row in &records.rows {
+            statement.execute(params_from_iter(row.values().map(to_sql)))?;
+        }
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{ColumnMapping, Graph, Node};
+    use crate::format::{read_nodes_from, write_nodes_to, Format, ReadOptions, WriteOptions};
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_read() {
+        let path = std::env::temp_dir().join(format!("nsi-test-read-{}.db", std::process::id()));
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE categories (code INTEGER, parent INTEGER, label TEXT, score REAL);
+             INSERT INTO categories VALUES (1, NULL, 'a', 0.5), (2, 1, 'b', NULL);",
+        )
+        .unwrap();
+
+        let options = ReadOptions {
+            columns: ColumnMapping {
+                id: "code".to_owned(),
+                ..Default::default()
+            },
+            table: Some("categories".to_owned()),
+            ..Default::default()
+        };
+        let parsed = read_nodes_from(&Format::Sqlite, Some(&path), &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(parsed.nodes.len(), 2);
+        assert_eq!(parsed.nodes[1].id(), "2");
+        assert_eq!(parsed.nodes[1].parent(), Some("1"));
+        assert_eq!(parsed.nodes[0].extra()["score"], 0.5);
+    }
+
+    #[test]
+    fn test_write() {
+        let nodes = vec![Node::new("1", "a"), Node::new("2", "b").with_parent("1")];
+        let mut graph = Graph::new(nodes).unwrap();
+        let graph = graph.build_index().unwrap();
+
+        let path = std::env::temp_dir().join(format!("nsi-test-{}.db", std::process::id()));
+        let options = WriteOptions::default();
+        write_nodes_to(&Format::Sqlite, Some(&path), &graph.nodes, &options).unwrap();
+        // overwrites the table when written again
+        write_nodes_to(&Format::Sqlite, Some(&path), &graph.nodes, &options).unwrap();
+
+        let conn = Connection::open(&path).unwrap();
+        let rows: Vec<(i64, String, i64, i64)> = conn
+            .prepare("SELECT id, classification, lft, rgt FROM nested_set ORDER BY id")
+            .unwrap()
+            .query_map([], |x| Ok((x.get(0)?, x.get(1)?, x.get(2)?, x.get(3)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            rows,
+            vec![(1, "1".to_owned(), 1, 4), (2, "2".to_owned(), 2, 3)]
+        );
+    }
+}
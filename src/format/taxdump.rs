@@ -0,0 +1,116 @@
+use crate::data::Node;
+use crate::error;
+use crate::format::Parsed;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Splits a line of a `.dmp` file into its fields delimited by `\t|\t`.
+fn fields(line: &str) -> Vec<&str> {
+    let line = line.trim_end_matches(['\t', '|']);
+    line.split("\t|\t").collect()
+}
+
+/// Reads the scientific names of `names.dmp` by tax id.
+fn read_names<R: io::Read>(input: R) -> error::Result<HashMap<String, String>> {
+    let mut names = HashMap::new();
+
+    for line in BufReader::new(input).lines() {
+        let line = line?;
+        if let [id, name, _, "scientific name", ..] = fields(&line)[..] {
+            names.insert(id.to_owned(), name.to_owned());
+        }
+    }
+
+    Ok(names)
+}
+
+/// Reads the nodes of `nodes.dmp`, labelled with the scientific names of `names.dmp` if given.
+///
+/// The root, whose parent is itself, is read as a node without a parent. The rank is kept in the
+/// `rank` column.
+pub fn read<R: io::Read, S: io::Read>(nodes: R, names: Option<S>) -> error::Result<Parsed> {
+    let names = match names {
+        Some(x) => read_names(x)?,
+        None => HashMap::new(),
+    };
+
+    let mut result = Vec::new();
+    let mut rejected = Vec::new();
+
+    for (i, line) in BufReader::new(nodes).lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+
+        match fields(&line)[..] {
+            [id, parent, rank, ..] => {
+                let label = names.get(id).map(|x| x.as_str()).unwrap_or_default();
+                let mut node = Node::new(id, label).with_extra("rank", rank);
+                if parent != id {
+                    node = node.with_parent(parent);
+                }
+                result.push(node);
+            }
+            _ => rejected.push(error::Error::InvalidRecordError {
+                line: i as u64 + 1,
+                message: "expected tax id, parent tax id and rank".to_owned(),
+            }),
+        }
+    }
+
+    Ok(Parsed {
+        nodes: result,
+        rejected,
+    })
+}
+
+/// Reads `nodes.dmp` and `names.dmp` in the directory at `path`, or next to the file at `path`.
+pub fn read_path(path: &Path) -> error::Result<Parsed> {
+    let dir = if path.is_dir() {
+        path
+    } else {
+        path.parent().unwrap_or_else(|| Path::new("."))
+    };
+
+    let nodes = File::open(dir.join("nodes.dmp"))?;
+    let names = File::open(dir.join("names.dmp"))?;
+
+    read(nodes, Some(names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read;
+
+    #[test]
+    fn test_taxdump() {
+        let nodes = "1\t|\t1\t|\tno rank\t|\t\t|\t8\t|\n\
+                     2\t|\t131567\t|\tsuperkingdom\t|\t\t|\t0\t|\n\
+                     131567\t|\t1\t|\tno rank\t|\t\t|\t8\t|\n\
+                     broken\n";
+        let names = "1\t|\tall\t|\t\t|\tsynonym\t|\n\
+                     1\t|\troot\t|\t\t|\tscientific name\t|\n\
+                     2\t|\tBacteria\t|\tBacteria <bacteria>\t|\tscientific name\t|\n";
+
+        let parsed = read(nodes.as_bytes(), Some(names.as_bytes())).unwrap();
+        let nodes: Vec<_> = parsed
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.label(), x.parent()))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("1", "root", None),
+                ("2", "Bacteria", Some("131567")),
+                ("131567", "", Some("1")),
+            ]
+        );
+        assert_eq!(parsed.nodes[1].extra()["rank"], "superkingdom");
+        assert_eq!(parsed.rejected.len(), 1);
+    }
+}
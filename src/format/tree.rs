@@ -0,0 +1,113 @@
+use crate::data::Node;
+use crate::error;
+use std::io;
+use std::io::{BufWriter, Write};
+
+/// Writes an indexed tree with box-drawing characters, annotating each node with `[lft, rgt]`.
+///
+/// Nodes are shown by their labels, or their ids if the labels are empty.
+pub fn write<W: io::Write>(output: W, nodes: &[Node]) -> error::Result<()> {
+    let mut writer = BufWriter::new(output);
+
+    let mut order: Vec<&Node> = nodes.iter().collect();
+    order.sort_by_key(|x| x.lft());
+
+    let lfts: Vec<Option<usize>> = order.iter().map(|x| x.lft()).collect();
+
+    // `rgt` of the ancestors of the current node and whether they are the last children
+    let mut ancestors: Vec<(Option<usize>, bool)> = Vec::new();
+    for node in order {
+        while ancestors.last().is_some_and(|(rgt, _)| *rgt < node.lft()) {
+            ancestors.pop();
+        }
+
+        let last = match ancestors.last() {
+            // the node is followed by a sibling if a node starts after it inside the parent
+            Some((rgt, _)) => {
+                let next = lfts.partition_point(|x| *x <= node.rgt());
+                lfts.get(next).is_none_or(|lft| lft > rgt)
+            }
+            None => true,
+        };
+
+        for (_, last) in ancestors.iter().skip(1) {
+            writer.write_all(if *last { "    " } else { "│   " }.as_bytes())?;
+        }
+        if !ancestors.is_empty() {
+            writer.write_all(if last { "└── " } else { "├── " }.as_bytes())?;
+        }
+
+        let name = match node.label() {
+            "" => node.id(),
+            x => x,
+        };
+        let index = |x: Option<usize>| x.map_or("-".to_owned(), |x| x.to_string());
+        writeln!(
+            writer,
+            "{} [{}, {}]",
+            name,
+            index(node.lft()),
+            index(node.rgt())
+        )?;
+
+        ancestors.push((node.rgt(), last));
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{Graph, Node, NumberingOptions};
+    use crate::format::{write_nodes, Format, WriteOptions};
+
+    #[test]
+    fn test_tree() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "").with_parent("3"),
+            Node::new("5", "c").with_parent("2"),
+            Node::new("6", "d").with_parent("1"),
+        ];
+        let write = |gap: usize| {
+            let mut graph = Graph::new(nodes.to_owned()).unwrap();
+            let graph = graph
+                .build_index_with(&NumberingOptions {
+                    gap,
+                    ..Default::default()
+                })
+                .unwrap();
+            let mut output = Vec::new();
+            write_nodes(
+                &Format::Tree,
+                &mut output,
+                &graph.nodes,
+                &WriteOptions::default(),
+            )
+            .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            write(1),
+            "root [1, 12]\n\
+             ├── a [2, 9]\n\
+             │   ├── b [3, 6]\n\
+             │   │   └── 4 [4, 5]\n\
+             │   └── c [7, 8]\n\
+             └── d [10, 11]\n"
+        );
+
+        // the branches do not depend on the spacing of the values
+        let branches = |output: String| {
+            output
+                .lines()
+                .map(|x| x.split(" [").next().unwrap_or_default().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(branches(write(10)), branches(write(1)));
+    }
+}
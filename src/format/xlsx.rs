@@ -0,0 +1,111 @@
+use crate::data::ColumnMapping;
+use crate::error;
+use crate::format::{json, Parsed};
+use calamine::{open_workbook_auto_from_rs, Data, Reader};
+use serde_json::{Map, Value};
+use std::io;
+use std::io::Cursor;
+
+fn to_value(cell: &Data) -> Value {
+    match cell {
+        Data::Empty => Value::Null,
+        Data::Int(x) => Value::from(*x),
+        // spreadsheets store all numbers as floats, so ids like `1` are read back as integers
+        Data::Float(x) if x.fract() == 0.0 && x.abs() < i64::MAX as f64 => Value::from(*x as i64),
+        Data::Float(x) => Value::from(*x),
+        Data::Bool(x) => Value::from(*x),
+        Data::String(x) => Value::from(x.as_str()),
+        x => Value::from(x.to_string()),
+    }
+}
+
+/// Reads nodes from `sheet`, or the first sheet, of an Excel or OpenDocument spreadsheet.
+///
+/// The first row is the header, and the columns are mapped the same way as CSV.
+pub fn read<R: io::Read>(
+    mut input: R,
+    sheet: Option<&str>,
+    columns: &ColumnMapping,
+) -> error::Result<Parsed> {
+    let mut buffer = Vec::new();
+    input.read_to_end(&mut buffer)?;
+
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(buffer))?;
+    let range = match sheet {
+        Some(name) => workbook.worksheet_range(name)?,
+        None => workbook
+            .worksheet_range_at(0)
+            .ok_or(calamine::Error::Msg("no sheet found"))??,
+    };
+
+    let mut rows = range.rows();
+    let headers: Vec<String> = match rows.next() {
+        Some(row) => row.iter().map(|x| x.to_string()).collect(),
+        None => Vec::new(),
+    };
+
+    let mut nodes = Vec::new();
+    let mut rejected = Vec::new();
+
+    for (i, row) in rows.enumerate() {
+        if row.iter().all(|x| x == &Data::Empty) {
+            continue;
+        }
+
+        let object: Map<String, Value> = headers
+            .iter()
+            .zip(row.iter())
+            .filter(|(header, _)| !header.is_empty())
+            .map(|(header, cell)| (header.to_owned(), to_value(cell)))
+            .collect();
+
+        match json::to_node(object, columns) {
+            Ok(node) => nodes.push(node),
+            Err(e) => rejected.push(error::Error::InvalidRecordError {
+                line: range.start().map_or(0, |(row, _)| row as u64) + i as u64 + 2,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    Ok(Parsed { nodes, rejected })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{read_nodes, Format, ReadOptions};
+    use rust_xlsxwriter::Workbook;
+
+    #[test]
+    fn test_xlsx() {
+        let mut workbook = Workbook::new();
+        workbook.add_worksheet().set_name("empty").unwrap();
+        let sheet = workbook.add_worksheet().set_name("tree").unwrap();
+        sheet
+            .write_row(0, 0, ["id", "parent", "label", "code"])
+            .unwrap();
+        sheet.write_number(1, 0, 1).unwrap();
+        sheet.write_string(1, 2, "a").unwrap();
+        sheet.write_string(1, 3, "007").unwrap();
+        sheet.write_number(2, 0, 2).unwrap();
+        sheet.write_number(2, 1, 1).unwrap();
+        sheet.write_string(2, 2, "b").unwrap();
+        sheet.write_number(2, 3, 1.5).unwrap();
+        let buffer = workbook.save_to_buffer().unwrap();
+
+        let options = ReadOptions {
+            sheet: Some("tree".to_owned()),
+            ..Default::default()
+        };
+        let parsed = read_nodes(&Format::Xlsx, &buffer[..], &options).unwrap();
+
+        assert_eq!(parsed.nodes.len(), 2);
+        assert_eq!(parsed.nodes[1].id(), "2");
+        assert_eq!(parsed.nodes[1].parent(), Some("1"));
+        assert_eq!(parsed.nodes[0].extra()["code"], "007");
+        assert_eq!(parsed.nodes[1].extra()["code"], 1.5);
+
+        let parsed = read_nodes(&Format::Xlsx, &buffer[..], &ReadOptions::default()).unwrap();
+        assert!(parsed.nodes.is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+//! Random hierarchies for benchmarking and testing.
+
+use crate::data::Node;
+use crate::error;
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+
+/// Shape of a generated hierarchy.
+#[derive(Debug, Clone)]
+pub struct Shape {
+    /// Number of nodes, including the root.
+    pub nodes: usize,
+    /// Maximum depth of the nodes, the root being at depth 0. Unlimited if `None`.
+    pub max_depth: Option<usize>,
+    /// Maximum number of children of a node. Unlimited if `None`.
+    pub max_children: Option<usize>,
+    /// Probability of a node to get a second parent, making a directed acyclic graph.
+    pub extra_parent: f64,
+}
+
+/// Generates a random hierarchy of `shape` from `seed`, the same seed giving the same hierarchy.
+///
+/// The nodes have the ids `1`, `2`, ... and each node is attached to a random earlier node which
+/// is not full yet, so the root is `1`. A second parent is never deeper than the first, keeping
+/// the depths within [`Shape::max_depth`] when the graph is converted to a tree.
+pub fn generate(shape: &Shape, seed: u64) -> error::Result<Vec<Node>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let max_depth = shape.max_depth.unwrap_or(usize::MAX);
+    let max_children = shape.max_children.unwrap_or(usize::MAX);
+
+    let mut nodes = Vec::with_capacity(shape.nodes);
+    let mut depths = Vec::with_capacity(shape.nodes);
+    let mut children = Vec::with_capacity(shape.nodes);
+    // nodes which can still get children
+    let mut open = Vec::new();
+
+    for i in 0..shape.nodes {
+        let id = (i + 1).to_string();
+        let label = format!("node {}", id);
+
+        let depth = if i == 0 {
+            nodes.push(Node::new(id, label));
+            0
+        } else {
+            if open.is_empty() {
+                Err(error::Error::GenerateError(i))?
+            }
+            let k = rng.random_range(0..open.len());
+            let parent: usize = open[k];
+            let mut picked = vec![k];
+            let node = Node::new(id.to_owned(), label.to_owned());
+            nodes.push(node.with_parent((parent + 1).to_string()));
+
+            if shape.extra_parent > 0.0 && rng.random_bool(shape.extra_parent) {
+                let k = rng.random_range(0..open.len());
+                let extra: usize = open[k];
+                if extra != parent && depths[extra] <= depths[parent] {
+                    picked.push(k);
+                    nodes.push(Node::new(id, label).with_parent((extra + 1).to_string()));
+                }
+            }
+
+            // remove from the back so that the other index stays valid
+            picked.sort_unstable_by(|a, b| b.cmp(a));
+            for k in picked {
+                children[open[k]] += 1;
+                if children[open[k]] >= max_children {
+                    open.swap_remove(k);
+                }
+            }
+            depths[parent] + 1
+        };
+
+        depths.push(depth);
+        children.push(0);
+        if depth < max_depth && max_children > 0 {
+            open.push(i);
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Strategy of random trees of 1 to `max_nodes` nodes in a random order, for property tests.
+///
+/// The nodes have the ids `1`, `2`, ... as in [`generate`], and labels with characters which
+/// need quoting in some formats.
+#[cfg(test)]
+pub(crate) fn trees(max_nodes: usize) -> impl proptest::strategy::Strategy<Value = Vec<Node>> {
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    (1..=max_nodes)
+        .prop_flat_map(|n| {
+            (
+                proptest::collection::vec(any::<Index>(), n - 1),
+                proptest::collection::vec("[A-Za-z0-9][A-Za-z0-9 ,;:\"'()-]{0,7}", n),
+            )
+        })
+        .prop_map(|(parents, labels)| {
+            let mut nodes = Vec::with_capacity(labels.len());
+            for (i, label) in labels.into_iter().enumerate() {
+                let node = Node::new((i + 1).to_string(), label);
+                nodes.push(match i {
+                    0 => node,
+                    _ => node.with_parent((parents[i - 1].index(i) + 1).to_string()),
+                });
+            }
+            nodes
+        })
+        .prop_shuffle()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate, Shape};
+    use crate::data::Graph;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_generate() {
+        let shape = Shape {
+            nodes: 200,
+            max_depth: Some(3),
+            max_children: Some(8),
+            extra_parent: 0.2,
+        };
+        let nodes = generate(&shape, 42).unwrap();
+        assert!(nodes.len() > 200);
+        assert_eq!(
+            nodes.iter().map(|x| x.id()).collect::<Vec<_>>(),
+            generate(&shape, 42)
+                .unwrap()
+                .iter()
+                .map(|x| x.id())
+                .collect::<Vec<_>>()
+        );
+
+        let mut children = HashMap::new();
+        for node in &nodes {
+            if let Some(parent) = node.parent() {
+                *children.entry(parent).or_insert(0) += 1;
+            }
+        }
+        assert!(children.values().all(|x| *x <= 8));
+
+        let mut graph = Graph::new(nodes.to_owned()).unwrap();
+        assert!(graph.is_dag());
+        graph = graph.dag_to_tree().unwrap();
+        graph.build_index().unwrap();
+        assert!(graph.nodes.iter().all(|x| x.depth() <= Some(3)));
+
+        let tree = generate(
+            &Shape {
+                extra_parent: 0.0,
+                ..shape.to_owned()
+            },
+            42,
+        )
+        .unwrap();
+        assert_eq!(tree.len(), 200);
+
+        let full = Shape {
+            nodes: 8,
+            max_depth: Some(2),
+            max_children: Some(2),
+            extra_parent: 0.0,
+        };
+        assert!(generate(&full, 0).is_err());
+    }
+}
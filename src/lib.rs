@@ -0,0 +1,38 @@
+//! Assign left/right indices of nested set to a hierarchy given as an adjacency list.
+//!
+//! ```
+//! use nested_set_indexer::{Graph, Node};
+//!
+//! let nodes = vec![
+//!     Node::new("1", "root"),
+//!     Node::new("2", "child").with_parent("1"),
+//! ];
+//!
+//! let mut graph = Graph::new(nodes)?;
+//! if graph.is_dag() {
+//!     graph = graph.dag_to_tree()?;
+//! }
+//! let graph = graph.build_index()?;
+//!
+//! assert_eq!(graph.nodes[0].lft(), Some(1));
+//! assert_eq!(graph.nodes[0].rgt(), Some(4));
+//! # Ok::<(), nested_set_indexer::Error>(())
+//! ```
+
+pub mod aggregate;
+pub mod check;
+pub mod data;
+pub mod diff;
+pub mod error;
+pub mod filter;
+pub mod format;
+pub mod generate;
+pub mod merge;
+pub mod patch;
+pub mod resolve;
+
+pub use data::{
+    ColumnMapping, ComplementOptions, DagOptions, DupNaming, Graph, IndexedNodes, KeepId, Node,
+    NodeCopy, NumberingOptions, PathOptions, PathSegment, RootNode, SiblingOrder,
+};
+pub use error::{Error, ErrorClass, Result};
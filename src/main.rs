@@ -1,96 +1,1117 @@
-use crate::cli::Format;
-use crate::data::Graph;
-use cli::Options;
-use csv::{ReaderBuilder, WriterBuilder};
-use data::Node;
-use std::fs::File;
-use std::io;
-use std::io::{BufReader, BufWriter};
+use cli::{
+    Cli, Command, CompletionsOptions, DagMode, DeleteOptions, DiffOptions, ErrorFormat,
+    GenerateOptions, IndexOptions, InputOptions, InsertOptions, LcaOptions, LogFormat,
+    MergeOptions, MoveOptions, Numbering, OutputOptions, OutputOrder, PatchOptions, QueryOptions,
+    RepairOptions, ServeOptions, SplitOptions, ValidateOptions, VerifyOptions,
+};
+use indicatif::MultiProgress;
+use nested_set_indexer::check::{self, Issue, IssueKind};
+use nested_set_indexer::data::POSITION_COLUMNS;
+use nested_set_indexer::diff::{self, ChangeKind};
+use nested_set_indexer::format::{self, Format, Parsed, ReadOptions};
+use nested_set_indexer::patch::Operation;
+use nested_set_indexer::resolve::{self, MissingParentPolicy, OrphanPolicy, SelfParentFix};
+use nested_set_indexer::{
+    aggregate, error, filter, generate, merge, patch, ErrorClass, Graph, Node, SiblingOrder,
+};
+use progress::{is_progress, ProgressLayer, ProgressWriter, TimingsLayer};
+use rand::RngExt;
+use regex::Regex;
+use serde_json::{json, Map};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::{io, iter, process};
 use structopt::StructOpt;
+use tracing::{debug, info, warn, Level};
+use tracing_subscriber::filter::{filter_fn, LevelFilter};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
 
 mod cli;
-mod data;
-mod error;
+mod config;
+mod progress;
+#[cfg(feature = "serve")]
+mod serve;
 
-fn main() -> error::Result<()> {
-    let options = Options::from_args();
+/// Reads the nodes of the input, warning about the skipped records.
+fn read(options: &InputOptions) -> error::Result<(Format, Parsed)> {
+    read_path(options, options.input.as_deref())
+}
 
-    let from = match &options.from {
-        Some(v) => v.clone(),
-        None => match &options.format_from_input() {
-            Some(v) => v.clone(),
-            None => Err(error::Error::RuntimeError(format!("missing option --from")))?,
-        },
+/// Reads the nodes of the file at `path` with the input options.
+fn read_path(options: &InputOptions, path: Option<&Path>) -> error::Result<(Format, Parsed)> {
+    let from = options.format_of(path)?;
+    let parsed = format::read_nodes_from(&from, path, &options.read_options())?;
+    for e in &parsed.rejected {
+        warn!("skipping record: {}", e);
+    }
+    debug!("read {} node(s)", parsed.nodes.len());
+
+    Ok((from, parsed))
+}
+
+/// Reads and indexes the nodes of the input, returning the graph and the output format.
+fn build(options: &IndexOptions) -> error::Result<(Graph, Format)> {
+    let (from, parsed) = read(&options.input)?;
+    let to = output_format(options, &from)?;
+
+    Ok((build_graph(options, parsed.nodes)?, to))
+}
+
+/// Returns the output format, failing if it can not be written with --no-index.
+fn output_format(options: &IndexOptions, input: &Format) -> error::Result<Format> {
+    let to = options.output.format(input);
+    if options.no_index && to.needs_index() {
+        Err(error::Error::IndexRequiredError(to.to_owned()))?
+    }
+    options.require_closure_output(&to)?;
+
+    Ok(to)
+}
+
+/// Removes the nodes deeper than `max_depth` as [`aggregate::truncate`].
+fn truncate(graph: Graph, max_depth: usize, rollup: bool) -> error::Result<Graph> {
+    let (nodes, pruned) = aggregate::truncate(graph.nodes, max_depth, rollup);
+    debug!("removed {} node(s) deeper than {}", pruned, max_depth);
+    Graph::new(nodes)
+}
+
+/// Indexes `nodes` with the index options.
+fn build_graph(options: &IndexOptions, nodes: Vec<Node>) -> error::Result<Graph> {
+    let (mut graph, order) = prepare_graph(options, nodes)?;
+    if let Some(order) = order {
+        graph.build_index_with(&options.numbering_options())?;
+        finish_index(options, &mut graph, &order);
+    }
+
+    Ok(graph)
+}
+
+/// Builds the graph of `nodes` with the index options up to numbering it, returning the order of
+/// the siblings, or `None` if the graph is kept without numbering it.
+fn prepare_graph(
+    options: &IndexOptions,
+    nodes: Vec<Node>,
+) -> error::Result<(Graph, Option<SiblingOrder>)> {
+    let (mut nodes, normalized) = resolve::normalize_ids(nodes, &options.normalize_ids);
+    if normalized > 0 {
+        debug!("normalized {} id(s) and parent(s)", normalized);
+    }
+    if let Some(root) = &options.root_node {
+        nodes = filter::subtree(nodes, &resolve::normalize_id(root, &options.normalize_ids))?;
+    }
+    let matches = |re: &Regex, x: &Node| re.is_match(x.id()) || re.is_match(x.label());
+    if let Some(re) = &options.exclude {
+        nodes = filter::exclude(nodes, |x| matches(re, x));
+    }
+    if let Some(re) = &options.include {
+        nodes = filter::include(nodes, |x| matches(re, x));
+    }
+    if !options.ranks.is_empty() {
+        let column = &options.rank_column;
+        nodes = filter::collapse(nodes, |x| {
+            let rank = x.extra().get(column).and_then(|x| x.as_str());
+            rank.is_some_and(|x| options.ranks.iter().any(|y| y == x))
+        });
+    }
+
+    let (nodes, fixed) = resolve::self_parents(nodes, options.fix_self_parent.as_ref())?;
+    let action = match options.fix_self_parent {
+        Some(SelfParentFix::Drop) => "dropping it",
+        _ => "removing the parent",
     };
-    let to = match &options.to {
-        Some(v) => v.clone(),
-        None => from.clone(),
+    for id in &fixed {
+        warn!("{} is its own parent, {}", id, action);
+    }
+
+    let (nodes, dropped) = resolve::duplicates(nodes, &options.on_duplicate)?;
+    if dropped > 0 {
+        warn!("collapsed {} duplicated row(s)", dropped);
+    }
+
+    let (nodes, missing) = resolve::missing_parents(nodes, &options.on_missing_parent)?;
+    let action = match options.on_missing_parent {
+        MissingParentPolicy::Skip => "skipping it with its descendants",
+        MissingParentPolicy::CreateStub => "creating it",
+        _ => "attaching it to the root",
     };
+    for x in &missing {
+        warn!("parent {} of {} not found, {}", x.parent, x.id, action);
+    }
 
-    let stdin = io::stdin();
-    let input: Box<dyn io::Read> = match &options.input {
-        Some(path) => {
-            let f = File::open(path)?;
-            Box::new(f)
-        }
-        None => Box::new(stdin.lock()),
+    let orphans = Node::new(&options.orphans_id, &options.orphans_label);
+    let (mut nodes, components) = resolve::orphans(nodes, &options.on_orphans, orphans)?;
+    let action = match options.on_orphans {
+        OrphanPolicy::Drop => "dropping it",
+        _ => "attaching it",
     };
+    for x in &components {
+        warn!(
+            "component of {} is not reachable from the root, {}",
+            x, action
+        );
+    }
 
-    let data = match from {
-        Format::JSON => serde_json::from_reader(BufReader::new(input))?,
-        _ => {
-            let mut builder = ReaderBuilder::new();
-            if let Format::TSV = from {
-                builder.delimiter(b'\t');
-            }
+    if options.infer_leaf {
+        let count = resolve::infer_leaf(&mut nodes);
+        debug!("inferred {} leaf node(s)", count);
+    }
+    for id in resolve::leaf_parents(&mut nodes, &options.on_leaf_parent)? {
+        warn!(
+            "{} is marked as a leaf but is the parent of other nodes, promoting it",
+            id
+        );
+    }
 
-            let mut reader = builder.from_reader(BufReader::new(input));
-            reader
-                .deserialize()
-                .filter_map(|x| x.ok())
-                .collect::<Vec<Node>>()
-        }
+    // a position column orders the children unless another order is given
+    let position = POSITION_COLUMNS
+        .iter()
+        .find(|column| nodes.iter().any(|x| x.extra().contains_key(**column)));
+    let order = match (&options.sort_children, position) {
+        (SiblingOrder::Input, Some(column)) => SiblingOrder::Column((*column).to_owned()),
+        (order, _) => order.to_owned(),
     };
 
-    let mut graph = Graph::new(data)?;
+    let mut graph = if options.allow_forest {
+        let root = Node::new(&options.forest_root_id, &options.forest_root_label);
+        Graph::from_forest(nodes, root)?
+    } else {
+        Graph::new(nodes)?
+    };
+    graph.sort_children(&order);
     if options.complement_leaf {
-        if !options.quiet {
-            eprintln!("complementing leaf nodes...");
+        info!("complementing leaf nodes...");
+        graph = graph.complement_leaf_with(&options.complement_options())?;
+    }
+    match options.dag_mode {
+        DagMode::Closure => {
+            // the closure of a cycle would list every node on it as an ancestor of itself
+            graph.ensure_acyclic()?;
+            return Ok((graph, None));
+        }
+        DagMode::Duplicate if graph.is_dag() => {
+            info!("converting directed acyclic graph to tree...");
+            graph = graph.dag_to_tree_with(&options.dag_options())?;
         }
-        graph = graph.complement_leaf()?;
+        DagMode::PrimaryParent if graph.is_dag() => {
+            info!("keeping the primary parents of directed acyclic graph...");
+            let (tree, dropped) = graph.primary_parent_tree()?;
+            match &options.secondary_edges {
+                Some(path) => {
+                    let mut writer = csv::Writer::from_path(path)?;
+                    for (id, parent) in &dropped {
+                        writer.write_record([id, parent])?;
+                    }
+                    writer.flush()?;
+                    info!("wrote {} secondary edge(s)", dropped.len());
+                }
+                None => {
+                    for (id, parent) in &dropped {
+                        warn!("dropping edge from {} to {}", parent, id);
+                    }
+                }
+            }
+            graph = tree;
+        }
+        _ => (),
+    }
+    if let Some(path) = &options.dup_mapping {
+        let mapping: Map<_, _> = graph
+            .copies()
+            .into_iter()
+            .map(|(id, copies)| (id, json!(copies)))
+            .collect();
+        serde_json::to_writer_pretty(File::create(path)?, &mapping)?;
+    }
+
+    match options.max_depth {
+        Some(max_depth) if !options.rollup => graph = truncate(graph, max_depth, false)?,
+        _ => (),
     }
-    if graph.is_dag() {
-        if !options.quiet {
-            eprintln!("converting directed acyclic graph to tree...");
+    if let Some(path) = &options.counts {
+        let delimiter = match path.extension() {
+            Some(x) if x == "csv" => b',',
+            _ => b'\t',
+        };
+        let counts = aggregate::read_counts(File::open(path)?, delimiter)?;
+        let missing = aggregate::roll_up_counts(&mut graph.nodes, &counts);
+        if let Some(id) = missing.first() {
+            warn!(
+                "{} counted id(s) not found in the tree, such as {}",
+                missing.len(),
+                id
+            );
         }
-        graph = graph.dag_to_tree()?;
+    }
+    if options.prune_zero {
+        let (nodes, pruned) = aggregate::prune_zero(graph.nodes);
+        debug!("pruned {} node(s) without counts", pruned);
+        graph = Graph::new(nodes)?;
     }
 
-    let graph = graph.build_index()?;
+    aggregate::roll_up(&mut graph.nodes, &options.aggregate);
+    if let Some(k) = options.top_children {
+        let (nodes, merged) = aggregate::top_children(graph.nodes, k, &options.other_label);
+        debug!("merged {} node(s) into {}", merged, options.other_label);
+        graph = Graph::new(nodes)?;
+    }
+    match options.max_depth {
+        Some(max_depth) if options.rollup => graph = truncate(graph, max_depth, true)?,
+        _ => (),
+    }
 
-    let stdout = io::stdout();
-    let output: Box<dyn io::Write> = match &options.output {
-        Some(path) => {
-            let f = File::create(path)?;
-            Box::new(f)
+    if options.no_index {
+        return Ok((graph, None));
+    }
+
+    Ok((graph, Some(order)))
+}
+
+/// Whether `order` numbers the siblings in a position column.
+fn numbers_siblings(order: &SiblingOrder) -> bool {
+    matches!(order, SiblingOrder::Column(x) if POSITION_COLUMNS.contains(&x.as_str()))
+}
+
+/// Adds the columns derived from the index of the numbered graph.
+fn finish_index(options: &IndexOptions, graph: &mut Graph, order: &SiblingOrder) {
+    match order {
+        SiblingOrder::Column(column) if numbers_siblings(order) => {
+            graph.number_siblings(column);
         }
-        None => Box::new(stdout.lock()),
-    };
+        _ => (),
+    }
+    if let Some(path_options) = options.path_options() {
+        graph.build_paths(&path_options);
+    }
+    if options.with_label_path {
+        graph.build_label_paths(&options.label_path_separator);
+    }
+    if options.with_hash {
+        graph.build_hashes();
+    }
+}
 
-    match to {
-        Format::JSON => serde_json::to_writer_pretty(BufWriter::new(output), &graph.nodes)?,
-        _ => {
-            let mut builder = WriterBuilder::new();
-            if let Format::TSV = to {
-                builder.delimiter(b'\t');
-            }
+/// Whether the nodes can be written as they are numbered, which needs a format written one row
+/// at a time in the order of `pid` and none of the columns derived from the whole index.
+fn streams(options: &IndexOptions, to: &Format, order: &SiblingOrder) -> bool {
+    to.streams()
+        && options.order == OutputOrder::Pid
+        && !numbers_siblings(order)
+        && options.path_options().is_none()
+        && !options.with_label_path
+        && !options.with_hash
+}
 
-            let mut writer = builder.from_writer(BufWriter::new(output));
-            for record in &graph.nodes {
-                writer.serialize(record)?;
+/// Returns the first row of every id of the input with --order input.
+fn input_rows(options: &IndexOptions, nodes: &[Node]) -> HashMap<String, usize> {
+    let mut rows = HashMap::new();
+    if options.order == OutputOrder::Input {
+        for (i, node) in nodes.iter().enumerate() {
+            rows.entry(node.id().to_owned()).or_insert(i);
+        }
+    }
+    rows
+}
+
+/// Sorts the nodes in --order, the copies of a node following it and the nodes which are not in
+/// `rows` coming last for --order input.
+fn sort_output(options: &IndexOptions, nodes: &mut [Node], rows: &HashMap<String, usize>) {
+    match options.order {
+        OutputOrder::Pid => (),
+        OutputOrder::Lft => nodes.sort_by_key(|x| x.lft()),
+        OutputOrder::Input => nodes.sort_by_key(|x| {
+            let id = x.origin().unwrap_or(x.id());
+            rows.get(id).copied().unwrap_or(usize::MAX)
+        }),
+    }
+}
+
+fn index(options: &IndexOptions) -> error::Result<()> {
+    let (from, parsed) = read(&options.input)?;
+    let to = output_format(options, &from)?;
+    let rows = input_rows(options, &parsed.nodes);
+
+    let (mut graph, order) = prepare_graph(options, parsed.nodes)?;
+    match order {
+        Some(order) if streams(options, &to, &order) => {
+            let indexed = graph.into_indexed(&options.numbering_options())?;
+            return format::write_indexed_to(
+                &to,
+                options.output.output.as_deref(),
+                indexed,
+                &options.write_options(),
+            );
+        }
+        Some(order) => {
+            graph.build_index_with(&options.numbering_options())?;
+            finish_index(options, &mut graph, &order);
+        }
+        None => (),
+    }
+    sort_output(options, &mut graph.nodes, &rows);
+
+    format::write_nodes_to(
+        &to,
+        options.output.output.as_deref(),
+        &graph.nodes,
+        &options.write_options(),
+    )
+}
+
+fn merge(options: &MergeOptions) -> error::Result<()> {
+    let (from, parsed) = read(&options.index.input)?;
+    let to = output_format(&options.index, &from)?;
+
+    let mut inputs = vec![parsed.nodes];
+    for path in &options.inputs {
+        let (_, parsed) = read_path(&options.index.input, Some(path))?;
+        inputs.push(parsed.nodes);
+    }
+
+    let nodes = merge::merge(inputs, &options.on_conflict)?;
+    let rows = input_rows(&options.index, &nodes);
+    let mut graph = build_graph(&options.index, nodes)?;
+    sort_output(&options.index, &mut graph.nodes, &rows);
+
+    format::write_nodes_to(
+        &to,
+        options.index.output.output.as_deref(),
+        &graph.nodes,
+        &options.index.write_options(),
+    )
+}
+
+/// Returns the file names of the subtrees of `roots` made of their ids, which are suffixed with
+/// their pids if the names would be the same on a case-insensitive filesystem.
+fn file_names(roots: &[&Node]) -> Vec<String> {
+    let mut used = HashSet::new();
+    roots
+        .iter()
+        .map(|root| {
+            let name: String = root
+                .id()
+                .chars()
+                .map(|c| if matches!(c, '/' | '\\') { '_' } else { c })
+                .collect();
+            if used.insert(name.to_lowercase()) {
+                return name;
             }
+            warn!(
+                "file name of {} is already used, suffixing it with the pid",
+                root.id()
+            );
+            let name = format!("{}_{}", name, root.pid().unwrap_or_default());
+            used.insert(name.to_lowercase());
+            name
+        })
+        .collect()
+}
+
+fn split(options: &SplitOptions) -> error::Result<()> {
+    options.index.require_index()?;
+    let (graph, to) = build(&options.index)?;
+    let write_options = options.index.write_options();
+
+    let extension: &str = (&to).into();
+    let extension = match &write_options.compression {
+        Some(compression) => format!("{}.{}", extension, compression.extension()),
+        None => extension.to_owned(),
+    };
+
+    fs::create_dir_all(&options.output_dir)?;
+    let subtrees = graph.split(matches!(options.numbering, Numbering::Local));
+    let roots: Vec<&Node> = subtrees.iter().map(|x| &x[0]).collect();
+    for (nodes, name) in subtrees.iter().zip(file_names(&roots)) {
+        let path = options.output_dir.join(format!("{}.{}", name, extension));
+        format::write_nodes_to(&to, Some(&path), nodes, &write_options)?;
+    }
+    info!("wrote {} subtree(s)", subtrees.len());
+
+    Ok(())
+}
+
+fn generate(options: &GenerateOptions) -> error::Result<()> {
+    let seed = options.seed.unwrap_or_else(|| rand::rng().random());
+    info!("generating with seed {}...", seed);
+
+    let mut nodes = generate::generate(&options.shape(), seed)?;
+    let mut write_options = options.output.write_options(Vec::new());
+    if options.index {
+        let mut graph = Graph::new(nodes)?;
+        if graph.is_dag() {
+            graph = graph.dag_to_tree()?;
+        }
+        graph.build_index()?;
+        nodes = graph.nodes;
+    } else if write_options.columns.select.is_empty() {
+        // the input columns of `index` by default
+        for (column, name) in [
+            ("classification", "id"),
+            ("classification_parent", "parent"),
+            ("classification_label", "label"),
+        ] {
+            write_options.columns.select.push(column.to_owned());
+            write_options
+                .columns
+                .rename
+                .push((column.to_owned(), name.to_owned()));
         }
+    }
+
+    format::write_nodes_to(
+        &options.output.format(&Format::Csv),
+        options.output.output.as_deref(),
+        &nodes,
+        &write_options,
+    )
+}
+
+fn query(options: &QueryOptions) -> error::Result<()> {
+    options.index.require_index()?;
+    let (graph, to) = build(&options.index)?;
+
+    let nodes: Vec<Node> = if options.ancestors {
+        graph.ancestors(&options.node)?
+    } else if options.descendants {
+        graph.descendants(&options.node)?
+    } else if options.subtree {
+        graph.subtree(&options.node)?
+    } else {
+        unreachable!("one of the relations is required")
+    }
+    .into_iter()
+    .cloned()
+    .collect();
+
+    format::write_nodes_to(
+        &to,
+        options.index.output.output.as_deref(),
+        &nodes,
+        &options.index.write_options(),
+    )
+}
+
+/// Reads all the nodes of the input, reporting the records which can not be parsed as issues.
+fn read_with_issues(options: &InputOptions) -> error::Result<(Vec<Node>, Vec<Issue>)> {
+    let from = options.format()?;
+    let read_options = ReadOptions {
+        strict: false,
+        ..options.read_options()
     };
+    let parsed = format::read_nodes_from(&from, options.input.as_deref(), &read_options)?;
+
+    let issues = parsed
+        .rejected
+        .iter()
+        .map(|e| Issue {
+            kind: IssueKind::InvalidRecord,
+            row: None,
+            line: e.line(),
+            id: None,
+            message: e.to_string(),
+        })
+        .collect();
+
+    Ok((parsed.nodes, issues))
+}
+
+/// Prints the issues as JSON, failing if there is any.
+fn report(nodes: &[Node], issues: Vec<Issue>) -> error::Result<()> {
+    let report = json!({
+        "valid": issues.is_empty(),
+        "nodes": nodes.len(),
+        "issues": issues,
+    });
+    serde_json::to_writer_pretty(io::stdout().lock(), &report)?;
+    println!();
+
+    if !issues.is_empty() {
+        Err(error::Error::ValidationError(issues.len()))?
+    }
 
     Ok(())
 }
+
+fn validate(options: &ValidateOptions) -> error::Result<()> {
+    let (nodes, mut issues) = read_with_issues(&options.input)?;
+    issues.extend(check::validate(&nodes));
+    report(&nodes, issues)
+}
+
+fn verify(options: &VerifyOptions) -> error::Result<()> {
+    let (nodes, mut issues) = read_with_issues(&options.input)?;
+    issues.extend(check::verify(&nodes, options.allow_gaps));
+    report(&nodes, issues)
+}
+
+fn repair(options: &RepairOptions) -> error::Result<()> {
+    let (from, parsed) = read(&options.input)?;
+    let to = options.output.format(&from);
+
+    let bounds: HashMap<String, _> = parsed
+        .nodes
+        .iter()
+        .map(|x| (x.id().to_owned(), (x.lft(), x.rgt())))
+        .collect();
+
+    let mut graph = Graph::new(parsed.nodes)?;
+    graph.rebuild_index()?;
+
+    if tracing::enabled!(Level::INFO) {
+        let changed = graph
+            .nodes
+            .iter()
+            .filter(|x| bounds.get(x.id()) != Some(&(x.lft(), x.rgt())))
+            .count();
+        info!("renumbered {} of {} node(s)", changed, graph.nodes.len());
+    }
+
+    format::write_nodes_to(
+        &to,
+        options.output.output.as_deref(),
+        &graph.nodes,
+        &options.output.write_options(Vec::new()),
+    )
+}
+
+fn renumber(options: &RepairOptions) -> error::Result<()> {
+    let (from, parsed) = read(&options.input)?;
+    let to = options.output.format(&from);
+
+    let mut graph = Graph::new(parsed.nodes)?;
+    graph.renumber()?;
+
+    format::write_nodes_to(
+        &to,
+        options.output.output.as_deref(),
+        &graph.nodes,
+        &options.output.write_options(Vec::new()),
+    )
+}
+
+fn lca(options: &LcaOptions) -> error::Result<()> {
+    options.index.require_index()?;
+    let (graph, _) = build(&options.index)?;
+
+    let ids: Vec<&str> = options.nodes.iter().map(|x| x.as_str()).collect();
+    let lca = graph.lowest_common_ancestor(&ids)?;
+
+    let mut paths = Map::new();
+    for id in ids {
+        let path: Vec<&str> = graph
+            .ancestors(id)?
+            .into_iter()
+            .filter(|x| x.lft() >= lca.lft())
+            .chain(iter::once(graph.find(id)?))
+            .map(|x| x.id())
+            .collect();
+        paths.insert(id.to_owned(), json!(path));
+    }
+
+    let report = json!({
+        "lca": {
+            "id": lca.id(),
+            "label": lca.label(),
+            "lft": lca.lft(),
+            "rgt": lca.rgt(),
+        },
+        "paths": paths,
+    });
+    serde_json::to_writer_pretty(io::stdout().lock(), &report)?;
+    println!();
+
+    Ok(())
+}
+
+fn diff(options: &DiffOptions) -> error::Result<()> {
+    let (_, new) = read(&options.input)?;
+
+    let from = options.input.format_of(Some(&options.old))?;
+    let old = format::read_nodes_from(&from, Some(&options.old), &options.input.read_options())?;
+
+    let changes = diff::diff(&old.nodes, &new.nodes);
+    let count = |kind: ChangeKind| changes.iter().filter(|x| x.kind == kind).count();
+    let report = json!({
+        "added": count(ChangeKind::Added),
+        "removed": count(ChangeKind::Removed),
+        "moved": count(ChangeKind::Moved),
+        "relabeled": count(ChangeKind::Relabeled),
+        "changes": changes,
+    });
+    serde_json::to_writer_pretty(io::stdout().lock(), &report)?;
+    println!();
+
+    Ok(())
+}
+
+/// Applies `operations` to the indexed input and writes it.
+fn edit(
+    input: &InputOptions,
+    output: &OutputOptions,
+    operations: &[Operation],
+) -> error::Result<()> {
+    let (from, parsed) = read(input)?;
+    let to = output.format(&from);
+
+    let mut graph = Graph::new(parsed.nodes)?;
+    patch::apply(&mut graph, operations)?;
+    info!("applied {} operation(s)", operations.len());
+
+    format::write_nodes_to(
+        &to,
+        output.output.as_deref(),
+        &graph.nodes,
+        &output.write_options(Vec::new()),
+    )
+}
+
+fn patch(options: &PatchOptions) -> error::Result<()> {
+    let operations = patch::read(File::open(&options.changes)?)?;
+    edit(&options.input, &options.output, &operations)
+}
+
+fn insert(options: &InsertOptions) -> error::Result<()> {
+    let operation = Operation::Add {
+        id: options.id.to_owned(),
+        parent: options.parent.to_owned(),
+        label: options
+            .label
+            .to_owned()
+            .unwrap_or_else(|| options.id.to_owned()),
+    };
+    edit(&options.input, &options.output, &[operation])
+}
+
+fn move_subtree(options: &MoveOptions) -> error::Result<()> {
+    let operation = Operation::Move {
+        id: options.node.to_owned(),
+        parent: options.new_parent.to_owned(),
+    };
+    edit(&options.input, &options.output, &[operation])
+}
+
+fn delete_subtree(options: &DeleteOptions) -> error::Result<()> {
+    let operation = Operation::Delete {
+        id: options.node.to_owned(),
+        promote_children: options.promote_children,
+    };
+    edit(&options.input, &options.output, &[operation])
+}
+
+/// Runs `run`, and again whenever the input of `options` or one of `inputs` changes with --watch.
+fn watching<F: Fn() -> error::Result<()>>(
+    options: &IndexOptions,
+    inputs: &[PathBuf],
+    run: F,
+) -> error::Result<()> {
+    if !options.watch {
+        return run();
+    }
+
+    let input = options.input.input.as_ref().ok_or_else(|| {
+        error::Error::UsageError("--watch needs an input file instead of stdin".to_owned())
+    })?;
+    let paths: Vec<&Path> = iter::once(input)
+        .chain(inputs)
+        .map(|x| x.as_path())
+        .collect();
+
+    watch(&paths, run)
+}
+
+/// Runs `run` whenever one of `paths` changes until interrupted, reporting the errors without
+/// exiting so that the input can be fixed.
+#[cfg(feature = "watch")]
+fn watch<F: Fn() -> error::Result<()>>(paths: &[&Path], run: F) -> error::Result<()> {
+    use notify::{RecursiveMode, Watcher};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let files = paths
+        .iter()
+        .map(fs::canonicalize)
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut dirs: Vec<&Path> = files.iter().filter_map(|x| x.parent()).collect();
+    dirs.sort_unstable();
+    dirs.dedup();
+
+    // editors often replace the file instead of writing it, so the directories are watched
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for dir in dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    let report = |result: error::Result<()>| {
+        if let Err(e) = result {
+            tracing::error!("{}", e);
+        }
+    };
+    report(run());
+
+    for event in &receiver {
+        let event = event?;
+        if event.kind.is_access() || !event.paths.iter().any(|x| files.contains(x)) {
+            continue;
+        }
+        // wait for the rest of the writes
+        while receiver.recv_timeout(Duration::from_millis(100)).is_ok() {}
+
+        info!("input changed, running again...");
+        report(run());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "watch"))]
+fn watch<F: Fn() -> error::Result<()>>(_: &[&Path], _: F) -> error::Result<()> {
+    Err(error::Error::UsageError(
+        "--watch needs the `watch` feature".to_owned(),
+    ))
+}
+
+#[cfg(feature = "serve")]
+fn serve(options: &ServeOptions) -> error::Result<()> {
+    serve::serve(options)
+}
+
+#[cfg(not(feature = "serve"))]
+fn serve(_: &ServeOptions) -> error::Result<()> {
+    Err(error::Error::UsageError(
+        "serve needs the `serve` feature".to_owned(),
+    ))
+}
+
+fn completions<W: io::Write>(options: &CompletionsOptions, output: &mut W) {
+    Cli::clap().gen_completions_to(env!("CARGO_PKG_NAME"), options.shell, output);
+}
+
+/// Logs to stderr at the level of --quiet or --verbose, with the timings of the phases from -v,
+/// and shows the running phase if stderr is a terminal. Returns the layer recording the phases
+/// for --timings.
+fn init_logging(cli: &Cli) -> Option<TimingsLayer> {
+    let level = log_level(cli);
+
+    let progress = MultiProgress::new();
+    let show_progress = shows_progress(cli, io::stderr().is_terminal());
+    let writer = if show_progress {
+        let progress = progress.to_owned();
+        BoxMakeWriter::new(move || ProgressWriter(progress.to_owned()))
+    } else {
+        BoxMakeWriter::new(io::stderr)
+    };
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(io::stderr().is_terminal())
+        .with_span_events(FmtSpan::CLOSE)
+        .with_target(false);
+
+    // the timings of the phases are only shown with the time
+    let layer = match cli.log_format {
+        LogFormat::Text if level < Level::DEBUG => layer.without_time().boxed(),
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
+    let progress =
+        show_progress.then(|| ProgressLayer::new(progress).with_filter(filter_fn(is_progress)));
+
+    let timings = cli.timings.then(TimingsLayer::default);
+
+    tracing_subscriber::registry()
+        .with(layer.with_filter(LevelFilter::from_level(level)))
+        .with(progress)
+        .with(timings.to_owned())
+        .init();
+
+    timings
+}
+
+/// Returns the level of the log messages: only errors with --quiet, and more with every -v.
+fn log_level(cli: &Cli) -> Level {
+    match cli.verbose {
+        _ if cli.command.quiet() => Level::ERROR,
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+/// Whether the running phase is shown, which is only if stderr is a `terminal` and not --quiet.
+fn shows_progress(cli: &Cli, terminal: bool) -> bool {
+    terminal && !cli.command.quiet()
+}
+
+/// Returns the exit status of an error of `class`.
+fn exit_code(class: ErrorClass) -> i32 {
+    match class {
+        ErrorClass::Other => 1,
+        ErrorClass::Usage => 2,
+        ErrorClass::Parse => 3,
+        ErrorClass::Structure => 4,
+        ErrorClass::Io => 5,
+    }
+}
+
+fn error_json(e: &error::Error) -> serde_json::Value {
+    let mut value = json!({
+        "kind": e.kind(),
+        "class": e.class(),
+        "message": e.to_string(),
+    });
+    if let Some(id) = e.node_id() {
+        value["id"] = json!(id);
+    }
+    if let Some(row) = e.row() {
+        value["row"] = json!(row);
+    }
+    if let Some(line) = e.line() {
+        value["line"] = json!(line);
+    }
+    if !e.causes().is_empty() {
+        value["errors"] = e.causes().iter().map(error_json).collect();
+    }
+    value
+}
+
+/// Writes the error to stderr in --error-format.
+fn report_error(e: &error::Error, format: &ErrorFormat) {
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {}", e),
+        ErrorFormat::Json => eprintln!("{}", error_json(e)),
+    }
+}
+
+fn run(command: &Command) -> error::Result<()> {
+    match command {
+        Command::Index(options) => watching(options, &[], || index(options)),
+        Command::Validate(options) => validate(options),
+        Command::Verify(options) => verify(options),
+        Command::Repair(options) => repair(options),
+        Command::Renumber(options) => renumber(options),
+        Command::Query(options) => watching(&options.index, &[], || query(options)),
+        Command::Lca(options) => watching(&options.index, &[], || lca(options)),
+        Command::Diff(options) => diff(options),
+        Command::Patch(options) => patch(options),
+        Command::Insert(options) => insert(options),
+        Command::Move(options) => move_subtree(options),
+        Command::Delete(options) => delete_subtree(options),
+        Command::Merge(options) => watching(&options.index, &options.inputs, || merge(options)),
+        Command::Split(options) => watching(&options.index, &[], || split(options)),
+        Command::Generate(options) => generate(options),
+        Command::Serve(options) => serve(options),
+        Command::Completions(options) => {
+            completions(options, &mut io::stdout().lock());
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    let cli = match Cli::from_args() {
+        Ok(cli) => cli,
+        Err(e) => {
+            report_error(&e, &Cli::error_format());
+            process::exit(exit_code(e.class()));
+        }
+    };
+    let timings = init_logging(&cli);
+
+    let result = run(&cli.command);
+    if let Some(timings) = timings {
+        timings.report();
+    }
+    if let Err(e) = result {
+        report_error(&e, &cli.error_format);
+        process::exit(exit_code(e.class()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        completions, file_names, index, log_level, shows_progress, watching, Cli,
+        CompletionsOptions, IndexOptions, LogFormat,
+    };
+    use nested_set_indexer::{error, Graph, Node};
+    use std::{env, fs, process};
+    use structopt::StructOpt;
+    use tracing::Level;
+
+    /// Runs index on `input` with `args`, returning the lines of the `output` file.
+    fn run_index(name: &str, input: &str, output: &str, args: &[&str]) -> Vec<String> {
+        let dir = env::temp_dir().join(format!("nested_set_indexer-{}-{}", process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let (input_path, output_path) = (dir.join("input.csv"), dir.join(output));
+        fs::write(&input_path, input).unwrap();
+
+        let paths = [
+            input_path.to_str().unwrap(),
+            "-o",
+            output_path.to_str().unwrap(),
+        ];
+        let args = ["index"].iter().chain(&paths).chain(args);
+        index(&IndexOptions::from_iter(args)).unwrap();
+
+        let output = fs::read_to_string(&output_path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        output.lines().map(|x| x.to_owned()).collect()
+    }
+
+    #[test]
+    fn test_file_names() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("a/b", "a").with_parent("1"),
+            Node::new("a_b", "b").with_parent("1"),
+            Node::new("A_B", "c").with_parent("1"),
+            Node::new("c", "d").with_parent("1"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        graph.build_index().unwrap();
+
+        let roots: Vec<&Node> = graph.nodes[1..].iter().collect();
+        assert_eq!(file_names(&roots), ["a_b", "a_b_3", "A_B_4", "c"]);
+    }
+
+    #[test]
+    fn test_order() {
+        // the pids are taken from the ids, so that the order of pid differs from the input
+        let input = "id,parent,label\n1,,r\n3,2,c\n2,1,b\n4,1,d\n5,3,e\n";
+        let rows = |args: &[&str]| {
+            let lines = run_index(&args.join(""), input, "output.csv", args);
+            let columns = |x: &String| x.split(',').map(|x| x.to_owned()).collect::<Vec<_>>();
+            let header = columns(&lines[0]);
+            let column = |name: &str| header.iter().position(|x| x == name).unwrap();
+            let (id, lft) = (column("classification"), column("lft"));
+            lines[1..]
+                .iter()
+                .map(|x| {
+                    let x = columns(x);
+                    format!("{}:{}", x[id], x[lft])
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let pid = ["1:1", "2:2", "3:3", "4:8", "5:4"];
+        let lft = ["1:1", "2:2", "3:3", "5:4", "4:8"];
+        let input_order = ["1:1", "3:3", "2:2", "4:8", "5:4"];
+        // the pid order is written as the nodes are numbered unless a column needs the whole index
+        assert_eq!(rows(&["--pid-from-id"]), pid);
+        assert_eq!(rows(&["--pid-from-id", "--with-path"]), pid);
+        assert_eq!(rows(&["--pid-from-id", "--order", "lft"]), lft);
+        assert_eq!(rows(&["--pid-from-id", "--order", "input"]), input_order);
+
+        let gap = ["1:10", "2:20", "3:30", "4:80", "5:40"];
+        assert_eq!(rows(&["--pid-from-id", "--gap", "10"]), gap);
+        assert_eq!(rows(&["--pid-from-id", "--gap", "10", "--with-hash"]), gap);
+        assert_eq!(
+            rows(&["--pid-from-id", "--gap", "10", "--order", "lft"]),
+            ["1:10", "2:20", "3:30", "5:40", "4:80"]
+        );
+        assert_eq!(
+            rows(&["--pid-from-id", "--gap", "10", "--order", "input"]),
+            ["1:10", "3:30", "2:20", "4:80", "5:40"]
+        );
+    }
+
+    #[test]
+    fn test_no_index() {
+        // the input numbering is kept even where it is not a valid nested set
+        let input = "id,parent,label,lft,rgt\n1,,r,100,200\n2,1,a,110,150\n3,1,b,160,190\n\
+                     4,2,c,120,130\n4,3,c,170,180\n";
+        let lines = run_index("no_index", input, "output.jsonl", &["--no-index"]);
+        let values: Vec<serde_json::Value> = lines
+            .iter()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect();
+        let rows: Vec<_> = values
+            .iter()
+            .map(|x| {
+                let field = |name: &str| x[name].to_string().replace('"', "");
+                format!(
+                    "{}:{}:{}:{}",
+                    field("classification"),
+                    field("classification_parent"),
+                    field("lft"),
+                    field("rgt")
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "1:null:100:200",
+                "2:1:110:150",
+                "3:1:160:190",
+                "4:2:120:130",
+                "4__1:3:170:180"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shows_progress() {
+        let cli = Cli::from_iter(["nested_set_indexer", "index", "input.csv"]);
+        assert!(shows_progress(&cli, true));
+        assert!(!shows_progress(&cli, false));
+
+        let cli = Cli::from_iter(["nested_set_indexer", "index", "input.csv", "--quiet"]);
+        assert!(!shows_progress(&cli, true));
+    }
+
+    #[test]
+    fn test_log_level() {
+        let cli = |args: &[&str]| {
+            let args = ["nested_set_indexer"]
+                .iter()
+                .chain(args)
+                .chain(&["index", "t.csv"]);
+            Cli::from_iter(args)
+        };
+        assert_eq!(log_level(&cli(&[])), Level::INFO);
+        assert_eq!(log_level(&cli(&["-v"])), Level::DEBUG);
+        assert_eq!(log_level(&cli(&["-vv"])), Level::TRACE);
+        assert_eq!(log_level(&cli(&["-vvv"])), Level::TRACE);
+
+        // --quiet is an option of the command, and wins over -v
+        let cli = Cli::from_iter(["nested_set_indexer", "-v", "index", "t.csv", "--quiet"]);
+        assert_eq!(log_level(&cli), Level::ERROR);
+
+        let cli = Cli::from_iter([
+            "nested_set_indexer",
+            "--log-format",
+            "json",
+            "index",
+            "t.csv",
+        ]);
+        assert!(matches!(cli.log_format, LogFormat::Json));
+    }
+
+    #[test]
+    fn test_watching() {
+        let options = IndexOptions::from_iter(["index"]);
+        assert!(watching(&options, &[], || Ok(())).is_ok());
+
+        // stdin can not be watched, so that it fails before running
+        let options = IndexOptions::from_iter(["index", "--watch"]);
+        let result = watching(&options, &[], || panic!("run without an input file"));
+        assert!(matches!(result, Err(error::Error::UsageError(_))));
+    }
+
+    #[test]
+    fn test_completions() {
+        let mut output = Vec::new();
+        completions(
+            &CompletionsOptions::from_iter(["completions", "bash"]),
+            &mut output,
+        );
+        let script = String::from_utf8(output).unwrap();
+        assert!(script.contains("_nested_set_indexer()"));
+        assert!(script.contains("--timings"));
+        assert!(script.contains("verify"));
+    }
+}
@@ -0,0 +1,137 @@
+//! Merging of hierarchies split across several inputs.
+
+use crate::data::Node;
+use crate::error;
+use std::collections::HashMap;
+use strum::{EnumString, EnumVariantNames};
+
+/// What to do with a node which appears in more than one input with a different parent or label.
+#[derive(Debug, Clone, Default, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Fail with [`error::Error::MergeConflictError`].
+    #[default]
+    Error,
+    /// Keep the node of the first input.
+    First,
+    /// Keep the node of the last input.
+    Last,
+    /// Keep the parents of all the inputs, making a directed acyclic graph, with the first label.
+    Keep,
+}
+
+/// Merges the nodes of several inputs into one list of nodes.
+///
+/// Nodes repeated with the same parents and label, such as a root shared by all the inputs, are
+/// kept once. Conflicting nodes are resolved by `policy`.
+pub fn merge(inputs: Vec<Vec<Node>>, policy: &ConflictPolicy) -> error::Result<Vec<Node>> {
+    let mut merged: Vec<Option<Node>> = Vec::new();
+    let mut rows: HashMap<String, Vec<usize>> = HashMap::new();
+
+    for nodes in inputs {
+        let mut groups: Vec<(String, Vec<Node>)> = Vec::new();
+        let mut positions = HashMap::new();
+        for node in nodes {
+            let i = *positions.entry(node.id().to_owned()).or_insert_with(|| {
+                groups.push((node.id().to_owned(), Vec::new()));
+                groups.len() - 1
+            });
+            groups[i].1.push(node);
+        }
+
+        for (id, nodes) in groups {
+            let existing = match rows.get(&id) {
+                Some(x) => x.to_owned(),
+                None => {
+                    let indices = (merged.len()..merged.len() + nodes.len()).collect();
+                    merged.extend(nodes.into_iter().map(Some));
+                    rows.insert(id, indices);
+                    continue;
+                }
+            };
+
+            let key = |x: &Node| (x.parent().map(|x| x.to_owned()), x.label().to_owned());
+            let mut before: Vec<_> = existing
+                .iter()
+                .filter_map(|i| merged[*i].as_ref())
+                .map(key)
+                .collect();
+            let mut after: Vec<_> = nodes.iter().map(key).collect();
+            before.sort();
+            after.sort();
+            if before == after {
+                continue;
+            }
+
+            match policy {
+                ConflictPolicy::Error => Err(error::Error::MergeConflictError(id))?,
+                ConflictPolicy::First => {}
+                ConflictPolicy::Last => {
+                    for i in &existing {
+                        merged[*i] = None;
+                    }
+                    let indices = (merged.len()..merged.len() + nodes.len()).collect();
+                    merged.extend(nodes.into_iter().map(Some));
+                    rows.insert(id, indices);
+                }
+                ConflictPolicy::Keep => {
+                    let label = before[0].1.to_owned();
+                    let parents: Vec<_> = before.into_iter().map(|x| x.0).collect();
+                    for node in nodes {
+                        if !parents.contains(&node.parent().map(|x| x.to_owned())) {
+                            let mut node = node;
+                            node.set_label(&label);
+                            rows.entry(id.to_owned()).or_default().push(merged.len());
+                            merged.push(Some(node));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(merged.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, ConflictPolicy};
+    use crate::data::Node;
+
+    #[test]
+    fn test_merge() {
+        let first = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+        ];
+        let second = vec![
+            Node::new("1", "root"),
+            Node::new("4", "c").with_parent("1"),
+            Node::new("3", "B").with_parent("4"),
+        ];
+
+        let merge = |policy| {
+            merge(vec![first.to_owned(), second.to_owned()], &policy).map(|nodes| {
+                nodes
+                    .iter()
+                    .map(|x| format!("{}:{}:{}", x.id(), x.parent().unwrap_or(""), x.label()))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert!(merge(ConflictPolicy::Error).is_err());
+        assert_eq!(
+            merge(ConflictPolicy::First).unwrap(),
+            vec!["1::root", "2:1:a", "3:2:b", "4:1:c"]
+        );
+        assert_eq!(
+            merge(ConflictPolicy::Last).unwrap(),
+            vec!["1::root", "2:1:a", "4:1:c", "3:4:B"]
+        );
+        assert_eq!(
+            merge(ConflictPolicy::Keep).unwrap(),
+            vec!["1::root", "2:1:a", "3:2:b", "4:1:c", "3:4:b"]
+        );
+    }
+}
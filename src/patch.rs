@@ -0,0 +1,115 @@
+//! Change files of operations applied to an indexed hierarchy.
+
+use crate::data::{Graph, Node};
+use crate::error;
+use serde::Deserialize;
+use serde_json::Value;
+use std::io;
+use std::io::{BufReader, Read};
+
+/// An edit of an indexed hierarchy, given as a JSON object such as
+/// `{"op": "move", "id": "3", "parent": "1"}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    /// Add a node as the last child of `parent`.
+    Add {
+        id: String,
+        parent: String,
+        #[serde(default)]
+        label: String,
+    },
+    /// Move the node with its descendants to the last child of `parent`.
+    Move { id: String, parent: String },
+    /// Delete the node with its descendants, or only the node with `promote_children`.
+    Delete {
+        id: String,
+        #[serde(default)]
+        promote_children: bool,
+    },
+    /// Set the label of the node.
+    Rename { id: String, label: String },
+}
+
+/// Reads operations from a JSON array or from JSON Lines.
+pub fn read<R: io::Read>(input: R) -> error::Result<Vec<Operation>> {
+    let mut buffer = String::new();
+    BufReader::new(input).read_to_string(&mut buffer)?;
+
+    if buffer.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(&buffer)?);
+    }
+
+    serde_json::Deserializer::from_str(&buffer)
+        .into_iter::<Value>()
+        .map(|x| Ok(serde_json::from_value(x?)?))
+        .collect()
+}
+
+/// Applies `operations` in order to an indexed graph.
+pub fn apply(graph: &mut Graph, operations: &[Operation]) -> error::Result<()> {
+    for operation in operations {
+        match operation {
+            Operation::Add { id, parent, label } => {
+                graph.insert(Node::new(id, label).with_parent(parent))?;
+            }
+            Operation::Move { id, parent } => {
+                graph.move_subtree(id, parent)?;
+            }
+            Operation::Delete {
+                id,
+                promote_children,
+            } => {
+                graph.delete_subtree(id, *promote_children)?;
+            }
+            Operation::Rename { id, label } => {
+                graph.rename(id, label)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, read};
+    use crate::data::{Graph, Node};
+
+    #[test]
+    fn test_patch() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("2"),
+        ];
+        let mut graph = Graph::new(nodes).unwrap();
+        graph.build_index().unwrap();
+
+        let operations = read(
+            r#"{"op": "add", "id": "4", "parent": "1", "label": "c"}
+               {"op": "move", "id": "3", "parent": "4"}
+               {"op": "delete", "id": "2"}
+               {"op": "rename", "id": "3", "label": "B"}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        apply(&mut graph, &operations).unwrap();
+
+        let nodes: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|x| (x.id(), x.label(), x.lft(), x.rgt()))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("1", "root", Some(1), Some(6)),
+                ("3", "B", Some(3), Some(4)),
+                ("4", "c", Some(2), Some(5)),
+            ]
+        );
+
+        assert!(read(r#"[{"op": "unknown"}]"#.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! Spinners and timings on stderr for the phases of long runs, driven by the spans of the
+//! library.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// Returns the message shown during the span `name`, or `None` if it is not a phase.
+fn message(name: &str) -> Option<&'static str> {
+    match name {
+        "parse" => Some("reading"),
+        "complement_leaf" => Some("complementing leaf nodes"),
+        "dag_to_tree" => Some("converting directed acyclic graph to tree"),
+        "build_index" | "rebuild_index" => Some("indexing"),
+        "build_paths" => Some("building paths"),
+        "build_label_paths" => Some("building label paths"),
+        "build_hashes" => Some("hashing subtrees"),
+        "write" => Some("writing"),
+        _ => None,
+    }
+}
+
+/// Values of the fields of the phases counting their progress: the `rows` to write, the
+/// `total_bytes` to read, and the `bytes` read or written so far.
+#[derive(Default)]
+struct Counts {
+    rows: Option<u64>,
+    total_bytes: Option<u64>,
+    bytes: Option<u64>,
+}
+
+impl Visit for Counts {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "rows" => self.rows = Some(value),
+            "total_bytes" => self.total_bytes = Some(value),
+            "bytes" => self.bytes = Some(value),
+            _ => {}
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if let Ok(value) = u64::try_from(value) {
+            self.record_u64(field, value);
+        }
+    }
+
+    fn record_debug(&mut self, _: &Field, _: &dyn fmt::Debug) {}
+}
+
+/// Whether `metadata` is of a span, or of an event reporting the progress of a phase.
+pub fn is_progress(metadata: &tracing::Metadata<'_>) -> bool {
+    metadata.is_span() || metadata.fields().field("bytes").is_some()
+}
+
+/// Layer showing a spinner with the elapsed time while a phase is running, with the rows to
+/// write and the bytes read or written so far.
+#[derive(Clone)]
+pub struct ProgressLayer {
+    progress: MultiProgress,
+    bars: Arc<Mutex<HashMap<Id, ProgressBar>>>,
+}
+
+impl ProgressLayer {
+    pub fn new(progress: MultiProgress) -> Self {
+        ProgressLayer {
+            progress,
+            bars: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Updates the bar of the span `id` by `counts`, showing the bytes once they are known.
+    fn update(&self, id: &Id, counts: Counts) {
+        let bars = self.bars.lock().unwrap();
+        let bar = match bars.get(id) {
+            Some(x) => x,
+            None => return,
+        };
+
+        if let Some(total_bytes) = counts.total_bytes {
+            bar.set_length(total_bytes);
+        }
+        if let Some(bytes) = counts.bytes {
+            bar.set_position(bytes);
+        }
+        if counts.total_bytes.is_some() || counts.bytes.is_some() {
+            let template = match bar.length() {
+                Some(_) => "{spinner} {msg} {bytes}/{total_bytes} ({elapsed})",
+                None => "{spinner} {msg} {bytes} ({elapsed})",
+            };
+            bar.set_style(ProgressStyle::with_template(template).expect("template is valid"));
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ProgressLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _: Context<'_, S>) {
+        let message = match message(attrs.metadata().name()) {
+            Some(x) => x,
+            None => return,
+        };
+        let mut counts = Counts::default();
+        attrs.record(&mut counts);
+
+        let bar = self.progress.add(ProgressBar::new_spinner());
+        bar.set_style(
+            ProgressStyle::with_template("{spinner} {msg} ({elapsed})").expect("template is valid"),
+        );
+        match counts.rows {
+            Some(rows) => bar.set_message(format!("{} {} rows", message, rows)),
+            None => bar.set_message(message),
+        }
+        bar.enable_steady_tick(Duration::from_millis(100));
+        self.bars.lock().unwrap().insert(id.to_owned(), bar);
+        self.update(id, counts);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, _: Context<'_, S>) {
+        let mut counts = Counts::default();
+        values.record(&mut counts);
+        self.update(id, counts);
+    }
+
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        if let Some(id) = event.parent() {
+            let mut counts = Counts::default();
+            event.record(&mut counts);
+            self.update(id, counts);
+        }
+    }
+
+    fn on_close(&self, id: Id, _: Context<'_, S>) {
+        if let Some(bar) = self.bars.lock().unwrap().remove(&id) {
+            bar.finish_and_clear();
+            self.progress.remove(&bar);
+        }
+    }
+}
+
+/// Wall-clock time and memory of a finished phase, named by its span.
+#[derive(Debug, Serialize)]
+struct Timing {
+    phase: &'static str,
+    seconds: f64,
+    peak_memory_bytes: Option<u64>,
+}
+
+/// Returns the peak resident memory of the process so far, which is only known on Linux.
+fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|x| x.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Layer recording the time and the peak memory of every phase for --timings.
+#[derive(Clone, Default)]
+pub struct TimingsLayer {
+    started: Arc<Mutex<HashMap<Id, (&'static str, Instant)>>>,
+    timings: Arc<Mutex<Vec<Timing>>>,
+}
+
+impl TimingsLayer {
+    /// Writes the finished phases to stderr as a JSON object.
+    pub fn report(&self) {
+        eprintln!("{}", self.to_json());
+    }
+
+    /// Returns the finished phases in their order with the peak memory so far.
+    fn to_json(&self) -> serde_json::Value {
+        let timings = self.timings.lock().unwrap();
+        json!({
+            "phases": *timings,
+            "peak_memory_bytes": peak_memory(),
+        })
+    }
+}
+
+impl<S: Subscriber> Layer<S> for TimingsLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _: Context<'_, S>) {
+        let name = attrs.metadata().name();
+        if message(name).is_some() {
+            let started = (name, Instant::now());
+            self.started.lock().unwrap().insert(id.to_owned(), started);
+        }
+    }
+
+    fn on_close(&self, id: Id, _: Context<'_, S>) {
+        if let Some((phase, started)) = self.started.lock().unwrap().remove(&id) {
+            self.timings.lock().unwrap().push(Timing {
+                phase,
+                seconds: started.elapsed().as_secs_f64(),
+                peak_memory_bytes: peak_memory(),
+            });
+        }
+    }
+}
+
+/// Writer of the log messages, hiding the spinners while writing.
+#[derive(Clone)]
+pub struct ProgressWriter(pub MultiProgress);
+
+impl Write for ProgressWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.suspend(|| io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProgressLayer, TimingsLayer};
+    use indicatif::{MultiProgress, ProgressDrawTarget};
+    use tracing::field;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_progress_layer() {
+        let layer =
+            ProgressLayer::new(MultiProgress::with_draw_target(ProgressDrawTarget::hidden()));
+        let subscriber = tracing_subscriber::registry().with(layer.to_owned());
+        let bar = |layer: &ProgressLayer| {
+            let bars = layer.bars.lock().unwrap();
+            bars.values()
+                .next()
+                .map(|x| (x.message(), x.position(), x.length()))
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::debug_span!("parse", total_bytes = field::Empty);
+            assert_eq!(bar(&layer), Some(("reading".to_owned(), 0, None)));
+            span.record("total_bytes", 100);
+            tracing::trace!(parent: &span, bytes = 40);
+            assert_eq!(bar(&layer), Some(("reading".to_owned(), 40, Some(100))));
+            drop(span);
+            assert_eq!(bar(&layer), None);
+
+            let span = tracing::debug_span!("write", rows = 3);
+            tracing::trace!(parent: &span, bytes = 20);
+            assert_eq!(bar(&layer), Some(("writing 3 rows".to_owned(), 20, None)));
+            drop(span);
+
+            // spans other than the phases are not shown
+            let _span = tracing::debug_span!("query");
+            assert_eq!(bar(&layer), None);
+        });
+    }
+
+    #[test]
+    fn test_timings_layer() {
+        let layer = TimingsLayer::default();
+        let subscriber = tracing_subscriber::registry().with(layer.to_owned());
+        tracing::subscriber::with_default(subscriber, || {
+            let _parse = tracing::debug_span!("parse").entered();
+            // only the closed phases are reported, in the order they are closed
+            tracing::debug_span!("build_paths").in_scope(|| {});
+            tracing::debug_span!("query").in_scope(|| {});
+            tracing::debug_span!("write").in_scope(|| {});
+        });
+
+        let report = layer.to_json();
+        let phases = report["phases"].as_array().unwrap();
+        let names: Vec<_> = phases
+            .iter()
+            .map(|x| x["phase"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["build_paths", "write", "parse"]);
+        for phase in phases {
+            assert_eq!(phase.as_object().unwrap().len(), 3);
+            assert!(phase["seconds"].as_f64().unwrap() >= 0.0);
+            let peak_memory = &phase["peak_memory_bytes"];
+            assert!(
+                peak_memory.is_u64() || cfg!(not(target_os = "linux")) && peak_memory.is_null()
+            );
+        }
+        assert_eq!(report.as_object().unwrap().len(), 2);
+        assert!(report["peak_memory_bytes"].is_u64() || cfg!(not(target_os = "linux")));
+    }
+}
@@ -0,0 +1,650 @@
+//! Resolution of the problems of dirty inputs which would otherwise fail the indexing.
+
+use crate::data::Node;
+use crate::error;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use strum::{EnumString, EnumVariantNames};
+use unicode_normalization::UnicodeNormalization;
+
+/// What to do with a node whose parent is not in the input.
+#[derive(Debug, Clone, Default, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum MissingParentPolicy {
+    /// Fail with [`error::Error::ParentNodeNotFoundError`].
+    #[default]
+    Error,
+    /// Drop the node with its descendants.
+    Skip,
+    /// Attach the node to the root, or make it a root if there is not exactly one root.
+    AttachToRoot,
+    /// Create the parent labelled with its id under the root, or as a root if there is not
+    /// exactly one root. Every node is given a `synthetic` column telling the created ones.
+    CreateStub,
+}
+
+/// What to do with a node which appears more than once with the same parent.
+#[derive(Debug, Clone, Default, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum DuplicatePolicy {
+    /// Fail with [`error::Error::DuplicateNodeError`].
+    Error,
+    /// Keep the first row.
+    #[default]
+    Dedupe,
+    /// Keep all the rows, duplicating the subtree of the node under the parent.
+    Keep,
+}
+
+/// Resolves the rows repeating the id and the parent of an earlier row by `policy`, returning the
+/// nodes with the number of the rows dropped.
+pub fn duplicates(nodes: Vec<Node>, policy: &DuplicatePolicy) -> error::Result<(Vec<Node>, usize)> {
+    if *policy == DuplicatePolicy::Keep {
+        return Ok((nodes, 0));
+    }
+
+    let mut pairs = HashSet::new();
+    let mut keep = Vec::with_capacity(nodes.len());
+    for node in &nodes {
+        let first = pairs.insert((node.id(), node.parent()));
+        if !first && *policy == DuplicatePolicy::Error {
+            Err(error::Error::DuplicateNodeError(node.id().to_owned()))?
+        }
+        keep.push(first);
+    }
+    let dropped = keep.iter().filter(|x| !**x).count();
+
+    let nodes = nodes
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(node, keep)| keep.then_some(node))
+        .collect();
+    Ok((nodes, dropped))
+}
+
+/// A normalization of the ids and the parents, so that a parent differing from the id of the node
+/// only by it still refers to the node.
+#[derive(Debug, Clone, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum IdNormalization {
+    /// Remove the leading and trailing whitespace.
+    Trim,
+    /// Convert to lowercase.
+    Lowercase,
+    /// Apply the Unicode normalization form KC, such as `ＡＢ` to `AB`.
+    Nfkc,
+}
+
+/// Returns `id` normalized by `normalizations` in order.
+pub fn normalize_id(id: &str, normalizations: &[IdNormalization]) -> String {
+    let mut id = id.to_owned();
+    for normalization in normalizations {
+        id = match normalization {
+            IdNormalization::Trim => id.trim().to_owned(),
+            IdNormalization::Lowercase => id.to_lowercase(),
+            IdNormalization::Nfkc => id.nfkc().collect(),
+        };
+    }
+    id
+}
+
+/// Normalizes the ids and the parents of `nodes` by `normalizations` in order, returning the nodes
+/// with the number of the ids and parents changed. The input ids are kept as
+/// [`Node::source_id`].
+pub fn normalize_ids(
+    mut nodes: Vec<Node>,
+    normalizations: &[IdNormalization],
+) -> (Vec<Node>, usize) {
+    if normalizations.is_empty() {
+        return (nodes, 0);
+    }
+
+    let mut changed = 0;
+    for node in &mut nodes {
+        let id = normalize_id(node.id(), normalizations);
+        if id != node.id() {
+            node.set_id(id);
+            changed += 1;
+        }
+
+        // a parent emptied by the normalization is missing as an empty field is
+        let parent = node
+            .parent()
+            .map(|x| normalize_id(x, normalizations))
+            .filter(|x| !x.is_empty());
+        if parent.as_deref() != node.parent() {
+            node.set_parent(parent);
+            changed += 1;
+        }
+    }
+
+    (nodes, changed)
+}
+
+/// How to fix a node whose parent is itself.
+#[derive(Debug, Clone, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum SelfParentFix {
+    /// Remove the parent, making the node a root.
+    Null,
+    /// Drop the row.
+    Drop,
+}
+
+/// Fixes the nodes whose parent is the node itself by `fix`, or fails with
+/// [`error::Error::SelfParentError`] listing all of them if `fix` is `None`. Returns the nodes with
+/// the ids of the fixed ones.
+pub fn self_parents(
+    nodes: Vec<Node>,
+    fix: Option<&SelfParentFix>,
+) -> error::Result<(Vec<Node>, Vec<String>)> {
+    let is_self_parent = |x: &Node| x.parent() == Some(x.id());
+    let ids: Vec<String> = nodes
+        .iter()
+        .filter(|x| is_self_parent(x))
+        .map(|x| x.id().to_owned())
+        .collect();
+    if ids.is_empty() {
+        return Ok((nodes, ids));
+    }
+
+    let nodes = match fix {
+        None => Err(error::Error::SelfParentError(ids.to_owned()))?,
+        Some(SelfParentFix::Null) => {
+            let mut nodes = nodes;
+            for node in nodes.iter_mut().filter(|x| is_self_parent(x)) {
+                node.set_parent(None);
+            }
+            nodes
+        }
+        Some(SelfParentFix::Drop) => nodes.into_iter().filter(|x| !is_self_parent(x)).collect(),
+    };
+
+    Ok((nodes, ids))
+}
+
+/// A node whose parent is not in the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingParent {
+    pub id: String,
+    pub parent: String,
+}
+
+/// Resolves the nodes whose parent is not in `nodes` by `policy`, returning the nodes with the
+/// affected ones.
+pub fn missing_parents(
+    nodes: Vec<Node>,
+    policy: &MissingParentPolicy,
+) -> error::Result<(Vec<Node>, Vec<MissingParent>)> {
+    let ids: HashSet<&str> = nodes.iter().map(|x| x.id()).collect();
+    let missing: Vec<usize> = (0..nodes.len())
+        .filter(|i| nodes[*i].parent().is_some_and(|x| !ids.contains(x)))
+        .collect();
+    let affected: Vec<MissingParent> = missing
+        .iter()
+        .map(|i| MissingParent {
+            id: nodes[*i].id().to_owned(),
+            parent: nodes[*i].parent().unwrap_or_default().to_owned(),
+        })
+        .collect();
+    if affected.is_empty() && *policy != MissingParentPolicy::CreateStub {
+        return Ok((nodes, affected));
+    }
+
+    let root = || {
+        let roots: Vec<&Node> = nodes.iter().filter(|x| x.parent().is_none()).collect();
+        match roots.as_slice() {
+            [root] => Some(root.id().to_owned()),
+            _ => None,
+        }
+    };
+
+    let nodes = match policy {
+        MissingParentPolicy::Error => Err(error::Error::ParentNodeNotFoundError {
+            id: affected[0].id.to_owned(),
+            parent: affected[0].parent.to_owned(),
+            row: Some(missing[0] + 1),
+        })?,
+        MissingParentPolicy::Skip => drop_rows(nodes, missing),
+        MissingParentPolicy::AttachToRoot => {
+            let root = root();
+            let mut nodes = nodes;
+            for i in missing {
+                nodes[i].set_parent(root.to_owned());
+            }
+            nodes
+        }
+        MissingParentPolicy::CreateStub => {
+            let root = root();
+            let mut nodes = nodes;
+            for node in nodes.iter_mut() {
+                node.set_synthetic(false);
+            }
+
+            let mut created = HashSet::new();
+            for x in &affected {
+                if created.insert(x.parent.as_str()) {
+                    let mut stub = Node::new(&x.parent, &x.parent);
+                    stub.set_parent(root.to_owned());
+                    stub.set_synthetic(true);
+                    nodes.push(stub);
+                }
+            }
+            nodes
+        }
+    };
+
+    Ok((nodes, affected))
+}
+
+/// Drops the rows `rows` with the rows of the descendants left without any row of their parent.
+fn drop_rows(nodes: Vec<Node>, rows: Vec<usize>) -> Vec<Node> {
+    let mut remaining = HashMap::<&str, usize>::new();
+    let mut children = HashMap::<&str, Vec<usize>>::new();
+    for (i, node) in nodes.iter().enumerate() {
+        *remaining.entry(node.id()).or_default() += 1;
+        if let Some(parent) = node.parent() {
+            children.entry(parent).or_default().push(i);
+        }
+    }
+
+    let mut keep = vec![true; nodes.len()];
+    let mut stack = rows;
+    while let Some(i) = stack.pop() {
+        if !keep[i] {
+            continue;
+        }
+        keep[i] = false;
+
+        let id = nodes[i].id();
+        let count = remaining.get_mut(id).expect("every id is counted");
+        *count -= 1;
+        if *count == 0 {
+            stack.extend(children.get(id).into_iter().flatten());
+        }
+    }
+
+    nodes
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(node, keep)| keep.then_some(node))
+        .collect()
+}
+
+/// Marks the nodes which are not the parent of any node as leaves, unless the leaf flag is given
+/// in the input. Returns the number of the nodes marked.
+pub fn infer_leaf(nodes: &mut [Node]) -> usize {
+    let parents: HashSet<String> = nodes
+        .iter()
+        .filter_map(|x| x.parent())
+        .map(|x| x.to_owned())
+        .collect();
+
+    let mut count = 0;
+    for node in nodes.iter_mut() {
+        if node.leaf().is_none() && !parents.contains(node.id()) {
+            node.set_leaf(true);
+            count += 1;
+        }
+    }
+    count
+}
+
+/// What to do with a node marked as a leaf which is the parent of other nodes.
+#[derive(Debug, Clone, Default, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum LeafParentPolicy {
+    /// Fail with [`error::Error::LeafParentError`] listing all such nodes.
+    #[default]
+    Error,
+    /// Unmark the node as a leaf.
+    Promote,
+}
+
+/// Resolves the nodes marked as a leaf which are the parent of other nodes by `policy`, returning
+/// the ids of the promoted ones.
+pub fn leaf_parents(nodes: &mut [Node], policy: &LeafParentPolicy) -> error::Result<Vec<String>> {
+    let parents: HashSet<&str> = nodes.iter().filter_map(|x| x.parent()).collect();
+    let mut seen = HashSet::new();
+    let ids: Vec<String> = nodes
+        .iter()
+        .filter(|x| x.is_leaf() && parents.contains(x.id()) && seen.insert(x.id()))
+        .map(|x| x.id().to_owned())
+        .collect();
+    if ids.is_empty() {
+        return Ok(ids);
+    }
+
+    match policy {
+        LeafParentPolicy::Error => return Err(error::Error::LeafParentError(ids)),
+        LeafParentPolicy::Promote => {
+            for node in nodes.iter_mut() {
+                if node.is_leaf() && ids.iter().any(|x| x == node.id()) {
+                    node.set_leaf(false);
+                }
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+/// What to do with the nodes which are not reachable from a root, such as the nodes on a cycle of
+/// parent links and their descendants.
+#[derive(Debug, Clone, Default, PartialEq, EnumString, EnumVariantNames)]
+#[strum(serialize_all = "kebab-case")]
+pub enum OrphanPolicy {
+    /// Fail with [`error::Error::OrphanedNodesError`] listing the orphaned components.
+    #[default]
+    Error,
+    /// Drop the orphaned nodes.
+    Drop,
+    /// Attach each orphaned component to a node created under the root.
+    Attach,
+}
+
+/// Nodes which are not reachable from a root, connected through their parent links.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrphanedComponent {
+    /// Id of the node whose parent link is cut to attach the component.
+    pub id: String,
+    /// Position of the row of the node among the nodes, starting from 1.
+    pub row: usize,
+    /// Number of the rows of the component.
+    pub size: usize,
+}
+
+impl fmt::Display for OrphanedComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at row {} with {} row(s)",
+            self.id, self.row, self.size
+        )
+    }
+}
+
+/// Returns the orphaned components of `nodes` with the rows of each one.
+fn orphaned_components(nodes: &[Node]) -> Vec<(OrphanedComponent, Vec<usize>)> {
+    let mut rows = HashMap::<&str, Vec<usize>>::new();
+    let mut children = HashMap::<&str, Vec<usize>>::new();
+    for (i, node) in nodes.iter().enumerate() {
+        rows.entry(node.id()).or_default().push(i);
+        if let Some(parent) = node.parent() {
+            children.entry(parent).or_default().push(i);
+        }
+    }
+
+    let mut reached = vec![false; nodes.len()];
+    let mut expanded = HashSet::new();
+    let mut reach = |starts: Vec<usize>, reached: &mut Vec<bool>| {
+        let mut found = Vec::new();
+        let mut stack = starts;
+        while let Some(i) = stack.pop() {
+            if reached[i] {
+                continue;
+            }
+            reached[i] = true;
+            found.push(i);
+            if expanded.insert(nodes[i].id()) {
+                stack.extend(children.get(nodes[i].id()).into_iter().flatten());
+            }
+        }
+        found
+    };
+
+    let roots = (0..nodes.len())
+        .filter(|i| nodes[*i].parent().is_none())
+        .collect();
+    reach(roots, &mut reached);
+
+    // follow the parent links of an orphan until a node repeats, which is on a cycle, and cut the
+    // link of that node so that the whole component hangs off it
+    let mut components = Vec::new();
+    for start in 0..nodes.len() {
+        if reached[start] {
+            continue;
+        }
+
+        let mut visited = HashSet::new();
+        let mut cut = start;
+        while visited.insert(nodes[cut].id()) {
+            match nodes[cut].parent().and_then(|x| rows.get(x)) {
+                Some(parents) => cut = parents[0],
+                None => break,
+            }
+        }
+
+        let mut found = reach(vec![cut], &mut reached);
+        found.sort_unstable();
+        let component = OrphanedComponent {
+            id: nodes[cut].id().to_owned(),
+            row: cut + 1,
+            size: found.len(),
+        };
+        components.push((component, found));
+    }
+
+    components
+}
+
+/// Resolves the nodes which are not reachable from a root by `policy`, returning the nodes with
+/// the orphaned components. Attached components are put under a node of `orphans`, which is
+/// created under the root, or as a root if there is not exactly one root.
+pub fn orphans(
+    nodes: Vec<Node>,
+    policy: &OrphanPolicy,
+    orphans: Node,
+) -> error::Result<(Vec<Node>, Vec<OrphanedComponent>)> {
+    let components = orphaned_components(&nodes);
+    if components.is_empty() {
+        return Ok((nodes, Vec::new()));
+    }
+
+    let nodes = match policy {
+        OrphanPolicy::Error => {
+            let components = components.into_iter().map(|(x, _)| x).collect();
+            return Err(error::Error::OrphanedNodesError(components));
+        }
+        OrphanPolicy::Drop => {
+            let rows = components.iter().flat_map(|(_, rows)| rows).copied();
+            let dropped: HashSet<usize> = rows.collect();
+            nodes
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, node)| (!dropped.contains(&i)).then_some(node))
+                .collect()
+        }
+        OrphanPolicy::Attach => {
+            if nodes.iter().any(|x| x.id() == orphans.id()) {
+                Err(error::Error::NodeConflictError(orphans.id().to_owned()))?
+            }
+
+            let roots: Vec<&Node> = nodes.iter().filter(|x| x.parent().is_none()).collect();
+            let mut orphans = orphans;
+            orphans.set_parent(match roots.as_slice() {
+                [root] => Some(root.id().to_owned()),
+                _ => None,
+            });
+
+            let mut nodes = nodes;
+            for (component, _) in &components {
+                nodes[component.row - 1].set_parent(Some(orphans.id().to_owned()));
+            }
+            nodes.push(orphans);
+            nodes
+        }
+    };
+
+    Ok((nodes, components.into_iter().map(|(x, _)| x).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{duplicates, infer_leaf, leaf_parents, missing_parents, orphans, self_parents};
+    use super::{normalize_id, normalize_ids, IdNormalization, SelfParentFix};
+    use super::{DuplicatePolicy, LeafParentPolicy, MissingParentPolicy, OrphanPolicy};
+    use crate::data::Node;
+
+    #[test]
+    fn test_duplicates() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("2", "a").with_parent("3"),
+            Node::new("3", "b").with_parent("1"),
+        ];
+
+        assert!(duplicates(nodes.to_owned(), &DuplicatePolicy::Error).is_err());
+        let (deduped, dropped) = duplicates(nodes.to_owned(), &DuplicatePolicy::Dedupe).unwrap();
+        assert_eq!((deduped.len(), dropped), (4, 1));
+        let (kept, dropped) = duplicates(nodes, &DuplicatePolicy::Keep).unwrap();
+        assert_eq!((kept.len(), dropped), (5, 0));
+    }
+
+    #[test]
+    fn test_normalize_ids() {
+        let nodes = vec![
+            Node::new("Root", "root"),
+            Node::new("a ", "a").with_parent(" ROOT"),
+            Node::new("ｂ", "b").with_parent("A"),
+            Node::new("c", "c").with_parent("  "),
+        ];
+        let all = [
+            IdNormalization::Trim,
+            IdNormalization::Lowercase,
+            IdNormalization::Nfkc,
+        ];
+
+        let (nodes, changed) = normalize_ids(nodes, &all);
+        assert_eq!(changed, 6);
+        let ids: Vec<_> = nodes.iter().map(|x| (x.id(), x.parent())).collect();
+        assert_eq!(
+            ids,
+            [
+                ("root", None),
+                ("a", Some("root")),
+                ("b", Some("a")),
+                ("c", None)
+            ]
+        );
+        assert_eq!(nodes[2].source_id(), "ｂ");
+
+        // only the given normalizations, in order
+        assert_eq!(normalize_id(" \u{3000}A", &all), "a");
+        assert_eq!(normalize_id(" \u{3000}A", &all[1..]), "  a");
+        assert_eq!(normalize_ids(vec![Node::new(" A", "a")], &[]).1, 0);
+    }
+
+    #[test]
+    fn test_self_parents() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("2"),
+            Node::new("3", "b").with_parent("2"),
+        ];
+
+        match self_parents(nodes.to_owned(), None) {
+            Err(crate::Error::SelfParentError(ids)) => assert_eq!(ids, vec!["2"]),
+            x => panic!("unexpected result: {:?}", x),
+        }
+        let (fixed, ids) = self_parents(nodes.to_owned(), Some(&SelfParentFix::Null)).unwrap();
+        assert_eq!((fixed[1].parent(), ids.len()), (None, 1));
+        let (fixed, _) = self_parents(nodes, Some(&SelfParentFix::Drop)).unwrap();
+        assert_eq!(fixed.len(), 2);
+    }
+
+    #[test]
+    fn test_missing_parents() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("9"),
+            Node::new("4", "c").with_parent("3"),
+            Node::new("4", "c").with_parent("2"),
+            Node::new("5", "d").with_parent("4"),
+        ];
+        let resolve = |policy| {
+            let (nodes, affected) = missing_parents(nodes.to_owned(), &policy).unwrap();
+            let nodes: Vec<_> = nodes
+                .iter()
+                .map(|x| format!("{}:{}", x.id(), x.parent().unwrap_or("")))
+                .collect();
+            (nodes, affected.len())
+        };
+
+        assert!(missing_parents(nodes.to_owned(), &MissingParentPolicy::Error).is_err());
+        let (nodes, affected) = resolve(MissingParentPolicy::Skip);
+        assert_eq!(nodes, vec!["1:", "2:1", "4:2", "5:4"]);
+        assert_eq!(affected, 1);
+        let (nodes, _) = resolve(MissingParentPolicy::AttachToRoot);
+        assert_eq!(nodes, vec!["1:", "2:1", "3:1", "4:3", "4:2", "5:4"]);
+        let (nodes, _) = resolve(MissingParentPolicy::CreateStub);
+        assert_eq!(nodes, vec!["1:", "2:1", "3:9", "4:3", "4:2", "5:4", "9:1"]);
+
+        let nodes = vec![Node::new("1", "root"), Node::new("2", "a").with_parent("9")];
+        let (nodes, _) = missing_parents(nodes, &MissingParentPolicy::CreateStub).unwrap();
+        let synthetic: Vec<_> = nodes.iter().map(|x| x.synthetic()).collect();
+        assert_eq!(synthetic, vec![Some(false), Some(false), Some(true)]);
+    }
+
+    #[test]
+    fn test_infer_leaf() {
+        let mut nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("1").with_leaf(false),
+            Node::new("2", "a").with_parent("3"),
+        ];
+
+        assert_eq!(infer_leaf(&mut nodes), 2);
+        let leaves: Vec<_> = nodes.iter().map(|x| x.is_leaf()).collect();
+        assert_eq!(leaves, vec![false, true, false, true]);
+    }
+
+    #[test]
+    fn test_leaf_parents() {
+        let mut nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("1").with_leaf(true),
+            Node::new("3", "b").with_parent("2"),
+        ];
+
+        match leaf_parents(&mut nodes, &LeafParentPolicy::Error) {
+            Err(crate::Error::LeafParentError(ids)) => assert_eq!(ids, vec!["2"]),
+            x => panic!("unexpected result: {:?}", x),
+        }
+        let ids = leaf_parents(&mut nodes, &LeafParentPolicy::Promote).unwrap();
+        assert_eq!(ids, vec!["2"]);
+        assert!(!nodes[1].is_leaf());
+    }
+
+    #[test]
+    fn test_orphans() {
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("2", "a").with_parent("4"),
+            Node::new("3", "b").with_parent("2"),
+            Node::new("4", "c").with_parent("3"),
+            Node::new("5", "d").with_parent("4"),
+            Node::new("6", "e").with_parent("1"),
+        ];
+        let node = || Node::new("orphans", "orphans");
+
+        match orphans(nodes.to_owned(), &OrphanPolicy::Error, node()) {
+            Err(crate::Error::OrphanedNodesError(components)) => {
+                let components: Vec<_> = components.iter().map(|x| (x.row, x.size)).collect();
+                assert_eq!(components, vec![(2, 4)]);
+            }
+            x => panic!("unexpected result: {:?}", x),
+        }
+
+        let (dropped, _) = orphans(nodes.to_owned(), &OrphanPolicy::Drop, node()).unwrap();
+        assert_eq!(dropped.len(), 2);
+
+        let (attached, _) = orphans(nodes, &OrphanPolicy::Attach, node()).unwrap();
+        let parents: Vec<_> = attached.iter().map(|x| x.parent().unwrap_or("")).collect();
+        assert_eq!(parents, vec!["", "orphans", "2", "3", "4", "1", "1"]);
+    }
+}
@@ -0,0 +1,252 @@
+//! HTTP service indexing posted nodes and answering queries about the loaded hierarchy.
+
+use crate::cli::ServeOptions;
+use nested_set_indexer::error;
+use nested_set_indexer::format::{self, Format};
+use nested_set_indexer::{Graph, Node};
+use serde_json::json;
+use std::str::FromStr;
+use tracing::{error, info};
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn error(e: error::Error) -> Reply {
+        let status = match e {
+            error::Error::NodeNotFoundError(_) => 404,
+            _ => 400,
+        };
+        Reply::message(status, &e.to_string())
+    }
+
+    fn message(status: u16, message: &str) -> Reply {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: json!({ "error": message }).to_string().into_bytes(),
+        }
+    }
+}
+
+fn content_type(format: &Format) -> &'static str {
+    match format {
+        Format::Json | Format::JsonTree => "application/json",
+        Format::Jsonl => "application/x-ndjson",
+        Format::Csv | Format::Closure => "text/csv",
+        Format::Tsv => "text/tab-separated-values",
+        Format::Html => "text/html",
+        Format::Sql | Format::Tree | Format::Newick | Format::Skos => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Decodes the `%XX` escapes and `+` of a URL component.
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s
+            .get(i + 1..i + 3)
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(x)) => {
+                decoded.push(x);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (x, _) => decoded.push(x),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Returns the format of the query parameter `name`, or `default` if it is not given.
+fn format_param(query: &str, name: &str, default: Format) -> error::Result<Format> {
+    let value = query
+        .split('&')
+        .filter_map(|x| x.split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| decode(v));
+
+    match value {
+        Some(v) => Format::from_str(&v)
+            .map_err(|_| error::Error::RuntimeError(format!("unknown format: {}", v))),
+        None => Ok(default),
+    }
+}
+
+fn write(options: &ServeOptions, to: &Format, nodes: &[Node]) -> error::Result<Reply> {
+    let mut body = Vec::new();
+    format::write_nodes(to, &mut body, nodes, &options.index.write_options())?;
+
+    Ok(Reply {
+        status: 200,
+        content_type: content_type(to),
+        body,
+    })
+}
+
+/// `POST /index?from=json&to=json` indexes the posted nodes.
+fn index(options: &ServeOptions, query: &str, body: &[u8]) -> error::Result<Reply> {
+    let from = format_param(query, "from", Format::Json)?;
+    let to = format_param(query, "to", Format::Json)?;
+
+    let parsed = format::read_nodes(&from, body, &options.index.input.read_options())?;
+    let graph = crate::build_graph(&options.index, parsed.nodes)?;
+
+    write(options, &to, &graph.nodes)
+}
+
+/// `GET /nodes/<id>` and `GET /nodes/<id>/ancestors|descendants|subtree?to=json` query the
+/// loaded hierarchy.
+fn query(options: &ServeOptions, graph: &Graph, path: &str, query: &str) -> error::Result<Reply> {
+    let (id, relation) = match path.rsplit_once('/') {
+        Some((id, x)) if matches!(x, "ancestors" | "descendants" | "subtree") => (id, Some(x)),
+        _ => (path, None),
+    };
+    let id = decode(id);
+
+    let nodes = match relation {
+        Some("ancestors") => graph.ancestors(&id)?,
+        Some("descendants") => graph.descendants(&id)?,
+        Some(_) => graph.subtree(&id)?,
+        None => {
+            let node = serde_json::to_vec(graph.find(&id)?)?;
+            return Ok(Reply {
+                status: 200,
+                content_type: "application/json",
+                body: node,
+            });
+        }
+    };
+    let nodes: Vec<Node> = nodes.into_iter().cloned().collect();
+
+    write(options, &format_param(query, "to", Format::Json)?, &nodes)
+}
+
+fn handle(
+    options: &ServeOptions,
+    graph: Option<&Graph>,
+    request: &mut tiny_http::Request,
+) -> Reply {
+    let url = request.url().to_owned();
+    let (path, query_string) = url.split_once('?').unwrap_or((&url, ""));
+
+    match (request.method(), path) {
+        (tiny_http::Method::Post, "/index") => {
+            let mut body = Vec::new();
+            if let Err(e) = request.as_reader().read_to_end(&mut body) {
+                return Reply::error(e.into());
+            }
+            index(options, query_string, &body).unwrap_or_else(Reply::error)
+        }
+        (tiny_http::Method::Get, _) if path.starts_with("/nodes/") => match graph {
+            Some(graph) => {
+                let path = &path["/nodes/".len()..];
+                query(options, graph, path, query_string).unwrap_or_else(Reply::error)
+            }
+            None => Reply::message(404, "no hierarchy is loaded"),
+        },
+        _ => Reply::message(404, "not found"),
+    }
+}
+
+/// Serves until interrupted, loading the hierarchy to query from the input if it is given.
+pub fn serve(options: &ServeOptions) -> error::Result<()> {
+    let graph = match &options.index.input.input {
+        Some(_) => Some(crate::build(&options.index)?.0),
+        None => None,
+    };
+
+    let server = tiny_http::Server::http(&options.address)
+        .map_err(|e| error::Error::RuntimeError(e.to_string()))?;
+    info!("listening on http://{}", options.address);
+
+    for mut request in server.incoming_requests() {
+        let reply = handle(options, graph.as_ref(), &mut request);
+        let header = tiny_http::Header::from_bytes("Content-Type", reply.content_type)
+            .expect("content type is a valid header");
+        let response = tiny_http::Response::from_data(reply.body)
+            .with_status_code(reply.status)
+            .with_header(header);
+        if let Err(e) = request.respond(response) {
+            error!("{}", e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, handle};
+    use crate::cli::ServeOptions;
+    use nested_set_indexer::{Graph, Node};
+    use structopt::StructOpt;
+    use tiny_http::{Method, TestRequest};
+
+    fn request(method: Method, path: &str, body: &'static str) -> tiny_http::Request {
+        let request = TestRequest::new().with_method(method).with_path(path);
+        request.with_body(body).into()
+    }
+
+    #[test]
+    fn test_handle() {
+        let options = ServeOptions::from_iter(["serve"]);
+        let nodes = vec![
+            Node::new("1", "root"),
+            Node::new("a/b", "a").with_parent("1"),
+            Node::new("3", "b").with_parent("a/b"),
+        ];
+        let graph = crate::build_graph(&options.index, nodes).unwrap();
+        let reply = |method: Method, path: &str, body: &'static str, graph: Option<&Graph>| {
+            let reply = handle(&options, graph, &mut request(method, path, body));
+            (reply.status, String::from_utf8(reply.body).unwrap())
+        };
+
+        let body = r#"[{"id": "1", "label": "root"}, {"id": "2", "label": "a", "parent": "1"}]"#;
+        let (status, output) = reply(Method::Post, "/index?to=csv", body, None);
+        assert_eq!(status, 200);
+        assert!(
+            output.lines().nth(2).unwrap().contains(",2,3,"),
+            "{}",
+            output
+        );
+
+        let (status, _) = reply(Method::Post, "/index?to=bogus", body, None);
+        assert_eq!(status, 400);
+        let (status, _) = reply(Method::Post, "/index", "[", None);
+        assert_eq!(status, 400);
+
+        let (status, output) = reply(Method::Get, "/nodes/a%2Fb", "", Some(&graph));
+        assert_eq!(status, 200);
+        assert!(output.contains(r#""lft":2"#), "{}", output);
+        let (status, output) = reply(Method::Get, "/nodes/3/ancestors?to=jsonl", "", Some(&graph));
+        assert_eq!((status, output.lines().count()), (200, 2));
+        let (status, _) = reply(Method::Get, "/nodes/3/subtree?to=bogus", "", Some(&graph));
+        assert_eq!(status, 400);
+
+        let (status, _) = reply(Method::Get, "/nodes/9", "", Some(&graph));
+        assert_eq!(status, 404);
+        let (status, _) = reply(Method::Get, "/nodes/1", "", None);
+        assert_eq!(status, 404);
+        let (status, _) = reply(Method::Get, "/unknown", "", Some(&graph));
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("a%2Fb+c"), "a/b c");
+        assert_eq!(decode("%E3%81%82"), "あ");
+        // invalid escapes are kept
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz"), "%zz");
+    }
+}